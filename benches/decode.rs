@@ -1,5 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use gift::Decoder;
+use gift::block::{Comment, Header, LogicalScreenDesc, Trailer};
+use gift::{Decoder, Encoder, Step};
+use pix::{gray::Gray8, rgb::SRgb8, Palette, Raster};
 use std::io::Cursor;
 
 const LOGO: &[u8] = include_bytes!("../res/gift_logo.gif") as &[u8];
@@ -39,5 +41,93 @@ fn decode_steps(crit: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, decode_blocks, decode_frames, decode_steps);
+/// Make a GIF animation with 100 frames
+fn animation_gif() -> Vec<u8> {
+    let mut palette = Palette::new(16);
+    for i in 0..16 {
+        palette.set_entry(SRgb8::new(i * 16, 255 - i * 16, i * 8));
+    }
+    let mut enc = Encoder::new(vec![]).into_step_enc();
+    for s in 0..100 {
+        let mut raster = Raster::<Gray8>::with_clear(256, 256);
+        for (i, p) in raster.pixels_mut().iter_mut().enumerate() {
+            let (x, y) = (i % 256, i / 256);
+            *p = Gray8::new(((x / 16 + y / 8 + s) % 16) as u8);
+        }
+        let step = Step::with_indexed(raster, palette.clone());
+        enc.encode_step(&step).unwrap();
+    }
+    enc.finish().unwrap()
+}
+
+fn decode_steps_skip(crit: &mut Criterion) {
+    let gif = animation_gif();
+    crit.bench_function("decode_steps_skip", |b| {
+        b.iter(|| {
+            let mut steps = Decoder::new(black_box(&gif[..])).into_steps();
+            black_box(steps.nth(99).unwrap().unwrap());
+        })
+    });
+}
+
+fn decode_steps_skip_steps(crit: &mut Criterion) {
+    let gif = animation_gif();
+    crit.bench_function("decode_steps_skip_steps", |b| {
+        b.iter(|| {
+            let mut steps = Decoder::new(black_box(&gif[..])).into_steps();
+            steps.skip_steps(99).unwrap();
+            black_box(steps.next().unwrap().unwrap());
+        })
+    });
+}
+
+/// Make a GIF with many comment sub-blocks
+fn comment_gif() -> Vec<u8> {
+    let mut comment = Comment::default();
+    for _ in 0..1000 {
+        comment.add_comment(b"The quick brown fox jumps over the lazy dog");
+    }
+    let mut enc = Encoder::new(vec![]).into_block_enc();
+    enc.encode(Header::default()).unwrap();
+    enc.encode(LogicalScreenDesc::default()).unwrap();
+    enc.encode(comment).unwrap();
+    enc.encode(Trailer::default()).unwrap();
+    enc.into_inner()
+}
+
+fn decode_comments(crit: &mut Criterion) {
+    let gif = comment_gif();
+    crit.bench_function("decode_comments", |b| {
+        b.iter(|| {
+            let decoder =
+                Decoder::new(Cursor::new(black_box(&gif[..]))).into_blocks();
+            for block in decoder {
+                black_box(block.unwrap());
+            }
+        })
+    });
+}
+
+fn decode_block_refs(crit: &mut Criterion) {
+    let gif = comment_gif();
+    crit.bench_function("decode_block_refs", |b| {
+        b.iter(|| {
+            let decoder = Decoder::new(black_box(&gif[..])).into_block_refs();
+            for block in decoder {
+                black_box(block.unwrap());
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    decode_blocks,
+    decode_frames,
+    decode_steps,
+    decode_steps_skip,
+    decode_steps_skip_steps,
+    decode_comments,
+    decode_block_refs
+);
 criterion_main!(benches);
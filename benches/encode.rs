@@ -1,5 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use gift::{block::Block, Decoder, Encoder};
+use gift::{block::Block, encode::Compression, Decoder, Encoder, Step};
+use pix::{gray::Gray8, rgb::SRgb8, Palette, Raster};
 use std::io::Cursor;
 
 const LOGO: &[u8] = include_bytes!("../res/gift_logo.gif") as &[u8];
@@ -15,11 +16,58 @@ fn encode_blocks(crit: &mut Criterion) {
                 Encoder::new(Cursor::new(black_box(Vec::with_capacity(32768))))
                     .into_block_enc();
             for block in &blocks {
-                encoder.encode(black_box(block.clone())).unwrap();
+                encoder.encode_ref(black_box(block)).unwrap();
             }
         })
     });
 }
 
-criterion_group!(benches, encode_blocks);
+/// Make indexed steps for benchmarks
+fn make_steps() -> Vec<Step> {
+    let mut palette = Palette::new(16);
+    for i in 0..16 {
+        palette.set_entry(SRgb8::new(i * 16, 255 - i * 16, i * 8));
+    }
+    (0..4)
+        .map(|s| {
+            let mut raster = Raster::<Gray8>::with_clear(256, 256);
+            for (i, p) in raster.pixels_mut().iter_mut().enumerate() {
+                let (x, y) = (i % 256, i / 256);
+                *p = Gray8::new(((x / 16 + y / 8 + s) % 16) as u8);
+            }
+            Step::with_indexed(raster, palette.clone())
+        })
+        .collect()
+}
+
+fn encode_steps(crit: &mut Criterion) {
+    let steps = make_steps();
+    crit.bench_function("encode_steps", |b| {
+        b.iter(|| {
+            let mut encoder =
+                Encoder::new(Cursor::new(black_box(Vec::with_capacity(32768))))
+                    .into_step_enc();
+            for step in &steps {
+                encoder.encode_step(black_box(step)).unwrap();
+            }
+        })
+    });
+}
+
+fn encode_steps_fast(crit: &mut Criterion) {
+    let steps = make_steps();
+    crit.bench_function("encode_steps_fast", |b| {
+        b.iter(|| {
+            let mut encoder =
+                Encoder::new(Cursor::new(black_box(Vec::with_capacity(32768))))
+                    .into_step_enc()
+                    .with_compression(Compression::Fast);
+            for step in &steps {
+                encoder.encode_step(black_box(step)).unwrap();
+            }
+        })
+    });
+}
+
+criterion_group!(benches, encode_blocks, encode_steps, encode_steps_fast);
 criterion_main!(benches);
@@ -0,0 +1,290 @@
+// analysis.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! Analysis of animations
+use crate::block::GlobalColorTable;
+use crate::private::{Step, StepRaster};
+use pix::{
+    el::Pixel,
+    rgb::{Rgb, SRgba8},
+};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+/// Maximum number of distinct colors counted
+const MAX_COLORS: usize = 4096;
+
+/// Usage of one color in an animation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorStats {
+    /// Color (all fully transparent pixels are counted as the default color)
+    pub color: SRgba8,
+    /// Number of pixels with the color, in all steps
+    pub pixels: u64,
+    /// Number of steps containing the color
+    pub frames: usize,
+}
+
+/// Color usage statistics for the steps of an animation.
+///
+/// Build with [palette_stats].
+///
+/// [palette_stats]: fn.palette_stats.html
+#[derive(Clone, Debug, Default)]
+pub struct PaletteStats {
+    /// Colors in order of first appearance
+    colors: Vec<ColorStats>,
+    /// Index of each color (by key)
+    index: HashMap<u32, usize>,
+    /// Last step containing each color
+    last_step: Vec<usize>,
+    /// Number of steps
+    steps: usize,
+    /// Were colors dropped after reaching the maximum?
+    truncated: bool,
+}
+
+/// Get a hash key for an RGBA color
+fn color_key(rgba: [u8; 4]) -> u32 {
+    u32::from_be_bytes(rgba)
+}
+
+/// Get statistics of color usage for all steps of an animation.
+///
+/// Every pixel of each step raster is counted.  Indexed steps are counted by
+/// color index, then mapped through their palettes, so steps with different
+/// palettes are combined by color.  For true color steps, up to 4096
+/// distinct colors are counted; any others are dropped (see
+/// [is_truncated]).
+///
+/// ## Example
+/// ```
+/// # fn stats(gif: &[u8]) -> gift::Result<()> {
+/// use gift::{analysis::palette_stats, Decoder};
+///
+/// let steps = Decoder::new(gif).into_steps().collect::<Result<Vec<_>, _>>()?;
+/// let stats = palette_stats(&steps);
+/// for clr in stats.colors() {
+///     println!("{:?}: {} pixels in {} frames", clr.color, clr.pixels, clr.frames);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [is_truncated]: struct.PaletteStats.html#method.is_truncated
+pub fn palette_stats<I, S>(steps: I) -> PaletteStats
+where
+    I: IntoIterator<Item = S>,
+    S: Borrow<Step>,
+{
+    let mut stats = PaletteStats::default();
+    for step in steps {
+        stats.add_step(step.borrow());
+    }
+    stats
+}
+
+impl PaletteStats {
+    /// Add colors from one step
+    fn add_step(&mut self, step: &Step) {
+        let num = self.steps;
+        self.steps += 1;
+        match &step.raster {
+            StepRaster::Indexed(raster, palette) => {
+                let mut counts = [0; 256];
+                for idx in raster.as_u8_slice() {
+                    counts[usize::from(*idx)] += 1;
+                }
+                let transparent = step.transparent_color();
+                for (idx, count) in counts.into_iter().enumerate() {
+                    if count == 0 {
+                        continue;
+                    }
+                    let rgba = match palette.entry(idx) {
+                        Some(clr) if Some(idx as u8) != transparent => [
+                            u8::from(Rgb::red(clr)),
+                            u8::from(Rgb::green(clr)),
+                            u8::from(Rgb::blue(clr)),
+                            0xFF,
+                        ],
+                        _ => [0; 4],
+                    };
+                    self.add_color(rgba, count, num);
+                }
+            }
+            StepRaster::TrueColor(raster) => {
+                for px in raster.as_u8_slice().chunks_exact(4) {
+                    let rgba = match px[3] {
+                        0 => [0; 4],
+                        a => [px[0], px[1], px[2], a],
+                    };
+                    self.add_color(rgba, 1, num);
+                }
+            }
+            StepRaster::Opaque(raster) => {
+                for px in raster.as_u8_slice().chunks_exact(3) {
+                    self.add_color([px[0], px[1], px[2], 0xFF], 1, num);
+                }
+            }
+        }
+    }
+
+    /// Add pixels of one color
+    fn add_color(&mut self, rgba: [u8; 4], count: u64, num: usize) {
+        let i = match self.index.get(&color_key(rgba)) {
+            Some(i) => *i,
+            None if self.colors.len() < MAX_COLORS => {
+                let [r, g, b, a] = rgba;
+                self.colors.push(ColorStats {
+                    color: SRgba8::new(r, g, b, a),
+                    pixels: 0,
+                    frames: 0,
+                });
+                self.last_step.push(usize::MAX);
+                self.index.insert(color_key(rgba), self.colors.len() - 1);
+                self.colors.len() - 1
+            }
+            None => {
+                self.truncated = true;
+                return;
+            }
+        };
+        self.colors[i].pixels += count;
+        if self.last_step[i] != num {
+            self.last_step[i] = num;
+            self.colors[i].frames += 1;
+        }
+    }
+
+    /// Get the number of steps counted
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get usage of all colors, in order of first appearance
+    pub fn colors(&self) -> &[ColorStats] {
+        &self.colors
+    }
+
+    /// Get usage of one color
+    pub fn color(&self, clr: SRgba8) -> Option<&ColorStats> {
+        let rgba = [
+            u8::from(Rgb::red(clr)),
+            u8::from(Rgb::green(clr)),
+            u8::from(Rgb::blue(clr)),
+            u8::from(clr.alpha()),
+        ];
+        let rgba = if rgba[3] == 0 { [0; 4] } else { rgba };
+        self.index.get(&color_key(rgba)).map(|i| &self.colors[*i])
+    }
+
+    /// Check if some colors were not counted.
+    ///
+    /// This happens when true color steps contain more than 4096 distinct
+    /// colors.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Find global color table entries which are never used.
+    ///
+    /// Returns indices of entries whose (opaque) color does not appear in
+    /// any step.  A transparent color entry is only used as transparent, so
+    /// it is included unless its color is also shown somewhere.
+    pub fn unused_global_entries(&self, tbl: &GlobalColorTable) -> Vec<usize> {
+        tbl.iter()
+            .enumerate()
+            .filter(|(_, clr)| {
+                let rgba = [
+                    u8::from(Rgb::red(*clr)),
+                    u8::from(Rgb::green(*clr)),
+                    u8::from(Rgb::blue(*clr)),
+                    0xFF,
+                ];
+                !self.index.contains_key(&color_key(rgba))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pix::{gray::Gray8, rgb::SRgb8, Palette, Raster};
+
+    /// Make a step with the 3x3 encoder test image
+    fn step_3x3() -> Step {
+        let mut raster = Raster::with_clear(3, 3);
+        *raster.pixel_mut(0, 0) = Gray8::new(1);
+        *raster.pixel_mut(1, 1) = Gray8::new(2);
+        *raster.pixel_mut(2, 2) = Gray8::new(3);
+        *raster.pixel_mut(0, 2) = Gray8::new(4);
+        let mut palette = Palette::new(5);
+        palette.set_entry(SRgb8::new(0xFF, 0, 0));
+        palette.set_entry(SRgb8::new(0, 0xFF, 0));
+        palette.set_entry(SRgb8::new(0, 0, 0xFF));
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0, 0xFF));
+        Step::with_indexed(raster, palette)
+    }
+
+    #[test]
+    fn counts_3x3() {
+        let stats = palette_stats([step_3x3()]);
+        assert_eq!(stats.steps(), 1);
+        assert!(!stats.is_truncated());
+        let counts: Vec<_> = stats
+            .colors()
+            .iter()
+            .map(|c| (c.pixels, c.frames))
+            .collect();
+        assert_eq!(counts, [(5, 1), (1, 1), (1, 1), (1, 1), (1, 1)]);
+        let red = stats.color(SRgba8::new(0xFF, 0, 0, 0xFF)).unwrap();
+        assert_eq!(red.pixels, 5);
+        assert!(stats.color(SRgba8::new(0, 0, 0, 0xFF)).is_none());
+    }
+
+    #[test]
+    fn counts_frames() {
+        let indexed = step_3x3();
+        let transparent = step_3x3().with_transparent_color(Some(0));
+        let true_color = transparent.clone().into_true_color();
+        let stats = palette_stats(&[indexed, transparent, true_color]);
+        assert_eq!(stats.steps(), 3);
+        let red = stats.color(SRgba8::new(0xFF, 0, 0, 0xFF)).unwrap();
+        assert_eq!((red.pixels, red.frames), (5, 1));
+        let clear = stats.color(SRgba8::new(0xFF, 0, 0, 0)).unwrap();
+        assert_eq!((clear.pixels, clear.frames), (10, 2));
+        let green = stats.color(SRgba8::new(0, 0xFF, 0, 0xFF)).unwrap();
+        assert_eq!((green.pixels, green.frames), (3, 3));
+    }
+
+    #[test]
+    fn unused_entries() {
+        let step = step_3x3();
+        let colors: Vec<u8> = [
+            [0, 0, 0xFF],
+            [0x80, 0x80, 0x80],
+            [0xFF, 0, 0],
+            [0, 0, 0],
+            [0xFF, 0, 0xFF],
+        ]
+        .concat();
+        let tbl = GlobalColorTable::with_colors(&colors);
+        let stats = palette_stats([&step]);
+        assert_eq!(stats.unused_global_entries(&tbl), [1, 3]);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut raster = Raster::<SRgba8>::with_clear(100, 50);
+        for (i, px) in raster.pixels_mut().iter_mut().enumerate() {
+            *px = SRgba8::new(i as u8, (i >> 8) as u8, 0, 0xFF);
+        }
+        let stats = palette_stats([Step::with_true_color(raster)]);
+        assert!(stats.is_truncated());
+        assert_eq!(stats.colors().len(), MAX_COLORS);
+    }
+}
@@ -0,0 +1,334 @@
+// animation.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! Animation container
+use crate::decode::Steps;
+use crate::error::{Error, Result};
+use crate::private::{Decoder, Encoder, Step};
+use pix::Palette;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// Default maximum size of decoded steps in an [Animation] (bytes)
+///
+/// [Animation]: struct.Animation.html
+const MAX_ANIMATION_SZ: usize = 1 << 28;
+
+/// An animation held in memory, with its [Step]s and metadata.
+///
+/// ## Example: Reverse an animation
+/// ```
+/// use gift::Animation;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let gif = &[
+/// #   0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00,
+/// #   0x02, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00,
+/// #   0xff, 0xff, 0xff, 0x2c, 0x00, 0x00, 0x00, 0x00,
+/// #   0x02, 0x00, 0x02, 0x00, 0x00, 0x02, 0x03, 0x0c,
+/// #   0x10, 0x05, 0x00, 0x3b,
+/// # ][..];
+/// // ... open a `File` as "gif"
+/// let anim = Animation::load(gif)?;
+/// let mut reversed = Animation::new(anim.width(), anim.height())
+///     .with_loop_count(anim.loop_count());
+/// for step in gift::tools::reverse(anim.steps().to_vec()) {
+///     reversed.push_step(step)?;
+/// }
+/// let mut out = vec![];
+/// reversed.save(&mut out)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Step]: struct.Step.html
+#[derive(Clone, Debug)]
+pub struct Animation {
+    /// Screen width
+    width: u16,
+    /// Screen height
+    height: u16,
+    /// Loop count (zero means forever)
+    loop_count: Option<u16>,
+    /// Comments
+    comments: Vec<String>,
+    /// Global palette
+    palette: Option<Palette>,
+    /// Animation steps
+    steps: Vec<Step>,
+}
+
+impl Animation {
+    /// Create an empty animation with the specified screen size.
+    pub fn new(width: u16, height: u16) -> Self {
+        Animation {
+            width,
+            height,
+            loop_count: None,
+            comments: Vec::new(),
+            palette: None,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Load an animation from a GIF file.
+    ///
+    /// The decoded steps are limited to an internal memory size, returning
+    /// [TooLargeImage] if it is exceeded.
+    ///
+    /// [TooLargeImage]: enum.Error.html#variant.TooLargeImage
+    pub fn load<R: Read>(reader: R) -> Result<Self> {
+        Self::load_with_max_sz(reader, Some(MAX_ANIMATION_SZ))
+    }
+
+    /// Load an animation, limiting the size of decoded steps (bytes).
+    ///
+    /// Each step holds a true color raster of the full screen, at four bytes
+    /// per pixel.  Returns [TooLargeImage] if the total is larger than
+    /// `max_sz`.
+    ///
+    /// [TooLargeImage]: enum.Error.html#variant.TooLargeImage
+    pub fn load_with_max_sz<R: Read>(
+        reader: R,
+        max_sz: Option<usize>,
+    ) -> Result<Self> {
+        let mut frames = Decoder::new(reader).into_frames();
+        let preamble = frames.preamble()?.ok_or(Error::UnexpectedEndOfFile)?;
+        let mut anim =
+            Animation::new(preamble.screen_width(), preamble.screen_height());
+        anim.loop_count = preamble
+            .loop_count_ext
+            .as_ref()
+            .and_then(|app| app.loop_count());
+        for comment in &preamble.comments {
+            let text = comment.comments().collect::<Vec<_>>().concat();
+            anim.comments
+                .push(String::from_utf8_lossy(&text).into_owned());
+        }
+        anim.palette = preamble.global_color_table.as_ref().map(Palette::from);
+        let step_sz = usize::from(anim.width) * usize::from(anim.height) * 4;
+        let mut sz = 0;
+        for step in Steps::new_once(frames) {
+            sz += step_sz;
+            if let Some(max_sz) = max_sz {
+                if sz > max_sz {
+                    return Err(Error::TooLargeImage);
+                }
+            }
+            anim.steps.push(step?);
+        }
+        Ok(anim)
+    }
+
+    /// Save the animation to a GIF file.
+    ///
+    /// The steps are encoded as with [StepEnc::encode_steps].
+    ///
+    /// [StepEnc::encode_steps]: encode/struct.StepEnc.html#method.encode_steps
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        let mut enc = Encoder::new(writer).into_step_enc();
+        if let Some(loop_count) = self.loop_count {
+            enc = enc.with_loop_count(loop_count);
+        }
+        if let Some(palette) = &self.palette {
+            enc = enc.with_global_color_table(palette);
+        }
+        for comment in &self.comments {
+            enc = enc.with_comment(comment);
+        }
+        enc.encode_steps(self.steps.iter().cloned().map(Ok))?;
+        enc.finish()?;
+        Ok(())
+    }
+
+    /// Set the loop count (zero means forever).
+    pub fn with_loop_count(mut self, loop_count: Option<u16>) -> Self {
+        self.loop_count = loop_count;
+        self
+    }
+
+    /// Add a comment.
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comments.push(comment.to_string());
+        self
+    }
+
+    /// Set the global palette.
+    pub fn with_palette(mut self, palette: Option<Palette>) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Add a step to the end of the animation.
+    ///
+    /// Returns [InvalidRasterDimensions] if the step raster is not the same
+    /// size as the screen.
+    ///
+    /// [InvalidRasterDimensions]: enum.Error.html#variant.InvalidRasterDimensions
+    pub fn push_step(&mut self, step: Step) -> Result<()> {
+        let size = (u32::from(self.width), u32::from(self.height));
+        if step.raster.size() != size {
+            return Err(Error::InvalidRasterDimensions);
+        }
+        self.steps.push(step);
+        Ok(())
+    }
+
+    /// Get the screen width
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Get the screen height
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Get the loop count (zero means forever)
+    pub fn loop_count(&self) -> Option<u16> {
+        self.loop_count
+    }
+
+    /// Get the comments
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Get the global palette
+    pub fn palette(&self) -> Option<&Palette> {
+        self.palette.as_ref()
+    }
+
+    /// Get the steps
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Get the steps mutably
+    pub fn steps_mut(&mut self) -> &mut [Step] {
+        &mut self.steps
+    }
+
+    /// Get the number of frames (steps)
+    pub fn frame_count(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Iterate over the steps
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Step> + '_ {
+        self.steps.iter()
+    }
+
+    /// Get the total duration of one loop (sum of step delays)
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().filter_map(|s| s.delay()).sum()
+    }
+
+    /// Get the step displayed at a time from the start of the animation.
+    ///
+    /// Times past the end of the animation get the last step; to loop, take
+    /// the time modulo the [duration].  Returns `None` if there are no steps.
+    ///
+    /// [duration]: struct.Animation.html#method.duration
+    pub fn frame_at(&self, time: Duration) -> Option<&Step> {
+        let mut end = Duration::ZERO;
+        for step in &self.steps {
+            end += step.delay().unwrap_or_default();
+            if time < end {
+                return Some(step);
+            }
+        }
+        self.steps.last()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pix::{
+        rgb::{SRgb8, SRgba8},
+        Raster,
+    };
+
+    /// Make an animation with solid color steps
+    fn make_anim() -> Animation {
+        let mut anim = Animation::new(4, 2)
+            .with_loop_count(Some(0))
+            .with_comment("first")
+            .with_comment("second")
+            .with_palette(Some(make_palette()));
+        for (i, delay) in [10, 20, 30].iter().enumerate() {
+            let clr = SRgba8::new(i as u8 * 0x40, 0xFF, 0, 0xFF);
+            let raster = Raster::with_color(4, 2, clr);
+            let step =
+                Step::with_true_color(raster).with_delay_time_cs(Some(*delay));
+            anim.push_step(step).unwrap();
+        }
+        anim
+    }
+
+    fn make_palette() -> Palette {
+        let mut palette = Palette::new(4);
+        palette.set_entry(SRgb8::new(0, 0, 0xFF));
+        for i in (0..3).rev() {
+            palette.set_entry(SRgb8::new(i * 0x40, 0xFF, 0));
+        }
+        palette
+    }
+
+    #[test]
+    fn round_trip() {
+        let anim = make_anim();
+        let mut bytes = vec![];
+        anim.save(&mut bytes).unwrap();
+        let loaded = Animation::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.width(), 4);
+        assert_eq!(loaded.height(), 2);
+        assert_eq!(loaded.loop_count(), Some(0));
+        assert_eq!(loaded.comments(), ["first", "second"]);
+        let palette = loaded.palette().unwrap();
+        assert_eq!(palette.colors(), make_palette().colors());
+        assert_eq!(loaded.frame_count(), 3);
+        for (a, b) in anim.iter().zip(loaded.iter()) {
+            assert_eq!(a.delay_time_cs(), b.delay_time_cs());
+            assert_eq!(a.raster().pixels(), b.raster().pixels());
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let anim = make_anim();
+        assert_eq!(anim.duration(), Duration::from_millis(600));
+        let delay_at = |ms| {
+            anim.frame_at(Duration::from_millis(ms))
+                .and_then(|s| s.delay_time_cs())
+        };
+        assert_eq!(delay_at(0), Some(10));
+        assert_eq!(delay_at(99), Some(10));
+        assert_eq!(delay_at(100), Some(20));
+        assert_eq!(delay_at(599), Some(30));
+        assert_eq!(delay_at(5000), Some(30));
+        assert!(Animation::new(1, 1).frame_at(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn push_step_size() {
+        let mut anim = Animation::new(4, 4);
+        let step = Step::with_true_color(Raster::with_clear(4, 2));
+        assert!(matches!(
+            anim.push_step(step),
+            Err(Error::InvalidRasterDimensions)
+        ));
+    }
+
+    #[test]
+    fn max_sz() {
+        let mut bytes = vec![];
+        make_anim().save(&mut bytes).unwrap();
+        let anim = Animation::load_with_max_sz(&bytes[..], Some(4 * 2 * 4 * 3));
+        assert_eq!(anim.unwrap().frame_count(), 3);
+        let anim = Animation::load_with_max_sz(&bytes[..], Some(4 * 2 * 4 * 2));
+        assert!(matches!(anim, Err(Error::TooLargeImage)));
+    }
+}
@@ -19,7 +19,16 @@
 //!   - [ImageData](struct.ImageData.html)
 //! * [Trailer](struct.Trailer.html)
 //!
-use pix::{gray::Gray8, Raster, Region};
+use crate::error::{Error, Result};
+use crate::lzw::Decompressor;
+use pix::{
+    gray::Gray8,
+    rgb::{Rgb, SRgb8, SRgba8},
+    Palette, Raster, Region,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::time::Duration;
 
 /// Number of channels in color tables (red, green and blue)
 const CHANNELS: usize = 3;
@@ -67,7 +76,12 @@ impl Default for ColorTableConfig {
 }
 
 impl ColorTableConfig {
-    /// Create a new color table configuration
+    /// Create a new color table configuration.
+    ///
+    /// The table length is clamped to 2-256 and rounded up to a power of two.
+    /// See [try_new] for a checked version.
+    ///
+    /// [try_new]: struct.ColorTableConfig.html#method.try_new
     pub fn new(
         existence: ColorTableExistence,
         ordering: ColorTableOrdering,
@@ -82,6 +96,23 @@ impl ColorTableConfig {
         }
     }
 
+    /// Create a new color table configuration, checking the length.
+    ///
+    /// The table length is rounded up to a power of two.  Returns
+    /// [InvalidColorTableLen] if it is not between 1 and 256.
+    ///
+    /// [InvalidColorTableLen]: ../enum.Error.html#variant.InvalidColorTableLen
+    pub fn try_new(
+        existence: ColorTableExistence,
+        ordering: ColorTableOrdering,
+        table_len: u16,
+    ) -> Result<Self> {
+        match table_len {
+            1..=256 => Ok(Self::new(existence, ordering, table_len)),
+            _ => Err(Error::InvalidColorTableLen(table_len.into())),
+        }
+    }
+
     /// Get the existence of a color table
     pub fn existence(&self) -> ColorTableExistence {
         self.existence
@@ -164,9 +195,21 @@ impl From<DisposalMethod> for u8 {
     }
 }
 
-/// Codes for each type of block
+/// Codes for each type of block.
+///
+/// Blocks after the preamble start with an introducer byte: `,` (0x2C) for
+/// an image descriptor, `!` (0x21) for an extension and `;` (0x3B) for the
+/// trailer.  Other blocks are identified by their position in the file.
+///
+/// ```
+/// use gift::block::BlockCode;
+///
+/// assert_eq!(BlockCode::from_u8(0x21), Some(BlockCode::Extension_));
+/// assert_eq!(BlockCode::Extension_.signature(), [0x21]);
+/// assert_eq!(BlockCode::ImageDesc_.size(), 10);
+/// ```
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub(crate) enum BlockCode {
+pub enum BlockCode {
     /// Header block code (signature / magic)
     Header_,
     /// Logical screen descriptor block code
@@ -187,6 +230,8 @@ pub(crate) enum BlockCode {
 
 impl BlockCode {
     /// Get block code from a separator / introducer byte.
+    ///
+    /// Returns `None` for bytes which are not an introducer.
     pub fn from_u8(t: u8) -> Option<Self> {
         use self::BlockCode::*;
         match t {
@@ -198,9 +243,13 @@ impl BlockCode {
     }
 
     /// Get the block signature (if any).
+    ///
+    /// This is `GIF` for a header, the introducer byte for blocks which
+    /// have one, and empty for all others.
     pub fn signature(self) -> &'static [u8] {
         use self::BlockCode::*;
         match self {
+            Header_ => b"GIF",
             ImageDesc_ => b",", // (0x2C) Image separator
             Extension_ => b"!", // (0x21) Extension introducer
             Trailer_ => b";",   // (0x3B) GIF trailer
@@ -208,7 +257,11 @@ impl BlockCode {
         }
     }
 
-    /// Get the block size in bytes
+    /// Get the fixed block size in bytes.
+    ///
+    /// This includes the signature, but not any sub-blocks.  Color tables
+    /// have a variable size (from the screen or image descriptor), so their
+    /// size is 0.
     pub fn size(self) -> usize {
         use self::BlockCode::*;
         match self {
@@ -223,9 +276,18 @@ impl BlockCode {
     }
 }
 
-/// Extension block codes
+/// Extension block codes.
+///
+/// The code (label) is the byte after an extension introducer.
+///
+/// ```
+/// use gift::block::ExtensionCode;
+///
+/// assert_eq!(ExtensionCode::from(0xF9), ExtensionCode::GraphicControl_);
+/// assert_eq!(u8::from(ExtensionCode::Comment_), 0xFE);
+/// ```
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub(crate) enum ExtensionCode {
+pub enum ExtensionCode {
     /// Plain text extension code
     PlainText_,
     /// Graphic control extension code
@@ -264,6 +326,45 @@ impl From<ExtensionCode> for u8 {
     }
 }
 
+/// GIF version
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Version {
+    /// Version 87a (no extension blocks)
+    Gif87a,
+    /// Version 89a
+    #[default]
+    Gif89a,
+    /// Unrecognized version
+    Other([u8; 3]),
+}
+
+impl From<Version> for [u8; 3] {
+    fn from(v: Version) -> Self {
+        match v {
+            Version::Gif87a => *b"87a",
+            Version::Gif89a => *b"89a",
+            Version::Other(version) => version,
+        }
+    }
+}
+
+impl From<[u8; 3]> for Version {
+    fn from(version: [u8; 3]) -> Self {
+        match &version {
+            b"87a" => Version::Gif87a,
+            b"89a" => Version::Gif89a,
+            _ => Version::Other(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let version = <[u8; 3]>::from(*self);
+        write!(fmt, "{}", String::from_utf8_lossy(&version))
+    }
+}
+
 /// The header contains the
 /// [magic](https://en.wikipedia.org/wiki/File_format#Magic_number)
 /// string "GIF", followed by a version number.
@@ -285,10 +386,24 @@ impl Header {
         Header { version }
     }
 
+    /// Create a header block with a GIF [Version]
+    ///
+    /// [Version]: enum.Version.html
+    pub fn with_version_enum(version: Version) -> Self {
+        Header::with_version(version.into())
+    }
+
     /// Get the GIF version
     pub fn version(self) -> [u8; 3] {
         self.version
     }
+
+    /// Get the GIF [Version]
+    ///
+    /// [Version]: enum.Version.html
+    pub fn version_enum(self) -> Version {
+        self.version.into()
+    }
 }
 
 /// The logical screen descriptor contains properties which apply to all frames
@@ -384,7 +499,20 @@ impl LogicalScreenDesc {
 
     /// Get the color resolution (obsolete GIF feature)
     pub fn color_resolution(self) -> u16 {
-        2 << ((self.flags & Self::COLOR_RESOLUTION) >> 4_u16)
+        1 << self.color_resolution_bits()
+    }
+
+    /// Get the color resolution, in bits per primary color (1 to 8)
+    pub fn color_resolution_bits(self) -> u8 {
+        ((self.flags & Self::COLOR_RESOLUTION) >> 4) + 1
+    }
+
+    /// Set the color resolution, in bits per primary color of the source
+    /// image (clamped to 1 to 8).
+    pub fn with_color_resolution(mut self, bits: u8) -> Self {
+        let res = (bits.clamp(1, 8) - 1) << 4;
+        self.flags = (self.flags & !Self::COLOR_RESOLUTION) | res;
+        self
     }
 
     /// Check the descriptor for global color table ordering
@@ -413,10 +541,16 @@ impl LogicalScreenDesc {
         }
     }
 
-    /// Set the global color table configuration
+    /// Set the global color table configuration.
+    ///
+    /// The color resolution is not changed, unless it has not been set; in
+    /// that case it is derived from the table length.
     pub fn with_color_table_config(mut self, tbl: ColorTableConfig) -> Self {
         let mut flags = tbl.len_bits() & Self::COLOR_TABLE_SIZE;
-        flags |= (flags << 4) & Self::COLOR_RESOLUTION;
+        match self.flags & Self::COLOR_RESOLUTION {
+            0 => flags |= (flags << 4) & Self::COLOR_RESOLUTION,
+            res => flags |= res,
+        }
         if tbl.existence == ColorTableExistence::Present {
             flags |= Self::COLOR_TABLE_PRESENT;
         }
@@ -451,6 +585,30 @@ impl LogicalScreenDesc {
     pub fn pixel_aspect_ratio(self) -> u8 {
         self.pixel_aspect_ratio
     }
+
+    /// Set the pixel aspect ratio (width / height).
+    ///
+    /// The ratio is rounded to the nearest encodable value, clamped to the
+    /// range 1:4 (`0.25`) to about 4:1 (`4.21875`).  A ratio which is not
+    /// positive and finite clears the aspect ratio.
+    pub fn with_aspect_ratio(mut self, ratio: f32) -> Self {
+        self.pixel_aspect_ratio = if ratio.is_finite() && ratio > 0.0 {
+            (ratio * 64.0 - 15.0).round().clamp(1.0, 255.0) as u8
+        } else {
+            0
+        };
+        self
+    }
+
+    /// Get the pixel aspect ratio (width / height).
+    ///
+    /// Returns `None` if no aspect ratio is given.
+    pub fn aspect_ratio(self) -> Option<f32> {
+        match self.pixel_aspect_ratio {
+            0 => None,
+            n => Some((f32::from(n) + 15.0) / 64.0),
+        }
+    }
 }
 
 /// The global color table, if present, is used for all frames which do not
@@ -466,11 +624,26 @@ impl GlobalColorTable {
     /// # Panics
     ///
     /// Panics if the length of `colors` is not divisible by 3, the number of
-    /// color channels.
+    /// color channels, or if there are more than 256 colors.
     pub fn with_colors(colors: &[u8]) -> Self {
-        assert_eq!(colors.len() / CHANNELS * CHANNELS, colors.len());
+        match Self::try_with_colors(colors) {
+            Ok(tbl) => tbl,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Create a global color table with specified colors, checking the length.
+    ///
+    /// Returns [InvalidColorTableSize] if the length of `colors` is not
+    /// divisible by 3, or [InvalidColorTableLen] if there are more than 256
+    /// colors.
+    ///
+    /// [InvalidColorTableLen]: ../enum.Error.html#variant.InvalidColorTableLen
+    /// [InvalidColorTableSize]: ../enum.Error.html#variant.InvalidColorTableSize
+    pub fn try_with_colors(colors: &[u8]) -> Result<Self> {
+        check_table_size(colors.len())?;
         let colors = colors.to_vec();
-        GlobalColorTable { colors }
+        Ok(GlobalColorTable { colors })
     }
 
     /// Get the global color table length (number of entries)
@@ -487,30 +660,197 @@ impl GlobalColorTable {
     pub fn colors(&self) -> &[u8] {
         &self.colors
     }
+
+    /// Get the color of one entry
+    pub fn color(&self, idx: usize) -> Option<SRgb8> {
+        table_color(&self.colors, idx)
+    }
+
+    /// Iterate over the colors in the table
+    pub fn iter(&self) -> impl Iterator<Item = SRgb8> + '_ {
+        self.colors
+            .chunks_exact(CHANNELS)
+            .map(|c| SRgb8::new(c[0], c[1], c[2]))
+    }
+
+    /// Make a color table from a palette.
+    ///
+    /// The table length is rounded up to a power of two, padded with black.
+    /// Returns [PaletteTooLarge] if there are more than 256 colors.
+    ///
+    /// [PaletteTooLarge]: ../enum.Error.html#variant.PaletteTooLarge
+    pub fn from_palette(palette: &Palette) -> Result<(ColorTableConfig, Self)> {
+        Self::from_colors(palette.colors())
+    }
+
+    /// Make a color table from a slice of colors
+    pub(crate) fn from_colors(
+        colors: &[SRgb8],
+    ) -> Result<(ColorTableConfig, Self)> {
+        let (tbl_cfg, colors) = make_color_table(colors)?;
+        Ok((tbl_cfg, GlobalColorTable { colors }))
+    }
+}
+
+impl From<&GlobalColorTable> for Palette {
+    /// Make a palette from a color table.
+    ///
+    /// Duplicate colors (including padding) are merged.
+    fn from(tbl: &GlobalColorTable) -> Self {
+        let mut palette = Palette::new(256);
+        for clr in tbl.iter() {
+            palette.set_entry(clr);
+        }
+        palette
+    }
 }
 
 /// The plain text extension block is an obsolete GIF feature.
+///
+/// The first sub-block is a 12-byte header containing the text grid and
+/// colors, followed by the text data.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct PlainText {
-    /// Sequence of sub-blocks
+    /// Sequence of sub-blocks (first is header)
     sub_blocks: Vec<Vec<u8>>,
 }
 
 impl PlainText {
+    /// Size of the header sub-block
+    pub(crate) const HEADER_SZ: usize = 12;
+
     /// Add a sub block
     ///
-    /// # Panics
-    ///
-    /// Panics if `b` is empty or longer than 255 bytes.
+    /// Data longer than 255 bytes is split into multiple sub-blocks, and
+    /// empty data is ignored.
     pub fn add_sub_block(&mut self, b: &[u8]) {
-        assert!(!b.is_empty() && b.len() < 256);
-        self.sub_blocks.push(b.to_vec());
+        for chunk in b.chunks(255) {
+            self.sub_blocks.push(chunk.to_vec());
+        }
     }
 
     /// Get the sub blocks
     pub fn sub_blocks(&self) -> &Vec<Vec<u8>> {
         &self.sub_blocks
     }
+
+    /// Get the header sub-block, adding it if necessary
+    fn header_mut(&mut self) -> &mut Vec<u8> {
+        if self.sub_blocks.is_empty() {
+            self.sub_blocks.push(vec![0; Self::HEADER_SZ]);
+        }
+        let header = &mut self.sub_blocks[0];
+        header.resize(Self::HEADER_SZ, 0);
+        header
+    }
+
+    /// Get one byte of the header
+    fn header_u8(&self, i: usize) -> u8 {
+        self.sub_blocks
+            .first()
+            .and_then(|h| h.get(i))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get a 16-bit value from the header
+    fn header_u16(&self, i: usize) -> u16 {
+        u16::from(self.header_u8(i + 1)) << 8 | u16::from(self.header_u8(i))
+    }
+
+    /// Set a 16-bit value in the header
+    fn set_header_u16(&mut self, i: usize, value: u16) {
+        let header = self.header_mut();
+        header[i] = value as u8;
+        header[i + 1] = (value >> 8) as u8;
+    }
+
+    /// Set the text grid position
+    pub fn with_grid_position(mut self, left: u16, top: u16) -> Self {
+        self.set_header_u16(0, left);
+        self.set_header_u16(2, top);
+        self
+    }
+
+    /// Get the text grid left position
+    pub fn grid_left(&self) -> u16 {
+        self.header_u16(0)
+    }
+
+    /// Get the text grid top position
+    pub fn grid_top(&self) -> u16 {
+        self.header_u16(2)
+    }
+
+    /// Set the text grid size (pixels)
+    pub fn with_grid_size(mut self, width: u16, height: u16) -> Self {
+        self.set_header_u16(4, width);
+        self.set_header_u16(6, height);
+        self
+    }
+
+    /// Get the text grid width (pixels)
+    pub fn grid_width(&self) -> u16 {
+        self.header_u16(4)
+    }
+
+    /// Get the text grid height (pixels)
+    pub fn grid_height(&self) -> u16 {
+        self.header_u16(6)
+    }
+
+    /// Set the character cell size (pixels)
+    pub fn with_cell_size(mut self, width: u8, height: u8) -> Self {
+        let header = self.header_mut();
+        header[8] = width;
+        header[9] = height;
+        self
+    }
+
+    /// Get the character cell width (pixels)
+    pub fn cell_width(&self) -> u8 {
+        self.header_u8(8)
+    }
+
+    /// Get the character cell height (pixels)
+    pub fn cell_height(&self) -> u8 {
+        self.header_u8(9)
+    }
+
+    /// Set the text foreground color index
+    pub fn with_fg_color_idx(mut self, idx: u8) -> Self {
+        self.header_mut()[10] = idx;
+        self
+    }
+
+    /// Get the text foreground color index
+    pub fn fg_color_idx(&self) -> u8 {
+        self.header_u8(10)
+    }
+
+    /// Set the text background color index
+    pub fn with_bg_color_idx(mut self, idx: u8) -> Self {
+        self.header_mut()[11] = idx;
+        self
+    }
+
+    /// Get the text background color index
+    pub fn bg_color_idx(&self) -> u8 {
+        self.header_u8(11)
+    }
+
+    /// Add text data, splitting it into sub-blocks of up to 255 bytes.
+    pub fn add_text(&mut self, text: &[u8]) {
+        self.header_mut();
+        for chunk in text.chunks(255) {
+            self.sub_blocks.push(chunk.to_vec());
+        }
+    }
+
+    /// Get the text data sub-blocks
+    pub fn text(&self) -> &[Vec<u8>] {
+        self.sub_blocks.get(1..).unwrap_or(&[])
+    }
 }
 
 /// The graphic control extension block contains animation parameters for one
@@ -574,6 +914,19 @@ impl GraphicControl {
         self.delay_time_cs = delay_time_cs;
     }
 
+    /// Get the frame delay time
+    pub fn delay(self) -> Duration {
+        Duration::from_millis(u64::from(self.delay_time_cs) * 10)
+    }
+
+    /// Set the frame delay time.
+    ///
+    /// The delay is rounded to the nearest centisecond, with a minimum of
+    /// 1 cs for non-zero durations, saturating at `u16::MAX` cs.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay_time_cs = delay_cs(delay);
+    }
+
     /// Get the transparent color, if any
     pub fn transparent_color(self) -> Option<u8> {
         let t = (self.flags & Self::TRANSPARENT_COLOR) != 0;
@@ -609,29 +962,88 @@ impl GraphicControl {
     }
 }
 
+/// Convert a delay duration to centiseconds (rounded to nearest)
+pub(crate) fn delay_cs(delay: Duration) -> u16 {
+    const CS_NANOS: u128 = 10_000_000;
+    let cs = (delay.as_nanos() + CS_NANOS / 2) / CS_NANOS;
+    if cs == 0 && !delay.is_zero() {
+        1
+    } else {
+        u16::try_from(cs).unwrap_or(u16::MAX)
+    }
+}
+
+/// Sequence of extension sub-blocks, stored contiguously.
+///
+/// Each sub-block is prefixed by its length, as in a GIF file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct SubBlocks {
+    /// Length-prefixed sub-block data
+    data: Vec<u8>,
+}
+
+impl SubBlocks {
+    /// Add a sub-block
+    ///
+    /// Data longer than 255 bytes is split into multiple sub-blocks, and
+    /// empty data is ignored.
+    fn push(&mut self, b: &[u8]) {
+        for chunk in b.chunks(255) {
+            self.data.push(chunk.len() as u8);
+            self.data.extend_from_slice(chunk);
+        }
+    }
+
+    /// Get an iterator of sub-blocks
+    fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        let mut data = &self.data[..];
+        std::iter::from_fn(move || {
+            let (len, rest) = data.split_first()?;
+            let (b, rest) = rest.split_at(usize::from(*len));
+            data = rest;
+            Some(b)
+        })
+    }
+}
+
 /// A comment extension block contains unstructured file metadata.
 ///
 /// The specification recommends using the ASCII encoding.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Comment {
-    /// Vec of comments
-    comments: Vec<Vec<u8>>,
+    /// Comment sub-blocks
+    comments: SubBlocks,
 }
 
 impl Comment {
-    /// Add a comment
-    ///
-    /// # Panics
+    /// Add a comment, splitting it into sub-blocks of up to 255 bytes.
     ///
-    /// Panics if `b` is empty or longer than 255 bytes.
+    /// An empty comment is ignored.
     pub fn add_comment(&mut self, b: &[u8]) {
-        assert!(!b.is_empty() && b.len() < 256);
-        self.comments.push(b.to_vec());
+        self.comments.push(b);
+    }
+
+    /// Set comment text, replacing any existing comments.
+    ///
+    /// The text is split into sub-blocks of up to 255 bytes, without
+    /// splitting any UTF-8 code points.
+    pub fn set_text(&mut self, text: &str) {
+        self.comments = SubBlocks::default();
+        let mut text = text;
+        while !text.is_empty() {
+            let mut end = text.len().min(255);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (chunk, rest) = text.split_at(end);
+            self.comments.push(chunk.as_bytes());
+            text = rest;
+        }
     }
 
     /// Get the comments
-    pub fn comments(&self) -> &Vec<Vec<u8>> {
-        &self.comments
+    pub fn comments(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.comments.iter()
     }
 }
 
@@ -640,7 +1052,7 @@ impl Comment {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Application {
     /// Sequence of sub-blocks
-    app_data: Vec<Vec<u8>>,
+    app_data: SubBlocks,
 }
 
 impl Application {
@@ -649,29 +1061,52 @@ impl Application {
         app_id == b"NETSCAPE2.0" || app_id == b"ANIMEXTS1.0"
     }
 
+    /// Create a new application block.
+    ///
+    /// * `app_id`: Application identifier.
+    /// * `auth_code`: Application authentication code.
+    pub fn new(app_id: [u8; 8], auth_code: [u8; 3]) -> Self {
+        let mut app_data = SubBlocks::default();
+        app_data.push(&[&app_id[..], &auth_code[..]].concat());
+        Application { app_data }
+    }
+
     /// Create a new application block with specified loop count.
     ///
     /// Use zero to loop forever.
     pub fn with_loop_count(loop_count: u16) -> Self {
-        let param0 = b"NETSCAPE2.0".to_vec();
-        let param1 = vec![1, (loop_count >> 8) as u8, loop_count as u8];
-        let app_data = vec![param0, param1];
+        let mut app_data = SubBlocks::default();
+        app_data.push(b"NETSCAPE2.0");
+        app_data.push(&[1, (loop_count >> 8) as u8, loop_count as u8]);
         Application { app_data }
     }
 
+    /// Add a NETSCAPE buffering sub-block (ID 2) with buffer size in bytes.
+    pub fn with_buffering(mut self, buffer_sz: u32) -> Self {
+        let b = buffer_sz.to_le_bytes();
+        self.app_data.push(&[2, b[0], b[1], b[2], b[3]]);
+        self
+    }
+
     /// Add application data
     ///
-    /// # Panics
-    ///
-    /// Panics if `b` is empty or longer than 255 bytes.
+    /// Same as [add_data](#method.add_data).
+    #[deprecated(since = "0.11.0", note = "use `add_data` instead")]
     pub fn add_app_data(&mut self, b: &[u8]) {
-        assert!(!b.is_empty() && b.len() < 256);
-        self.app_data.push(b.to_vec());
+        self.add_data(b);
+    }
+
+    /// Add application data, splitting it into sub-blocks of up to 255
+    /// bytes.
+    ///
+    /// Empty data is ignored.
+    pub fn add_data(&mut self, data: &[u8]) {
+        self.app_data.push(data);
     }
 
-    /// Get the application data
-    pub fn app_data(&self) -> &Vec<Vec<u8>> {
-        &self.app_data
+    /// Get the application data sub-blocks
+    pub fn app_data(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.app_data.iter()
     }
 
     /// Get the loop count, if applicable.
@@ -681,16 +1116,29 @@ impl Application {
     pub fn loop_count(&self) -> Option<u16> {
         // NOTE: this block must follow immediately after GlobalColorTable
         //       (or LogicalScreenDesc if there is no GlobalColorTable).
-        let d = &self.app_data;
-        let exists = d.len() == 2 &&            // 2 sub-blocks
-                     Self::is_looping(&d[0]) && // app ID / auth code
-                     d[1].len() == 3 &&         // app data sub-block length
-                     d[1][0] == 1; // sub-block ID
-        if exists {
-            Some(u16::from(d[1][1]) << 8 | u16::from(d[1][2]))
-        } else {
-            None
-        }
+        self.looping_data().find_map(|d| match *d {
+            // sub-block ID 1: loop count
+            [1, hi, lo] => Some(u16::from(hi) << 8 | u16::from(lo)),
+            _ => None,
+        })
+    }
+
+    /// Get the buffer size (bytes), if applicable.
+    ///
+    /// This is from the NETSCAPE buffering sub-block (ID 2).
+    pub fn buffering(&self) -> Option<u32> {
+        self.looping_data().find_map(|d| match *d {
+            // sub-block ID 2: buffering
+            [2, a, b, c, d] => Some(u32::from_le_bytes([a, b, c, d])),
+            _ => None,
+        })
+    }
+
+    /// Get data sub-blocks of a looping application block
+    fn looping_data(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        let mut d = self.app_data.iter();
+        let looping = d.next().is_some_and(Self::is_looping);
+        d.filter(move |_| looping)
     }
 }
 
@@ -699,36 +1147,26 @@ impl Application {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Unknown {
     /// Sequence of sub-blocks (first has ext_id)
-    sub_blocks: Vec<Vec<u8>>,
+    sub_blocks: SubBlocks,
 }
 
 impl Unknown {
     /// Get the extension ID
     pub fn ext_id(&self) -> &[u8] {
-        if self.sub_blocks.is_empty() {
-            &[]
-        } else {
-            &self.sub_blocks[0]
-        }
+        self.sub_blocks.iter().next().unwrap_or(&[])
     }
 
     /// Add a sub-block
     ///
-    /// # Panics
-    ///
-    /// Panics if `b` is empty or longer than 255 bytes.
+    /// Data longer than 255 bytes is split into multiple sub-blocks, and
+    /// empty data is ignored.
     pub fn add_sub_block(&mut self, b: &[u8]) {
-        assert!(!b.is_empty() && b.len() < 256);
-        self.sub_blocks.push(b.to_vec());
+        self.sub_blocks.push(b);
     }
 
-    /// Get the sub-blocks
-    pub fn sub_blocks(&self) -> &[Vec<u8>] {
-        if self.sub_blocks.is_empty() {
-            &[]
-        } else {
-            &self.sub_blocks[1..]
-        }
+    /// Get the sub-blocks (after the extension ID)
+    pub fn sub_blocks(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.sub_blocks.iter().skip(1)
     }
 }
 
@@ -917,11 +1355,26 @@ impl LocalColorTable {
     /// # Panics
     ///
     /// Panics if the length of `colors` is not divisible by 3, the number of
-    /// color channels.
+    /// color channels, or if there are more than 256 colors.
     pub fn with_colors(colors: &[u8]) -> Self {
-        assert_eq!(colors.len() / CHANNELS * CHANNELS, colors.len());
+        match Self::try_with_colors(colors) {
+            Ok(tbl) => tbl,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Create a local color table with specified colors, checking the length.
+    ///
+    /// Returns [InvalidColorTableSize] if the length of `colors` is not
+    /// divisible by 3, or [InvalidColorTableLen] if there are more than 256
+    /// colors.
+    ///
+    /// [InvalidColorTableLen]: ../enum.Error.html#variant.InvalidColorTableLen
+    /// [InvalidColorTableSize]: ../enum.Error.html#variant.InvalidColorTableSize
+    pub fn try_with_colors(colors: &[u8]) -> Result<Self> {
+        check_table_size(colors.len())?;
         let colors = colors.to_vec();
-        LocalColorTable { colors }
+        Ok(LocalColorTable { colors })
     }
 
     /// Get the local color table length (number of entries)
@@ -938,13 +1391,62 @@ impl LocalColorTable {
     pub fn colors(&self) -> &[u8] {
         &self.colors
     }
+
+    /// Get the color of one entry
+    pub fn color(&self, idx: usize) -> Option<SRgb8> {
+        table_color(&self.colors, idx)
+    }
+
+    /// Iterate over the colors in the table
+    pub fn iter(&self) -> impl Iterator<Item = SRgb8> + '_ {
+        self.colors
+            .chunks_exact(CHANNELS)
+            .map(|c| SRgb8::new(c[0], c[1], c[2]))
+    }
+
+    /// Make a color table from a palette.
+    ///
+    /// The table length is rounded up to a power of two, padded with black.
+    /// Returns [PaletteTooLarge] if there are more than 256 colors.
+    ///
+    /// [PaletteTooLarge]: ../enum.Error.html#variant.PaletteTooLarge
+    pub fn from_palette(palette: &Palette) -> Result<(ColorTableConfig, Self)> {
+        Self::from_colors(palette.colors())
+    }
+
+    /// Make a color table from a slice of colors
+    pub(crate) fn from_colors(
+        colors: &[SRgb8],
+    ) -> Result<(ColorTableConfig, Self)> {
+        let (tbl_cfg, colors) = make_color_table(colors)?;
+        Ok((tbl_cfg, LocalColorTable { colors }))
+    }
+}
+
+impl From<&LocalColorTable> for Palette {
+    /// Make a palette from a color table.
+    ///
+    /// Duplicate colors (including padding) are merged.
+    fn from(tbl: &LocalColorTable) -> Self {
+        let mut palette = Palette::new(256);
+        for clr in tbl.iter() {
+            palette.set_entry(clr);
+        }
+        palette
+    }
 }
 
 /// An image data block contains image data for one frame.
+///
+/// Normally, the data is uncompressed color indices.  Raw image data is
+/// LZW-compressed, stored as sub-blocks (each with a leading size byte),
+/// without the final zero-length sub-block.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ImageData {
-    /// Image data in uncompressed form.
+    /// Image data in uncompressed form (or raw sub-blocks).
     data: Vec<u8>,
+    /// LZW minimum code size (raw data only)
+    min_code_size: Option<u8>,
 }
 
 impl From<&Raster<Gray8>> for ImageData {
@@ -960,7 +1462,39 @@ impl ImageData {
     /// Create a new image data block
     pub fn new(image_sz: usize) -> Self {
         let data = Vec::with_capacity(image_sz);
-        ImageData { data }
+        ImageData {
+            data,
+            min_code_size: None,
+        }
+    }
+
+    /// Create a new raw image data block with LZW-compressed sub-blocks
+    pub fn new_raw(min_code_size: u8) -> Self {
+        ImageData {
+            data: Vec::new(),
+            min_code_size: Some(min_code_size),
+        }
+    }
+
+    /// Check if the image data is raw (LZW-compressed)
+    pub fn is_raw(&self) -> bool {
+        self.min_code_size.is_some()
+    }
+
+    /// Get the LZW minimum code size (raw data only)
+    pub fn min_code_size(&self) -> Option<u8> {
+        self.min_code_size
+    }
+
+    /// Get the maximum color index used.
+    ///
+    /// Returns `None` for raw or empty image data.
+    pub fn max_index(&self) -> Option<u8> {
+        if self.is_raw() {
+            None
+        } else {
+            self.data.iter().copied().max()
+        }
     }
 
     /// Get the image data
@@ -972,6 +1506,80 @@ impl ImageData {
     pub fn data_mut(&mut self) -> &mut Vec<u8> {
         &mut self.data
     }
+
+    /// Reorder rows from raster order into interlaced order.
+    ///
+    /// Raw data, or data which does not match the size of `image_desc`, is
+    /// not changed.
+    pub fn interlace(&self, image_desc: &ImageDesc) -> Self {
+        if self.is_raw() {
+            return self.clone();
+        }
+        let data = interlace(&self.data, image_desc);
+        ImageData {
+            data,
+            min_code_size: None,
+        }
+    }
+
+    /// Reorder rows from interlaced order into raster order.
+    ///
+    /// Raw data, or data which does not match the size of `image_desc`, is
+    /// not changed.
+    pub fn deinterlace(&self, image_desc: &ImageDesc) -> Self {
+        if self.is_raw() {
+            return self.clone();
+        }
+        let mut image_data = ImageData::new(self.data.len());
+        match rows(&self.data, image_desc) {
+            Some(rows) => {
+                let mut order = vec![0; rows.len()];
+                for (i, row) in interlaced_rows(rows.len()).enumerate() {
+                    order[row] = i;
+                }
+                for i in order {
+                    image_data.data.extend_from_slice(rows[i]);
+                }
+            }
+            None => image_data.data.extend_from_slice(&self.data),
+        }
+        image_data
+    }
+}
+
+/// Reorder rows of image data from raster order into interlaced order.
+///
+/// Data which does not match the size of `image_desc` is not changed.
+pub(crate) fn interlace(data: &[u8], image_desc: &ImageDesc) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len());
+    match rows(data, image_desc) {
+        Some(rows) => {
+            for row in interlaced_rows(rows.len()) {
+                buf.extend_from_slice(rows[row]);
+            }
+        }
+        None => buf.extend_from_slice(data),
+    }
+    buf
+}
+
+/// Split image data into rows, if it matches the image size
+fn rows<'a>(data: &'a [u8], image_desc: &ImageDesc) -> Option<Vec<&'a [u8]>> {
+    let width = usize::from(image_desc.width());
+    if width > 0 && data.len() == image_desc.image_sz() {
+        Some(data.chunks_exact(width).collect())
+    } else {
+        None
+    }
+}
+
+/// Get row numbers of an image in interlaced order (four passes)
+fn interlaced_rows(height: usize) -> impl Iterator<Item = usize> {
+    (0..height)
+        .step_by(8)
+        .chain((4..height).step_by(8))
+        .chain((2..height).step_by(4))
+        .chain((1..height).step_by(2))
 }
 
 /// The trailer block indicates the end of a GIF file.
@@ -1021,6 +1629,67 @@ impl Block {
                 | ImageData(_)
         )
     }
+
+    /// Get the block code
+    pub fn code(&self) -> BlockCode {
+        use self::Block::*;
+        match self {
+            Header(_) => BlockCode::Header_,
+            LogicalScreenDesc(_) => BlockCode::LogicalScreenDesc_,
+            GlobalColorTable(_) => BlockCode::GlobalColorTable_,
+            PlainText(_) | GraphicControl(_) | Comment(_) | Application(_)
+            | Unknown(_) => BlockCode::Extension_,
+            ImageDesc(_) => BlockCode::ImageDesc_,
+            LocalColorTable(_) => BlockCode::LocalColorTable_,
+            ImageData(_) => BlockCode::ImageData_,
+            Trailer(_) => BlockCode::Trailer_,
+        }
+    }
+
+    /// Get the extension code, for extension blocks
+    pub fn extension_code(&self) -> Option<ExtensionCode> {
+        use self::Block::*;
+        match self {
+            PlainText(_) => Some(ExtensionCode::PlainText_),
+            GraphicControl(_) => Some(ExtensionCode::GraphicControl_),
+            Comment(_) => Some(ExtensionCode::Comment_),
+            Application(_) => Some(ExtensionCode::Application_),
+            Unknown(b) => {
+                let id = b.ext_id().first().copied().unwrap_or_default();
+                Some(ExtensionCode::Unknown_(id))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the expected next block code and size
+    pub(crate) fn expected_next(&self) -> Option<(BlockCode, usize)> {
+        use crate::block::BlockCode::*;
+        match self {
+            Block::Header(_) => {
+                Some((LogicalScreenDesc_, LogicalScreenDesc_.size()))
+            }
+            Block::LogicalScreenDesc(b) => {
+                let sz = b.color_table_config().size_bytes();
+                if sz > 0 {
+                    Some((GlobalColorTable_, sz))
+                } else {
+                    None
+                }
+            }
+            Block::ImageDesc(b) => {
+                let sz = b.color_table_config().size_bytes();
+                if sz > 0 {
+                    Some((LocalColorTable_, sz))
+                } else {
+                    Some((ImageData_, ImageData_.size()))
+                }
+            }
+            Block::LocalColorTable(_) => Some((ImageData_, ImageData_.size())),
+            Block::Trailer(_) => Some((Header_, Header_.size())),
+            _ => None,
+        }
+    }
 }
 
 impl From<Header> for Block {
@@ -1107,11 +1776,78 @@ pub struct Preamble {
     pub global_color_table: Option<GlobalColorTable>,
     /// Loop count (application) extension block
     pub loop_count_ext: Option<Application>,
+    /// Other application extension blocks
+    pub applications: Vec<Application>,
     /// Comment blocks
     pub comments: Vec<Comment>,
 }
 
 impl Preamble {
+    /// Create a preamble with the specified screen size.
+    pub fn with_screen(width: u16, height: u16) -> Self {
+        let logical_screen_desc = LogicalScreenDesc::default()
+            .with_screen_width(width)
+            .with_screen_height(height);
+        Preamble {
+            logical_screen_desc,
+            ..Default::default()
+        }
+    }
+
+    /// Set the GIF version of the header.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.header = Header::with_version_enum(version);
+        self
+    }
+
+    /// Set the global color table from a palette.
+    ///
+    /// The table length is rounded up to a power of two, padded with black.
+    pub fn with_palette(self, palette: &Palette) -> Self {
+        // a pix palette never has more than 256 entries
+        self.with_colors(palette.colors())
+            .expect("palette too large")
+    }
+
+    /// Set the global color table from a slice of colors
+    pub(crate) fn with_colors(mut self, colors: &[SRgb8]) -> Result<Self> {
+        let (tbl_cfg, tbl) = GlobalColorTable::from_colors(colors)?;
+        self.logical_screen_desc =
+            self.logical_screen_desc.with_color_table_config(tbl_cfg);
+        self.global_color_table = Some(tbl);
+        Ok(self)
+    }
+
+    /// Set the background color index.
+    pub fn with_background_color_idx(mut self, idx: u8) -> Self {
+        self.logical_screen_desc =
+            self.logical_screen_desc.with_background_color_idx(idx);
+        self
+    }
+
+    /// Set the loop count for an animation.
+    ///
+    /// * `loop_count`: Number of times to loop animation; zero means forever.
+    pub fn with_loop_count(mut self, loop_count: u16) -> Self {
+        self.loop_count_ext = Some(Application::with_loop_count(loop_count));
+        self
+    }
+
+    /// Add an application extension block.
+    pub fn with_application(mut self, app: Application) -> Self {
+        self.applications.push(app);
+        self
+    }
+
+    /// Add a comment, splitting it into sub-blocks of up to 255 bytes
+    /// (without splitting UTF-8 code points).
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        let mut block = Comment::default();
+        block.set_text(comment);
+        self.comments.push(block);
+        self
+    }
+
     /// Get the screen width
     pub fn screen_width(&self) -> u16 {
         self.logical_screen_desc.screen_width()
@@ -1121,6 +1857,59 @@ impl Preamble {
     pub fn screen_height(&self) -> u16 {
         self.logical_screen_desc.screen_height()
     }
+
+    /// Get the background color.
+    ///
+    /// Returns `None` without a global color table, or if the background
+    /// color index is not within it.
+    pub fn background_color(&self) -> Option<SRgb8> {
+        let idx = self.logical_screen_desc.background_color_idx();
+        self.global_color_table.as_ref()?.color(usize::from(idx))
+    }
+}
+
+/// Make a color table from palette colors
+fn make_color_table(colors: &[SRgb8]) -> Result<(ColorTableConfig, Vec<u8>)> {
+    if colors.len() > 256 {
+        return Err(Error::PaletteTooLarge(colors.len()));
+    }
+    let tbl_cfg = table_config(colors.len());
+    let mut pal = Vec::with_capacity(tbl_cfg.size_bytes());
+    for clr in colors {
+        pal.push(u8::from(Rgb::red(*clr)));
+        pal.push(u8::from(Rgb::green(*clr)));
+        pal.push(u8::from(Rgb::blue(*clr)));
+    }
+    pal.resize(tbl_cfg.size_bytes(), 0);
+    Ok((tbl_cfg, pal))
+}
+
+/// Make a color table config for a number of colors
+fn table_config(len: usize) -> ColorTableConfig {
+    ColorTableConfig::new(
+        ColorTableExistence::Present,
+        ColorTableOrdering::NotSorted,
+        len as u16,
+    )
+}
+
+/// Check the size (in bytes) of color table data
+fn check_table_size(sz: usize) -> Result<()> {
+    if sz / CHANNELS * CHANNELS != sz {
+        return Err(Error::InvalidColorTableSize(sz));
+    }
+    let len = sz / CHANNELS;
+    if len > 256 {
+        return Err(Error::InvalidColorTableLen(len));
+    }
+    Ok(())
+}
+
+/// Get one color from color table data
+fn table_color(colors: &[u8], idx: usize) -> Option<SRgb8> {
+    let i = idx.checked_mul(CHANNELS)?;
+    let c = colors.get(i..i + CHANNELS)?;
+    Some(SRgb8::new(c[0], c[1], c[2]))
 }
 
 /// A single frame of a GIF animation.
@@ -1155,7 +1944,23 @@ impl Frame {
         }
     }
 
-    /// Get the frame disposal method
+    /// Get the frame delay time in centiseconds.
+    ///
+    /// Returns `None` without a graphic control block.
+    pub fn delay_time_cs(&self) -> Option<u16> {
+        self.graphic_control_ext.map(|gc| gc.delay_time_cs())
+    }
+
+    /// Get the frame delay time.
+    ///
+    /// Returns `None` without a graphic control block.
+    pub fn delay(&self) -> Option<Duration> {
+        self.graphic_control_ext.map(|gc| gc.delay())
+    }
+
+    /// Get the frame disposal method.
+    ///
+    /// Without a graphic control block, this is `NoAction`.
     pub fn disposal_method(&self) -> DisposalMethod {
         match &self.graphic_control_ext {
             Some(gc) => gc.disposal_method(),
@@ -1163,7 +1968,9 @@ impl Frame {
         }
     }
 
-    /// Get the frame transparent color
+    /// Get the frame transparent color.
+    ///
+    /// Returns `None` without a graphic control block.
     pub fn transparent_color(&self) -> Option<u8> {
         match &self.graphic_control_ext {
             Some(gc) => gc.transparent_color(),
@@ -1191,7 +1998,17 @@ impl Frame {
         self.image_desc.height()
     }
 
-    /// Get frame region
+    /// Check if the frame is interlaced
+    pub fn interlaced(&self) -> bool {
+        self.image_desc.interlaced()
+    }
+
+    /// Check if the image data is raw (LZW-compressed)
+    pub fn is_raw(&self) -> bool {
+        self.image_data.is_raw()
+    }
+
+    /// Get the frame region within the logical screen
     pub fn region(&self) -> Region {
         let x = self.left().into();
         let y = self.top().into();
@@ -1199,6 +2016,201 @@ impl Frame {
         let h = self.height().into();
         Region::new(x, y, w, h)
     }
+
+    /// Get the color table for the frame (local or global)
+    pub(crate) fn color_table<'a>(
+        &'a self,
+        global_tbl: Option<&'a GlobalColorTable>,
+    ) -> Result<&'a [u8]> {
+        match (&self.local_color_table, global_tbl) {
+            (Some(tbl), _) => Ok(tbl.colors()),
+            (None, Some(tbl)) => Ok(tbl.colors()),
+            (None, None) => Err(Error::MissingColorTable),
+        }
+    }
+
+    /// Get the color indices of the frame, in raster order
+    fn indices(&self) -> Result<Cow<'_, [u8]>> {
+        let data = match self.image_data.min_code_size() {
+            Some(min_code_bits) => {
+                let sz = self.image_desc.image_sz();
+                let mut dec = Decompressor::new(min_code_bits.clamp(2, 8))
+                    .with_max_len(sz);
+                let mut image_data = ImageData::new(sz);
+                let raw = self.image_data.data();
+                let mut pos = 0;
+                while pos < raw.len() {
+                    let end = (pos + 1 + usize::from(raw[pos])).min(raw.len());
+                    dec.decompress(&raw[pos + 1..end], image_data.data_mut())?;
+                    pos = end;
+                }
+                if self.interlaced() {
+                    image_data = image_data.deinterlace(&self.image_desc);
+                }
+                Cow::Owned(image_data.data)
+            }
+            None => Cow::Borrowed(self.image_data.data()),
+        };
+        if data.len() == self.image_desc.image_sz() {
+            Ok(data)
+        } else {
+            Err(Error::IncompleteImageData)
+        }
+    }
+
+    /// Count the pixels using each color index
+    pub fn color_usage(&self) -> Result<ColorUsage> {
+        let mut counts = [0; 256];
+        for idx in self.indices()?.iter() {
+            counts[usize::from(*idx)] += 1;
+        }
+        Ok(ColorUsage { counts })
+    }
+
+    /// Convert the frame to a true color raster, without compositing.
+    ///
+    /// The raster covers only the frame region.  Colors come from the local
+    /// color table, or `global_tbl` if there is none.  Pixels using the
+    /// transparent color are fully transparent.
+    pub fn to_raster(
+        &self,
+        global_tbl: Option<&GlobalColorTable>,
+    ) -> Result<Raster<SRgba8>> {
+        let clrs = self.color_table(global_tbl)?;
+        let trans_clr = self.transparent_color();
+        let pixels = self
+            .indices()?
+            .iter()
+            .map(|idx| index_color(clrs, trans_clr, *idx))
+            .collect::<Result<Vec<SRgba8>>>()?;
+        let width = self.width().into();
+        let height = self.height().into();
+        Ok(Raster::with_pixels(width, height, pixels))
+    }
+
+    /// Convert the frame to an indexed raster and palette.
+    ///
+    /// The raster covers only the frame region.  Colors come from the local
+    /// color table, or `global_tbl` if there is none.  Duplicate colors in
+    /// the table are merged into one palette entry.
+    pub fn to_indexed(
+        &self,
+        global_tbl: Option<&GlobalColorTable>,
+    ) -> Result<(Raster<Gray8>, Palette)> {
+        let clrs = self.color_table(global_tbl)?;
+        let mut palette = Palette::new(256);
+        let mut map = Vec::with_capacity(clrs.len() / CHANNELS);
+        for i in 0..clrs.len() / CHANNELS {
+            let clr = table_color(clrs, i).unwrap_or_default();
+            let idx = palette.set_entry(clr).unwrap_or_default();
+            map.push(Gray8::new(idx as u8));
+        }
+        let pixels = self
+            .indices()?
+            .iter()
+            .map(|idx| {
+                map.get(usize::from(*idx))
+                    .copied()
+                    .ok_or(Error::InvalidColorIndex)
+            })
+            .collect::<Result<Vec<Gray8>>>()?;
+        let width = self.width().into();
+        let height = self.height().into();
+        Ok((Raster::with_pixels(width, height, pixels), palette))
+    }
+
+    /// Get the local color table as a palette, if present.
+    ///
+    /// Duplicate colors in the table are merged into one palette entry.
+    pub fn palette(&self) -> Option<Palette> {
+        self.local_color_table.as_ref().map(Palette::from)
+    }
+}
+
+impl TryFrom<&Frame> for Raster<Gray8> {
+    type Error = Error;
+
+    /// Get the color indices of a frame as a raster.
+    ///
+    /// The raster covers only the frame region.  Raw image data is
+    /// decompressed and deinterlaced.  Returns [IncompleteImageData] if the
+    /// data length does not match the frame size.
+    ///
+    /// [IncompleteImageData]: ../enum.Error.html#variant.IncompleteImageData
+    fn try_from(frame: &Frame) -> Result<Self> {
+        let pixels: Vec<Gray8> = frame
+            .indices()?
+            .iter()
+            .map(|idx| Gray8::new(*idx))
+            .collect();
+        let width = frame.width().into();
+        let height = frame.height().into();
+        Ok(Raster::with_pixels(width, height, pixels))
+    }
+}
+
+/// Count of pixels using each color index in a [Frame]
+///
+/// [Frame]: struct.Frame.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColorUsage {
+    /// Pixel count for each index
+    counts: [u32; 256],
+}
+
+impl ColorUsage {
+    /// Get the pixel count for one color index
+    pub fn count(&self, idx: u8) -> u32 {
+        self.counts[usize::from(idx)]
+    }
+
+    /// Get the number of distinct color indices used
+    pub fn distinct(&self) -> usize {
+        self.counts.iter().filter(|c| **c > 0).count()
+    }
+
+    /// Get the most common color index (lowest index on ties)
+    pub fn most_common(&self) -> Option<u8> {
+        self.iter()
+            .fold(None, |best: Option<(u8, u32)>, (idx, count)| match best {
+                Some((_, c)) if c >= count => best,
+                _ => Some((idx, count)),
+            })
+            .map(|(idx, _)| idx)
+    }
+
+    /// Get the maximum color index used
+    pub fn max_index(&self) -> Option<u8> {
+        self.iter().last().map(|(idx, _)| idx)
+    }
+
+    /// Iterate over used color indices, with pixel counts
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .map(|(idx, c)| (idx as u8, *c))
+    }
+}
+
+/// Get the color of an index in a color table
+pub(crate) fn index_color(
+    clrs: &[u8],
+    trans_clr: Option<u8>,
+    idx: u8,
+) -> Result<SRgba8> {
+    let clr =
+        table_color(clrs, usize::from(idx)).ok_or(Error::InvalidColorIndex)?;
+    Ok(match trans_clr {
+        Some(trans_idx) if trans_idx == idx => SRgba8::default(),
+        _ => SRgba8::new(
+            u8::from(Rgb::red(clr)),
+            u8::from(Rgb::green(clr)),
+            u8::from(Rgb::blue(clr)),
+            255,
+        ),
+    })
 }
 
 #[cfg(test)]
@@ -1211,6 +2223,236 @@ mod test {
         assert!(std::mem::size_of::<Block>() <= 32);
     }
 
+    #[test]
+    fn block_codes() {
+        use BlockCode::*;
+        assert_eq!(BlockCode::from_u8(0x2C), Some(ImageDesc_));
+        assert_eq!(BlockCode::from_u8(0x21), Some(Extension_));
+        assert_eq!(BlockCode::from_u8(0x3B), Some(Trailer_));
+        assert_eq!(BlockCode::from_u8(0x00), None);
+        assert_eq!(Header_.signature(), b"GIF");
+        assert_eq!(ImageDesc_.signature(), [0x2C]);
+        assert_eq!(Extension_.signature(), [0x21]);
+        assert_eq!(Trailer_.signature(), [0x3B]);
+        assert_eq!(GlobalColorTable_.signature(), []);
+        let sizes: Vec<_> = [
+            Header_,
+            LogicalScreenDesc_,
+            GlobalColorTable_,
+            Extension_,
+            ImageDesc_,
+            LocalColorTable_,
+            ImageData_,
+            Trailer_,
+        ]
+        .iter()
+        .map(|bc| bc.size())
+        .collect();
+        assert_eq!(sizes, [6, 7, 0, 2, 10, 0, 1, 1]);
+    }
+
+    #[test]
+    fn extension_codes() {
+        use ExtensionCode::*;
+        let codes = [
+            (PlainText_, 0x01),
+            (GraphicControl_, 0xF9),
+            (Comment_, 0xFE),
+            (Application_, 0xFF),
+            (Unknown_(0x42), 0x42),
+        ];
+        for (code, byte) in codes {
+            assert_eq!(u8::from(code), byte);
+            assert_eq!(ExtensionCode::from(byte), code);
+        }
+        let block = Block::from(Comment::default());
+        assert_eq!(block.code(), BlockCode::Extension_);
+        assert_eq!(block.extension_code(), Some(Comment_));
+        let mut unknown = Unknown::default();
+        unknown.add_sub_block(&[0x42]);
+        let block = Block::from(unknown);
+        assert_eq!(block.extension_code(), Some(Unknown_(0x42)));
+        let block = Block::from(Trailer::default());
+        assert_eq!(block.code(), BlockCode::Trailer_);
+        assert_eq!(block.extension_code(), None);
+    }
+
+    #[test]
+    fn sub_blocks() {
+        let mut b = Unknown::default();
+        assert_eq!(b.ext_id(), []);
+        assert_eq!(b.sub_blocks().count(), 0);
+        b.add_sub_block(&[0x99]);
+        b.add_sub_block(&[1; 255]);
+        b.add_sub_block(b"two");
+        assert_eq!(b.ext_id(), [0x99]);
+        let sub_blocks: Vec<_> = b.sub_blocks().collect();
+        assert_eq!(sub_blocks, [&[1; 255][..], b"two"]);
+        let mut b = Comment::default();
+        b.add_comment(b"one");
+        b.add_comment(b"two");
+        assert_eq!(b.comments().collect::<Vec<_>>(), [b"one", b"two"]);
+    }
+
+    #[test]
+    fn comment_text() {
+        let mut b = Comment::default();
+        b.add_comment(&[b'x'; 300]);
+        b.add_comment(b"");
+        let sizes: Vec<_> = b.comments().map(|c| c.len()).collect();
+        assert_eq!(sizes, [255, 45]);
+        let text = "gift ✓ ünïcödé ".repeat(50);
+        assert_eq!(text.chars().count(), 750);
+        b.set_text(&text);
+        let chunks: Vec<_> = b.comments().collect();
+        assert!(chunks.len() > 4);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 255);
+            assert!(std::str::from_utf8(chunk).is_ok());
+        }
+        assert_eq!(chunks.concat(), text.as_bytes());
+        b.set_text("");
+        assert_eq!(b.comments().count(), 0);
+    }
+
+    #[test]
+    fn frame_indexed_raster() {
+        let pixels: Vec<Gray8> = (0..12).map(|i| Gray8::new(i % 5)).collect();
+        let raster = Raster::with_pixels(4, 3, pixels);
+        let desc = ImageDesc::default().with_width(4).with_height(3);
+        let frame = Frame::new(None, desc, None, ImageData::from(&raster));
+        let indexed = Raster::<Gray8>::try_from(&frame).unwrap();
+        assert_eq!(indexed.width(), 4);
+        assert_eq!(indexed.height(), 3);
+        assert_eq!(indexed.pixels(), raster.pixels());
+        assert!(frame.palette().is_none());
+        let desc = desc.with_height(4);
+        let frame = Frame::new(None, desc, None, ImageData::from(&raster));
+        match Raster::<Gray8>::try_from(&frame) {
+            Err(Error::IncompleteImageData) => (),
+            _ => panic!("expected IncompleteImageData"),
+        }
+        let mut palette = Palette::new(256);
+        palette.set_entry(SRgb8::new(1, 2, 3));
+        palette.set_entry(SRgb8::new(4, 5, 6));
+        let (_, tbl) = LocalColorTable::from_palette(&palette).unwrap();
+        let frame = Frame::new(None, desc, Some(tbl), ImageData::new(16));
+        assert_eq!(frame.palette().unwrap().colors(), palette.colors());
+    }
+
+    #[test]
+    fn image_data_capacity() {
+        let desc = ImageDesc::default().with_width(4).with_height(3);
+        let mut image_data = ImageData::new(12);
+        image_data.data_mut().reserve_exact(100);
+        image_data.data_mut().extend_from_slice(&[1; 11]);
+        assert!(image_data.data_mut().capacity() > 12);
+        let frame = Frame::new(None, desc, None, image_data.clone());
+        match frame.color_usage() {
+            Err(Error::IncompleteImageData) => (),
+            _ => panic!("expected IncompleteImageData"),
+        }
+        image_data.data_mut().push(2);
+        let frame = Frame::new(None, desc, None, image_data.clone());
+        assert_eq!(frame.color_usage().unwrap().max_index(), Some(2));
+        let raster = Raster::<Gray8>::try_from(&frame).unwrap();
+        assert_eq!(image_data, ImageData::from(&raster));
+    }
+
+    #[test]
+    fn frame_defaults() {
+        let desc = ImageDesc::default()
+            .with_left(2)
+            .with_top(3)
+            .with_width(4)
+            .with_height(5);
+        let frame = Frame::new(None, desc, None, ImageData::new(20));
+        assert_eq!(frame.delay_time_cs(), None);
+        assert_eq!(frame.disposal_method(), DisposalMethod::NoAction);
+        assert_eq!(frame.disposal_method(), DisposalMethod::default());
+        assert_eq!(frame.transparent_color(), None);
+        assert_eq!(frame.region(), Region::new(2, 3, 4, 5));
+        assert!(!frame.interlaced());
+        let mut control = GraphicControl::default();
+        control.set_delay_time_cs(25);
+        control.set_disposal_method(DisposalMethod::Previous);
+        control.set_transparent_color(Some(7));
+        let desc = desc.with_interlaced(true);
+        let frame = Frame::new(Some(control), desc, None, ImageData::new(20));
+        assert_eq!(frame.delay_time_cs(), Some(25));
+        assert_eq!(frame.disposal_method(), DisposalMethod::Previous);
+        assert_eq!(frame.transparent_color(), Some(7));
+        assert!(frame.interlaced());
+    }
+
+    #[test]
+    fn palette_tables() {
+        let mut palette = Palette::new(256);
+        for v in [0x10, 0x20, 0x30, 0x40, 0x50] {
+            palette.set_entry(SRgb8::new(v, v + 1, v + 2));
+        }
+        let (cfg, tbl) = GlobalColorTable::from_palette(&palette).unwrap();
+        assert_eq!(cfg.len(), 8);
+        assert_eq!(tbl.len(), 8);
+        assert_eq!(tbl.color(1), Some(SRgb8::new(0x20, 0x21, 0x22)));
+        assert_eq!(tbl.color(7), Some(SRgb8::default()));
+        assert_eq!(tbl.color(8), None);
+        let pal = Palette::from(&tbl);
+        // padding is merged into one entry
+        assert_eq!(pal.len(), 6);
+        assert_eq!(&pal.colors()[..5], palette.colors());
+        assert_eq!(GlobalColorTable::from_palette(&pal).unwrap().1, tbl);
+        let (cfg, tbl) = LocalColorTable::from_palette(&palette).unwrap();
+        assert_eq!(cfg.len(), 8);
+        assert_eq!(tbl.len(), 8);
+        let colors: Vec<SRgb8> = tbl.iter().collect();
+        assert_eq!(&colors[..5], palette.colors());
+        let pal = Palette::from(&tbl);
+        assert_eq!(LocalColorTable::from_palette(&pal).unwrap().1, tbl);
+    }
+
+    #[test]
+    fn color_table_too_large() {
+        let colors = vec![SRgb8::default(); 300];
+        match GlobalColorTable::from_colors(&colors) {
+            Err(Error::PaletteTooLarge(300)) => (),
+            _ => panic!("expected PaletteTooLarge"),
+        }
+        match LocalColorTable::from_colors(&colors[..257]) {
+            Err(Error::PaletteTooLarge(257)) => (),
+            _ => panic!("expected PaletteTooLarge"),
+        }
+        let (cfg, tbl) = LocalColorTable::from_colors(&colors[..256]).unwrap();
+        assert_eq!(cfg.len(), 256);
+        assert_eq!(tbl.len(), 256);
+    }
+
+    #[test]
+    fn color_table_padding() {
+        let colors = vec![SRgb8::new(1, 2, 3); 256];
+        for len in 1..=256 {
+            let (cfg, tbl) =
+                GlobalColorTable::from_colors(&colors[..len]).unwrap();
+            assert_eq!(tbl.colors().len(), cfg.size_bytes());
+            assert!(tbl.colors()[len * 3..].iter().all(|c| *c == 0));
+        }
+    }
+
+    #[test]
+    fn version_enum() {
+        assert_eq!(Version::from(*b"87a"), Version::Gif87a);
+        assert_eq!(Version::from(*b"89a"), Version::Gif89a);
+        assert_eq!(Version::from(*b"90a"), Version::Other(*b"90a"));
+        assert_eq!(<[u8; 3]>::from(Version::Gif87a), *b"87a");
+        assert_eq!(<[u8; 3]>::from(Version::Other(*b"90a")), *b"90a");
+        assert_eq!(Version::Gif87a.to_string(), "87a");
+        assert_eq!(Version::Gif89a.to_string(), "89a");
+        assert_eq!(Version::Other(*b"9\xFFa").to_string(), "9\u{FFFD}a");
+        let header = Header::with_version_enum(Version::Gif87a);
+        assert_eq!(header.version(), *b"87a");
+        assert_eq!(Header::default().version_enum(), Version::Gif89a);
+    }
+
     #[test]
     fn color_table_len() {
         let t = ColorTableConfig::new(
@@ -1265,6 +2507,91 @@ mod test {
         assert_eq!(t.len_bits(), 0);
     }
 
+    #[test]
+    fn color_resolution() {
+        let tbl_cfg = ColorTableConfig::new(
+            ColorTableExistence::Present,
+            ColorTableOrdering::Sorted,
+            4,
+        );
+        for bits in 1..=8 {
+            let desc = LogicalScreenDesc::default()
+                .with_color_table_config(tbl_cfg)
+                .with_color_resolution(bits);
+            assert_eq!(desc.flags(), 0b1000_1001 | ((bits - 1) << 4));
+            assert_eq!(desc.color_resolution_bits(), bits);
+            assert_eq!(desc.color_resolution(), 1 << bits);
+            let desc = LogicalScreenDesc::default().with_flags(desc.flags());
+            assert_eq!(desc.color_resolution_bits(), bits);
+            let desc = desc.with_color_table_config(tbl_cfg);
+            assert_eq!(desc.color_resolution_bits(), bits.max(2));
+            assert_eq!(desc.color_table_config(), tbl_cfg);
+        }
+        let desc = LogicalScreenDesc::default().with_color_resolution(9);
+        assert_eq!(desc.color_resolution_bits(), 8);
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        let desc = LogicalScreenDesc::default();
+        assert_eq!(desc.aspect_ratio(), None);
+        let desc = desc.with_aspect_ratio(1.0);
+        assert_eq!(desc.pixel_aspect_ratio(), 49);
+        assert_eq!(desc.aspect_ratio(), Some(1.0));
+        let desc = desc.with_aspect_ratio(0.1);
+        assert_eq!(desc.pixel_aspect_ratio(), 1);
+        assert_eq!(desc.aspect_ratio(), Some(0.25));
+        let desc = desc.with_aspect_ratio(4.0);
+        assert_eq!(desc.pixel_aspect_ratio(), 241);
+        let desc = desc.with_aspect_ratio(100.0);
+        assert_eq!(desc.pixel_aspect_ratio(), 255);
+        assert_eq!(desc.aspect_ratio(), Some(4.21875));
+        for ratio in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let desc = desc.with_aspect_ratio(ratio);
+            assert_eq!(desc.aspect_ratio(), None);
+        }
+        for n in 1..=255 {
+            let desc = LogicalScreenDesc::default().with_pixel_aspect_ratio(n);
+            let ratio = desc.aspect_ratio().unwrap();
+            let desc = desc.with_aspect_ratio(ratio);
+            assert_eq!(desc.pixel_aspect_ratio(), n);
+        }
+    }
+
+    #[test]
+    fn color_table_try_new() {
+        use ColorTableExistence::Present;
+        use ColorTableOrdering::NotSorted;
+        match ColorTableConfig::try_new(Present, NotSorted, 0) {
+            Err(Error::InvalidColorTableLen(0)) => (),
+            _ => panic!("expected InvalidColorTableLen"),
+        }
+        match ColorTableConfig::try_new(Present, NotSorted, 257) {
+            Err(Error::InvalidColorTableLen(257)) => (),
+            _ => panic!("expected InvalidColorTableLen"),
+        }
+        let t = ColorTableConfig::try_new(Present, NotSorted, 5).unwrap();
+        assert_eq!(t.len(), 8);
+        let t = ColorTableConfig::try_new(Present, NotSorted, 256).unwrap();
+        assert_eq!(t.len(), 256);
+    }
+
+    #[test]
+    fn color_table_try_with_colors() {
+        match GlobalColorTable::try_with_colors(&[0; 7]) {
+            Err(Error::InvalidColorTableSize(7)) => (),
+            _ => panic!("expected InvalidColorTableSize"),
+        }
+        match LocalColorTable::try_with_colors(&[0; 257 * 3]) {
+            Err(Error::InvalidColorTableLen(257)) => (),
+            _ => panic!("expected InvalidColorTableLen"),
+        }
+        let tbl = LocalColorTable::try_with_colors(&[0; 6]).unwrap();
+        assert_eq!(tbl.len(), 2);
+        let tbl = GlobalColorTable::try_with_colors(&[]).unwrap();
+        assert_eq!(tbl.len(), 0);
+    }
+
     #[test]
     fn graphic_control() {
         let mut g = GraphicControl::default();
@@ -1297,5 +2624,99 @@ mod test {
         assert_eq!(b.loop_count(), Some(0));
         let b = Application::with_loop_count(4);
         assert_eq!(b.loop_count(), Some(4));
+        let b = Application::new(*b"NETSCAPE", *b"2.0");
+        assert_eq!(b.loop_count(), None);
+    }
+
+    #[test]
+    fn delay() {
+        let mut g = GraphicControl::default();
+        assert_eq!(g.delay(), Duration::ZERO);
+        g.set_delay(Duration::from_millis(4));
+        assert_eq!(g.delay_time_cs(), 1);
+        g.set_delay(Duration::from_millis(9));
+        assert_eq!(g.delay_time_cs(), 1);
+        g.set_delay(Duration::from_millis(10));
+        assert_eq!(g.delay_time_cs(), 1);
+        g.set_delay(Duration::from_millis(14));
+        assert_eq!(g.delay_time_cs(), 1);
+        g.set_delay(Duration::from_millis(15));
+        assert_eq!(g.delay_time_cs(), 2);
+        g.set_delay(Duration::from_millis(655_340));
+        assert_eq!(g.delay_time_cs(), u16::MAX - 1);
+        g.set_delay(Duration::from_millis(655_360));
+        assert_eq!(g.delay_time_cs(), u16::MAX);
+        assert_eq!(g.delay(), Duration::from_millis(655_350));
+        g.set_delay(Duration::ZERO);
+        assert_eq!(g.delay_time_cs(), 0);
+    }
+
+    #[test]
+    fn buffering() {
+        let b = Application::with_loop_count(5);
+        assert_eq!(b.buffering(), None);
+        let b = b.with_buffering(1024);
+        assert_eq!(b.loop_count(), Some(5));
+        assert_eq!(b.buffering(), Some(1024));
+        let mut b = Application::new(*b"NETSCAPE", *b"2.0");
+        b.add_data(&[2, 0x00, 0x10, 0x00, 0x00]);
+        b.add_data(&[1, 0, 3]);
+        assert_eq!(b.app_data().count(), 3);
+        assert_eq!(b.loop_count(), Some(3));
+        assert_eq!(b.buffering(), Some(4096));
+        let mut b = Application::new(*b"GIFTTEST", *b"1.0");
+        b.add_data(&[1, 0, 3]);
+        assert_eq!(b.loop_count(), None);
+    }
+
+    #[test]
+    fn app_data() {
+        let mut b = Application::new(*b"GIFTTEST", *b"1.0");
+        b.add_data(&[7; 300]);
+        let data: Vec<&[u8]> = b.app_data().collect();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[0], b"GIFTTEST1.0");
+        assert_eq!(data[1], [7; 255]);
+        assert_eq!(data[2], [7; 45]);
+    }
+
+    #[test]
+    fn plain_text() {
+        let mut b = PlainText::default()
+            .with_grid_position(1, 300)
+            .with_grid_size(64, 16)
+            .with_cell_size(8, 16)
+            .with_fg_color_idx(3)
+            .with_bg_color_idx(4);
+        b.add_text(&[b'A'; 260]);
+        assert_eq!(b.grid_left(), 1);
+        assert_eq!(b.grid_top(), 300);
+        assert_eq!(b.grid_width(), 64);
+        assert_eq!(b.grid_height(), 16);
+        assert_eq!(b.cell_width(), 8);
+        assert_eq!(b.cell_height(), 16);
+        assert_eq!(b.fg_color_idx(), 3);
+        assert_eq!(b.bg_color_idx(), 4);
+        assert_eq!(b.sub_blocks()[0], [1, 0, 44, 1, 64, 0, 16, 0, 8, 16, 3, 4]);
+        assert_eq!(b.text().len(), 2);
+        assert_eq!(b.text()[1], [b'A'; 5]);
+        assert!(PlainText::default().text().is_empty());
+    }
+
+    #[test]
+    fn interlace() {
+        let desc = ImageDesc::default().with_width(2).with_height(10);
+        let mut data = ImageData::new(20);
+        for row in 0..10 {
+            data.data_mut().extend_from_slice(&[row, row]);
+        }
+        let inter = data.interlace(&desc);
+        #[rustfmt::skip]
+        assert_eq!(inter.data(), &[
+            0, 0, 8, 8, 4, 4, 2, 2, 6, 6, 1, 1, 3, 3, 5, 5, 7, 7, 9, 9,
+        ]);
+        assert_eq!(inter.deinterlace(&desc), data);
+        let desc = ImageDesc::default().with_width(3).with_height(10);
+        assert_eq!(data.interlace(&desc), data);
     }
 }
@@ -4,12 +4,20 @@
 //
 //! GIF file decoding
 use crate::block::*;
+use crate::diff::DirtyRegions;
 use crate::error::{Error, Result};
 use crate::lzw::Decompressor;
 use crate::private::Step;
 use pix::{rgb::SRgba8, Raster, Region};
 use std::cmp::Ordering;
-use std::io::{ErrorKind, Read};
+use std::io::{self, ErrorKind, Read};
+use std::time::Duration;
+
+/// Maximum bytes to check for the start of a block
+const MAX_BLOCK_START_LEN: usize = 1 << 16;
+
+/// Bytes to read ahead at a time when checking for the start of a block
+const READ_AHEAD_LEN: usize = 256;
 
 /// An Iterator for [Block]s within a GIF file.
 ///
@@ -44,14 +52,36 @@ use std::io::{ErrorKind, Read};
 pub struct Blocks<R: Read> {
     /// Reader for blocks
     reader: R,
+    /// Bytes read ahead, before the rest of the reader
+    lookahead: Vec<u8>,
     /// Maximum image size in bytes
     max_image_sz: Option<usize>,
+    /// Maximum screen size in bytes
+    max_screen_sz: Option<usize>,
+    /// Maximum extension sub-block data size in bytes
+    max_extension_sz: Option<usize>,
+    /// Keep image data compressed
+    raw_image_data: bool,
+    /// Discard image data without decompressing
+    skip_image_data: bool,
+    /// Report recoverable errors instead of aborting
+    lenient: bool,
+    /// Check for data after the trailer
+    deny_trailing_data: bool,
     /// Expected next block and size
     expected_next: Option<(BlockCode, usize)>,
     /// Size of image data
     image_sz: usize,
+    /// Size of extension sub-block data in current block
+    extension_sz: usize,
+    /// Compressed statistics of current image data
+    image_stats: FrameStats,
     /// LZW decompressor
     decompressor: Option<Decompressor>,
+    /// Image data of a block which failed to decode
+    partial_image_data: Option<ImageData>,
+    /// Problems recovered from in the current block
+    recovered: Vec<Recovered>,
     /// Flag when done
     done: bool,
 }
@@ -76,26 +106,83 @@ impl<R: Read> Iterator for Blocks<R> {
 
 impl<R: Read> Blocks<R> {
     /// Create a new block iterator
-    pub(crate) fn new(reader: R, max_image_sz: Option<usize>) -> Self {
+    pub(crate) fn new(
+        reader: R,
+        max_image_sz: Option<usize>,
+        max_screen_sz: Option<usize>,
+        max_extension_sz: Option<usize>,
+        raw_image_data: bool,
+        lenient: bool,
+        deny_trailing_data: bool,
+    ) -> Self {
         use self::BlockCode::Header_;
         Blocks {
             reader,
+            lookahead: Vec::new(),
             max_image_sz,
+            max_screen_sz,
+            max_extension_sz,
+            raw_image_data,
+            skip_image_data: false,
+            lenient,
+            deny_trailing_data,
             expected_next: Some((Header_, Header_.size())),
             image_sz: 0,
+            extension_sz: 0,
+            image_stats: FrameStats::default(),
             done: false,
             decompressor: None,
+            partial_image_data: None,
+            recovered: Vec::new(),
         }
     }
 
+    /// Discard image data without decompressing.
+    ///
+    /// `ImageData` blocks are returned empty, in raw form.
+    pub(crate) fn with_skip_image_data(mut self) -> Self {
+        self.skip_image_data = true;
+        self
+    }
+
+    /// Check if a specific block is expected next (not trailer)
+    pub(crate) fn expects_block(&self) -> bool {
+        self.expected_next.is_some()
+    }
+
+    /// Take image data of a block which failed to decode
+    pub(crate) fn take_partial_image_data(&mut self) -> Option<ImageData> {
+        self.partial_image_data.take()
+    }
+
+    /// Take problems recovered from in the most recent block
+    pub(crate) fn take_recovered(&mut self) -> Vec<Recovered> {
+        std::mem::take(&mut self.recovered)
+    }
+
     /// Decode the next block (including all sub-blocks).
     fn next_block(&mut self) -> Result<Block> {
+        self.partial_image_data = None;
+        self.recovered.clear();
         let mut block = self.decode_block()?;
+        self.extension_sz = 0;
+        match self.decode_sub_blocks(&mut block) {
+            Ok(()) => Ok(block),
+            Err(e) => {
+                if let Block::ImageData(b) = block {
+                    self.partial_image_data = Some(b);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Decode all sub-blocks of a block
+    fn decode_sub_blocks(&mut self, block: &mut Block) -> Result<()> {
         if block.has_sub_blocks() {
-            while self.decode_sub_block(&mut block)? {}
+            while self.decode_sub_block(block)? {}
         }
-        self.check_block_end(&mut block)?;
-        Ok(block)
+        self.check_block_end(block)
     }
 
     /// Decode one block
@@ -104,7 +191,7 @@ impl<R: Read> Blocks<R> {
             Some((bc, sz)) => self.parse_expected(bc, sz)?,
             None => self.parse_block()?,
         };
-        self.expected_next = self.expected_next(&block);
+        self.expected_next = block.expected_next();
         Ok(block)
     }
 
@@ -126,10 +213,16 @@ impl<R: Read> Blocks<R> {
         let mut buf = vec![0; BlockCode::Header_.size()];
         self.fill_buffer(&mut buf)?;
         if &buf[..3] == b"GIF" {
-            let version = [buf[3], buf[4], buf[5]];
-            match &version {
-                b"87a" | b"89a" => Ok(Header::with_version(version).into()),
-                _ => Err(Error::UnsupportedVersion(version)),
+            match Version::from([buf[3], buf[4], buf[5]]) {
+                v @ Version::Other(_) if !self.lenient => {
+                    Err(Error::UnsupportedVersion(v))
+                }
+                v => {
+                    if let Version::Other(_) = v {
+                        warn!("Unsupported version: {v}, decoding as 89a");
+                    }
+                    Ok(Header::with_version_enum(v).into())
+                }
             }
         } else {
             Err(Error::MalformedHeader)
@@ -145,6 +238,15 @@ impl<R: Read> Blocks<R> {
         let flags = buf[4];
         let bg_color = buf[5];
         let aspect = buf[6];
+        check_color_table_flags("Global", flags);
+        if let Some(sz) = self.max_screen_sz {
+            // size of raster for compositing steps
+            let screen_sz = (usize::from(width) * usize::from(height))
+                .saturating_mul(std::mem::size_of::<SRgba8>());
+            if screen_sz > sz {
+                return Err(Error::TooLargeImage);
+            }
+        }
         Ok(LogicalScreenDesc::default()
             .with_screen_width(width)
             .with_screen_height(height)
@@ -156,16 +258,35 @@ impl<R: Read> Blocks<R> {
 
     /// Parse a Global Color Table block
     fn parse_global_color_table(&mut self, sz: usize) -> Result<Block> {
-        let mut buf = vec![0; sz];
-        self.fill_buffer(&mut buf)?;
-        Ok(GlobalColorTable::with_colors(&buf).into())
+        let buf = self.read_color_table(sz)?;
+        Ok(GlobalColorTable::try_with_colors(&buf)?.into())
     }
 
     /// Parse a Local Color Table block
     fn parse_local_color_table(&mut self, sz: usize) -> Result<Block> {
+        let buf = self.read_color_table(sz)?;
+        Ok(LocalColorTable::try_with_colors(&buf)?.into())
+    }
+
+    /// Read color table data, checking for truncation
+    fn read_color_table(&mut self, sz: usize) -> Result<Vec<u8>> {
         let mut buf = vec![0; sz];
-        self.fill_buffer(&mut buf)?;
-        Ok(LocalColorTable::with_colors(&buf).into())
+        let len = self.fill_partial(&mut buf)?;
+        if len < sz {
+            if !self.lenient {
+                return Err(Error::TruncatedColorTable {
+                    expected: sz,
+                    actual: len,
+                });
+            }
+            // remaining colors are black
+            warn!("Color table truncated: {len} of {sz} bytes");
+            self.recovered.push(Recovered::TruncatedColorTable {
+                expected: sz,
+                actual: len,
+            });
+        }
+        Ok(buf)
     }
 
     /// Parse an Image Data block
@@ -173,11 +294,19 @@ impl<R: Read> Blocks<R> {
         let mut buf = vec![0; BlockCode::ImageData_.size()];
         self.fill_buffer(&mut buf)?;
         let mut min_code_bits = buf[0];
+        self.image_stats = FrameStats::default();
+        if self.raw_image_data || self.skip_image_data {
+            return Ok(ImageData::new_raw(min_code_bits).into());
+        }
         if !(2..=8).contains(&min_code_bits) {
+            if !self.lenient {
+                return Err(Error::InvalidLzwCodeSize(min_code_bits));
+            }
             warn!("Invalid LZW minimum code size: {min_code_bits}");
-            min_code_bits = 2.max(min_code_bits).min(8);
+            min_code_bits = min_code_bits.clamp(2, 8);
         }
-        self.decompressor = Some(Decompressor::new(min_code_bits));
+        let dec = Decompressor::new(min_code_bits).with_max_len(self.image_sz);
+        self.decompressor = Some(dec);
         Ok(ImageData::new(self.image_sz).into())
     }
 
@@ -189,11 +318,30 @@ impl<R: Read> Blocks<R> {
         match BlockCode::from_u8(buf[0]) {
             Some(Extension_) => self.parse_extension(),
             Some(ImageDesc_) => self.parse_image_desc(),
-            Some(Trailer_) => Ok(Trailer::default().into()),
+            Some(Trailer_) => {
+                if self.deny_trailing_data {
+                    self.check_trailing_data()?;
+                }
+                Ok(Trailer::default().into())
+            }
             _ => Err(Error::InvalidBlockCode),
         }
     }
 
+    /// Check for data after the trailer
+    fn check_trailing_data(&mut self) -> Result<()> {
+        let bytes = io::copy(&mut self.reader, &mut io::sink())?;
+        let bytes = bytes + self.lookahead.drain(..).count() as u64;
+        if bytes > 0 {
+            let bytes = usize::try_from(bytes)?;
+            if !self.lenient {
+                return Err(Error::TrailingData { bytes });
+            }
+            warn!("Trailing data after trailer: {bytes} bytes");
+        }
+        Ok(())
+    }
+
     /// Parse an extension block
     fn parse_extension(&mut self) -> Result<Block> {
         use crate::block::ExtensionCode::*;
@@ -218,6 +366,7 @@ impl<R: Read> Blocks<R> {
         let width = u16::from(buf[5]) << 8 | u16::from(buf[4]);
         let height = u16::from(buf[7]) << 8 | u16::from(buf[6]);
         let flags = buf[8];
+        check_color_table_flags("Local", flags);
         let b = ImageDesc::default()
             .with_left(left)
             .with_top(top)
@@ -235,53 +384,47 @@ impl<R: Read> Blocks<R> {
 
     /// Fill a buffer from reader
     fn fill_buffer(&mut self, buffer: &mut [u8]) -> Result<()> {
-        let mut len = 0;
+        if self.fill_partial(buffer)? < buffer.len() {
+            return Err(Error::UnexpectedEndOfFile);
+        }
+        Ok(())
+    }
+
+    /// Fill a buffer from reader, stopping at end of file.
+    ///
+    /// Returns the number of bytes read.
+    fn fill_partial(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let mut len = self.lookahead.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&self.lookahead[..len]);
+        self.lookahead.drain(..len);
         while len < buffer.len() {
             match self.reader.read(&mut buffer[len..]) {
-                Ok(0) => return Err(Error::UnexpectedEndOfFile),
+                Ok(0) => break,
                 Ok(n) => len += n,
                 Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
                 Err(e) => return Err(e.into()),
             }
         }
-        Ok(())
-    }
-
-    /// Get the expected next block code and size
-    fn expected_next(&mut self, block: &Block) -> Option<(BlockCode, usize)> {
-        use crate::block::BlockCode::*;
-        match block {
-            Block::Header(_) => {
-                Some((LogicalScreenDesc_, LogicalScreenDesc_.size()))
-            }
-            Block::LogicalScreenDesc(b) => {
-                let sz = b.color_table_config().size_bytes();
-                if sz > 0 {
-                    Some((GlobalColorTable_, sz))
-                } else {
-                    None
-                }
-            }
-            Block::ImageDesc(b) => {
-                let sz = b.color_table_config().size_bytes();
-                if sz > 0 {
-                    Some((LocalColorTable_, sz))
-                } else {
-                    Some((ImageData_, ImageData_.size()))
-                }
-            }
-            Block::LocalColorTable(_) => Some((ImageData_, ImageData_.size())),
-            Block::Trailer(_) => Some((Header_, Header_.size())),
-            _ => None,
-        }
+        Ok(len)
     }
 
     /// Check end of block (after sub-blocks)
     fn check_block_end(&mut self, block: &mut Block) -> Result<()> {
         if let Block::ImageData(ref mut b) = block {
+            if b.is_raw() {
+                return Ok(());
+            }
             match self.decompressor.take() {
-                Some(_decompressor) => b.finish(self.image_sz)?,
-                _ => panic!("Invalid state in check_block_end!"),
+                Some(dec) => {
+                    if dec.is_truncated() {
+                        if !self.lenient {
+                            return Err(Error::InvalidLzwData);
+                        }
+                        warn!("Extra image data truncated");
+                    }
+                    b.finish(self.image_sz)?
+                }
+                None => return Err(Error::InvalidBlockSequence),
             }
         }
         Ok(())
@@ -292,15 +435,86 @@ impl<R: Read> Blocks<R> {
         let mut buf = [0; 256];
         self.fill_buffer(&mut buf[..1])?;
         let len = buf[0] as usize;
+        if self.lenient
+            && !matches!(block, Block::ImageData(_))
+            && self.is_missing_terminator(buf[0])?
+        {
+            warn!("Extension block terminator missing");
+            self.recovered.push(Recovered::MissingTerminator);
+            self.lookahead.insert(0, buf[0]);
+            return Ok(false);
+        }
         if len > 0 {
             let blk_sz = len + 1;
             self.fill_buffer(&mut buf[1..blk_sz])?;
             debug!("sub-block: {:?} {:?}", block, blk_sz);
-            self.parse_sub_block(block, &buf[1..blk_sz])?;
+            if let Block::ImageData(_) = block {
+                self.image_stats.compressed_len += len;
+                self.image_stats.sub_block_count += 1;
+            }
+            if self.check_extension_sz(block, len)? {
+                self.parse_sub_block(block, &buf[1..blk_sz])?;
+            }
         }
         Ok(len > 0)
     }
 
+    /// Check if a sub-block size is the start of the next block instead.
+    ///
+    /// Bytes are read ahead to check whether a block starts there.
+    fn is_missing_terminator(&mut self, size: u8) -> Result<bool> {
+        if !matches!(size, 0x21 | 0x2C | 0x3B) {
+            return Ok(false);
+        }
+        let mut buf = vec![size];
+        let found = loop {
+            let start = buf.len();
+            buf.resize(start + READ_AHEAD_LEN, 0);
+            let len = self.fill_partial(&mut buf[start..])?;
+            buf.truncate(start + len);
+            if let Some(found) = is_block_start(&buf, len < READ_AHEAD_LEN) {
+                break found;
+            }
+        };
+        self.lookahead.splice(..0, buf[1..].iter().copied());
+        Ok(found)
+    }
+
+    /// Check extension sub-block data size.
+    ///
+    /// Returns `false` if the sub-block should be dropped.
+    fn check_extension_sz(
+        &mut self,
+        block: &Block,
+        len: usize,
+    ) -> Result<bool> {
+        match block {
+            Block::PlainText(_)
+            | Block::Comment(_)
+            | Block::Application(_)
+            | Block::Unknown(_) => (),
+            _ => return Ok(true),
+        }
+        let max_sz = match self.max_extension_sz {
+            Some(sz) => sz,
+            None => return Ok(true),
+        };
+        let total = self.extension_sz + len;
+        if total <= max_sz {
+            self.extension_sz = total;
+            return Ok(true);
+        }
+        if !self.lenient {
+            return Err(Error::TooLargeExtension);
+        }
+        if self.extension_sz <= max_sz {
+            warn!("Extension larger than {max_sz} bytes truncated");
+            // only warn once per block
+            self.extension_sz = max_sz + 1;
+        }
+        Ok(false)
+    }
+
     /// Parse a sub-block in the buffer
     fn parse_sub_block(
         &mut self,
@@ -314,13 +528,128 @@ impl<R: Read> Blocks<R> {
             Comment(b) => b.parse_sub_block(bytes),
             Application(b) => b.parse_sub_block(bytes),
             Unknown(b) => b.parse_sub_block(bytes),
+            ImageData(_) if self.skip_image_data => (),
             ImageData(b) => b.parse_sub_block(bytes, &mut self.decompressor)?,
-            _ => panic!("Invalid state in parse_sub_block!"),
+            _ => return Err(Error::InvalidBlockSequence),
         }
         Ok(())
     }
 }
 
+/// Block header checked by [block_header]
+enum BlockHeader {
+    /// More bytes needed to check
+    Incomplete,
+    /// Not a block header
+    Invalid,
+    /// Trailer at end of file
+    Trailer,
+    /// Block header, with sub-blocks starting at a position
+    SubBlocks(usize),
+}
+
+/// Check if bytes look like a block header
+fn block_header(bytes: &[u8], eof: bool) -> BlockHeader {
+    match bytes {
+        [0x3B] if eof => BlockHeader::Trailer,
+        [0x3B] => BlockHeader::Incomplete,
+        [0x21, 0xF9, 0x04, ..]
+        | [0x21, 0xFE, _, ..]
+        | [0x21, 0xFF, 0x0B, ..]
+        | [0x21, 0x01, 0x0C, ..] => BlockHeader::SubBlocks(2),
+        [0x21] | [0x21, 0xF9 | 0xFE | 0xFF | 0x01] if !eof => {
+            BlockHeader::Incomplete
+        }
+        [0x2C, ..] => image_header(bytes, eof),
+        _ => BlockHeader::Invalid,
+    }
+}
+
+/// Check if bytes look like an image descriptor header
+fn image_header(bytes: &[u8], eof: bool) -> BlockHeader {
+    let incomplete = if eof {
+        BlockHeader::Invalid
+    } else {
+        BlockHeader::Incomplete
+    };
+    let desc = match bytes.get(..10) {
+        Some(desc) => desc,
+        None => return incomplete,
+    };
+    let width = u16::from_le_bytes([desc[5], desc[6]]);
+    let height = u16::from_le_bytes([desc[7], desc[8]]);
+    let flags = desc[9];
+    if width == 0 || height == 0 {
+        return BlockHeader::Invalid;
+    }
+    let tbl_sz = if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    };
+    // LZW minimum code size follows any local color table
+    match bytes.get(10 + tbl_sz) {
+        Some(2..=8) => BlockHeader::SubBlocks(11 + tbl_sz),
+        Some(_) => BlockHeader::Invalid,
+        None => incomplete,
+    }
+}
+
+/// Check if bytes look like the start of a block.
+///
+/// Used to detect an extension block with a missing terminator, where the
+/// next block introducer would be read as a sub-block size.  The block's
+/// sub-blocks are walked to a terminator followed by another block, or to
+/// the start of another block with a missing terminator.
+///
+/// Returns `None` if more bytes are needed before `eof`.
+fn is_block_start(bytes: &[u8], eof: bool) -> Option<bool> {
+    let mut pos = match block_header(bytes, eof) {
+        BlockHeader::Incomplete => return None,
+        BlockHeader::Invalid => return Some(false),
+        BlockHeader::Trailer => return Some(true),
+        BlockHeader::SubBlocks(pos) => pos,
+    };
+    // a sub-block chain this long is very unlikely to be a coincidence
+    while pos < MAX_BLOCK_START_LEN {
+        let size = match bytes.get(pos) {
+            Some(size) => *size,
+            None if eof => return Some(false),
+            None => return None,
+        };
+        if size == 0 {
+            return match bytes.get(pos + 1) {
+                Some(next) => Some(matches!(next, 0x21 | 0x2C | 0x3B)),
+                None if eof => Some(true),
+                None => None,
+            };
+        }
+        match block_header(&bytes[pos..], eof) {
+            BlockHeader::Incomplete => return None,
+            BlockHeader::Invalid => pos += usize::from(size) + 1,
+            BlockHeader::Trailer | BlockHeader::SubBlocks(_) => {
+                return Some(true)
+            }
+        }
+    }
+    Some(true)
+}
+
+/// Check background color index against the global color table
+fn check_background_color(preamble: &Preamble, tbl: &GlobalColorTable) {
+    let idx = preamble.logical_screen_desc.background_color_idx();
+    if usize::from(idx) >= tbl.len() {
+        warn!("Background color index {idx} not in global color table");
+    }
+}
+
+/// Check for color table size without the present flag
+fn check_color_table_flags(table: &str, flags: u8) {
+    if flags & 0x80 == 0 && flags & 0x07 != 0 {
+        warn!("{table} color table size set, but not present");
+    }
+}
+
 impl ImageData {
     /// Parse an Image Data block
     fn parse_sub_block(
@@ -328,20 +657,20 @@ impl ImageData {
         bytes: &[u8],
         decompressor: &mut Option<Decompressor>,
     ) -> Result<()> {
-        if let Some(ref mut dec) = decompressor {
-            dec.decompress(bytes, self.data_mut())?;
+        if self.is_raw() {
+            // keep sub-block framing for passthrough
+            self.data_mut().push(bytes.len() as u8);
+            self.data_mut().extend_from_slice(bytes);
             return Ok(());
         }
-        panic!("Invalid state in decode_image_data!");
+        match decompressor {
+            Some(dec) => dec.decompress(bytes, self.data_mut()),
+            None => Err(Error::InvalidBlockSequence),
+        }
     }
 
     /// Finish LZW decompression
     fn finish(&mut self, image_sz: usize) -> Result<()> {
-        if self.data().len() > image_sz {
-            warn!("Extra image data: {:?}", &self.data()[image_sz..]);
-            self.data_mut().truncate(image_sz);
-            self.data_mut().shrink_to_fit();
-        }
         if self.data().len() == image_sz {
             Ok(())
         } else {
@@ -382,7 +711,7 @@ impl Comment {
 impl Application {
     /// Parse an Application extension sub-block
     fn parse_sub_block(&mut self, bytes: &[u8]) {
-        self.add_app_data(bytes);
+        self.add_data(bytes);
     }
 }
 
@@ -400,6 +729,510 @@ impl Unknown {
     }
 }
 
+/// An Iterator for comments within a GIF file.
+///
+/// Build with Decoder.[comments].  Each item contains the text of one
+/// [Comment] block, with invalid UTF-8 replaced.  By default, scanning stops
+/// at the first image descriptor.
+///
+/// [Comment]: ../block/struct.Comment.html
+/// [comments]: ../struct.Decoder.html#method.comments
+pub struct Comments<R: Read> {
+    /// Block decoder
+    blocks: Blocks<R>,
+    /// Include comments between frames
+    include_interleaved: bool,
+    /// Flag when done
+    done: bool,
+}
+
+impl<R: Read> Iterator for Comments<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.blocks.next() {
+                Some(Ok(Block::Comment(b))) => {
+                    let text = b.comments().collect::<Vec<_>>().concat();
+                    return Some(Ok(String::from_utf8_lossy(&text).into()));
+                }
+                Some(Ok(Block::ImageDesc(_))) if !self.include_interleaved => {
+                    self.done = true;
+                }
+                Some(Ok(_)) => (),
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => self.done = true,
+            }
+        }
+        None
+    }
+}
+
+impl<R: Read> Comments<R> {
+    /// Create a new comment iterator
+    pub(crate) fn new(blocks: Blocks<R>) -> Self {
+        Comments {
+            blocks: blocks.with_skip_image_data(),
+            include_interleaved: false,
+            done: false,
+        }
+    }
+
+    /// Include comments between frames, scanning the whole file.
+    ///
+    /// Image data is skipped without decompressing.
+    pub fn include_interleaved(mut self, include: bool) -> Self {
+        self.include_interleaved = include;
+        self
+    }
+}
+
+/// Problem recovered from when decoding leniently
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Recovered {
+    /// Color table truncated by end of file (sizes in bytes)
+    TruncatedColorTable { expected: usize, actual: usize },
+    /// Extension block terminator missing
+    MissingTerminator,
+}
+
+/// Compressed size statistics of a frame, as read from the stream.
+///
+/// Get with Frames.[frame_stats].
+///
+/// [frame_stats]: struct.Frames.html#method.frame_stats
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameStats {
+    /// Length of LZW-compressed image data, in bytes (not including
+    /// sub-block size bytes)
+    pub compressed_len: usize,
+    /// Number of image data sub-blocks
+    pub sub_block_count: usize,
+}
+
+/// A frame which was only partly decoded before an error.
+///
+/// Get with Frames.[take_partial].
+///
+/// [take_partial]: struct.Frames.html#method.take_partial
+#[derive(Debug)]
+pub struct PartialFrame {
+    /// Graphic control for the frame
+    pub graphic_control_ext: Option<GraphicControl>,
+    /// Image descriptor for the frame
+    pub image_desc: ImageDesc,
+    /// Local color table for the frame
+    pub local_color_table: Option<LocalColorTable>,
+    /// Image data decoded before the error.
+    ///
+    /// This is shorter than the image size (possibly empty).  Interlaced
+    /// rows are not reordered.
+    pub image_data: ImageData,
+}
+
+impl PartialFrame {
+    /// Get the number of complete rows decoded
+    pub fn rows(&self) -> usize {
+        match usize::from(self.image_desc.width()) {
+            0 => 0,
+            width => self.image_data.data().len() / width,
+        }
+    }
+}
+
+/// Summary of a GIF file.
+///
+/// Build with Decoder.[metadata].
+///
+/// [metadata]: ../struct.Decoder.html#method.metadata
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    /// GIF version
+    pub version: Version,
+    /// Logical screen width
+    pub screen_width: u16,
+    /// Logical screen height
+    pub screen_height: u16,
+    /// Number of entries in global color table (0 if none)
+    pub global_palette_len: usize,
+    /// Background color index
+    pub background_color_idx: u8,
+    /// Animation loop count (0 means forever)
+    pub loop_count: Option<u16>,
+    /// Number of frames
+    pub frame_count: usize,
+    /// Total delay time of all frames
+    pub duration: Duration,
+    /// Any frame is interlaced
+    pub interlaced: bool,
+    /// Number of comment blocks
+    pub comment_count: usize,
+}
+
+impl Metadata {
+    /// Read metadata from all blocks
+    pub(crate) fn from_blocks<R: Read>(blocks: Blocks<R>) -> Result<Self> {
+        let mut meta = Metadata::default();
+        let mut delay_cs = None;
+        for block in blocks.with_skip_image_data() {
+            match block? {
+                Block::Header(b) => meta.version = b.version_enum(),
+                Block::LogicalScreenDesc(b) => {
+                    meta.screen_width = b.screen_width();
+                    meta.screen_height = b.screen_height();
+                    meta.background_color_idx = b.background_color_idx();
+                }
+                Block::GlobalColorTable(b) => {
+                    meta.global_palette_len = b.len();
+                }
+                Block::Application(b) if meta.loop_count.is_none() => {
+                    meta.loop_count = b.loop_count();
+                }
+                Block::Comment(_) => meta.comment_count += 1,
+                Block::GraphicControl(b) => {
+                    delay_cs = Some(b.delay_time_cs());
+                }
+                Block::ImageDesc(b) => {
+                    meta.frame_count += 1;
+                    meta.interlaced |= b.interlaced();
+                    if let Some(cs) = delay_cs.take() {
+                        let ms = u64::from(cs) * 10;
+                        meta.duration += Duration::from_millis(ms);
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(meta)
+    }
+}
+
+/// Sequence of extension sub-blocks borrowed from GIF data.
+///
+/// Each sub-block is prefixed by its length, as in a GIF file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SubBlockRefs<'a> {
+    /// Length-prefixed sub-block data (without terminator)
+    data: &'a [u8],
+}
+
+impl<'a> SubBlockRefs<'a> {
+    /// Get the sub-block data, including length prefixes
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Get an iterator of sub-blocks
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        let mut data = self.data;
+        std::iter::from_fn(move || {
+            let (len, rest) = data.split_first()?;
+            let (b, rest) = rest.split_at(usize::from(*len));
+            data = rest;
+            Some(b)
+        })
+    }
+}
+
+/// A [Block] borrowing its data from a GIF in memory.
+///
+/// Color tables and extension sub-blocks are not copied.  Image data is
+/// still decompressed into an owned buffer.
+///
+/// [Block]: ../block/enum.Block.html
+#[derive(Debug, Eq, PartialEq)]
+pub enum BlockRef<'a> {
+    /// Header block
+    Header(Header),
+    /// Logical screen descriptor block
+    LogicalScreenDesc(LogicalScreenDesc),
+    /// Global color table block
+    GlobalColorTable(&'a [u8]),
+    /// Plain text extension block
+    PlainText(SubBlockRefs<'a>),
+    /// Graphics control extension block
+    GraphicControl(GraphicControl),
+    /// Comment extension block
+    Comment(SubBlockRefs<'a>),
+    /// Application extension block
+    Application(SubBlockRefs<'a>),
+    /// Unknown extension block (extension ID and sub-blocks)
+    Unknown(u8, SubBlockRefs<'a>),
+    /// Image descriptor block
+    ImageDesc(ImageDesc),
+    /// Local color table block
+    LocalColorTable(&'a [u8]),
+    /// Image data block
+    ImageData(ImageData),
+    /// Trailer block
+    Trailer(Trailer),
+}
+
+impl TryFrom<BlockRef<'_>> for Block {
+    type Error = Error;
+
+    fn try_from(b: BlockRef<'_>) -> Result<Self> {
+        Ok(match b {
+            BlockRef::Header(b) => b.into(),
+            BlockRef::LogicalScreenDesc(b) => b.into(),
+            BlockRef::GlobalColorTable(b) => {
+                GlobalColorTable::try_with_colors(b)?.into()
+            }
+            BlockRef::PlainText(b) => {
+                let mut block = PlainText::default();
+                b.iter().for_each(|b| block.add_sub_block(b));
+                block.into()
+            }
+            BlockRef::GraphicControl(b) => b.into(),
+            BlockRef::Comment(b) => {
+                let mut block = Comment::default();
+                b.iter().for_each(|b| block.add_comment(b));
+                block.into()
+            }
+            BlockRef::Application(b) => {
+                let mut block = Application::default();
+                b.iter().for_each(|b| block.add_data(b));
+                block.into()
+            }
+            BlockRef::Unknown(ext_id, b) => {
+                let mut block = Unknown::new(ext_id);
+                b.iter().for_each(|b| block.add_sub_block(b));
+                block.into()
+            }
+            BlockRef::ImageDesc(b) => b.into(),
+            BlockRef::LocalColorTable(b) => {
+                LocalColorTable::try_with_colors(b)?.into()
+            }
+            BlockRef::ImageData(b) => b.into(),
+            BlockRef::Trailer(b) => b.into(),
+        })
+    }
+}
+
+impl Block {
+    /// Parse one block from the start of a byte slice.
+    ///
+    /// Returns the block and the number of bytes consumed, including any
+    /// sub-blocks.  Only self-describing blocks can be parsed: a [Header],
+    /// or a block starting with an introducer byte (extension, [ImageDesc]
+    /// or [Trailer]).  Other blocks depend on a preceding descriptor, so
+    /// they return [InvalidBlockCode].
+    ///
+    /// ## Example
+    /// ```
+    /// use gift::block::Block;
+    ///
+    /// let bytes = [0x21, 0xFE, 0x02, b'h', b'i', 0x00, 0x3B];
+    /// let (block, len) = Block::from_bytes(&bytes)?;
+    /// assert!(matches!(block, Block::Comment(_)));
+    /// assert_eq!(len, 6);
+    /// # Ok::<(), gift::Error>(())
+    /// ```
+    ///
+    /// [Header]: struct.Header.html
+    /// [ImageDesc]: struct.ImageDesc.html
+    /// [InvalidBlockCode]: ../enum.Error.html#variant.InvalidBlockCode
+    /// [Trailer]: struct.Trailer.html
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Block, usize)> {
+        let mut blocks =
+            Blocks::new(bytes, None, None, None, false, false, false);
+        if !bytes.starts_with(BlockCode::Header_.signature()) {
+            blocks.expected_next = None;
+        }
+        let block = blocks.next_block()?;
+        let len = bytes.len() - blocks.reader.len() - blocks.lookahead.len();
+        Ok((block, len))
+    }
+}
+
+/// An Iterator for [BlockRef]s within a GIF in memory.
+///
+/// Build with Decoder.[into_block_refs].
+///
+/// [BlockRef]: enum.BlockRef.html
+/// [into_block_refs]: ../struct.Decoder.html#method.into_block_refs
+pub struct BlockRefs<'a> {
+    /// Entire input
+    data: &'a [u8],
+    /// Block decoder
+    blocks: Blocks<&'a [u8]>,
+}
+
+impl<'a> Iterator for BlockRefs<'a> {
+    type Item = Result<BlockRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.blocks.done {
+            self.blocks.done = false;
+            None
+        } else {
+            let res = self.next_block_ref();
+            match res {
+                Ok(BlockRef::Trailer(_)) | Err(_) => self.blocks.done = true,
+                _ => (),
+            }
+            Some(res)
+        }
+    }
+}
+
+impl<'a> BlockRefs<'a> {
+    /// Create a new block reference iterator
+    pub(crate) fn new(blocks: Blocks<&'a [u8]>) -> Self {
+        let data = blocks.reader;
+        BlockRefs { data, blocks }
+    }
+
+    /// Get the byte offset of the next block within the input.
+    ///
+    /// After a block is returned, this is the offset where it ends, so the
+    /// raw bytes of each block (including sub-blocks) can be sliced from the
+    /// input.
+    pub fn position(&self) -> usize {
+        self.data.len() - self.blocks.reader.len() - self.blocks.lookahead.len()
+    }
+
+    /// Decode the next block with the block decoder.
+    ///
+    /// Bytes read ahead are put back into the input, so it can be borrowed.
+    fn next_block(&mut self) -> Result<Block> {
+        let block = self.blocks.next_block()?;
+        let ahead = self.blocks.lookahead.len();
+        if ahead > 0 {
+            let pos = self.data.len() - self.blocks.reader.len() - ahead;
+            self.blocks.reader = &self.data[pos..];
+            self.blocks.lookahead.clear();
+        }
+        Ok(block)
+    }
+
+    /// Decode the next block, borrowing from the input if possible
+    fn next_block_ref(&mut self) -> Result<BlockRef<'a>> {
+        use crate::block::BlockCode::*;
+        let blocks = &mut self.blocks;
+        match blocks.expected_next {
+            Some((GlobalColorTable_, sz)) => {
+                let tbl = blocks.take_color_table(sz)?;
+                blocks.expected_next = None;
+                return Ok(BlockRef::GlobalColorTable(tbl));
+            }
+            Some((LocalColorTable_, sz)) => {
+                let tbl = blocks.take_color_table(sz)?;
+                blocks.expected_next = Some((ImageData_, ImageData_.size()));
+                return Ok(BlockRef::LocalColorTable(tbl));
+            }
+            Some(_) => (),
+            None => {
+                if let Some(block) = blocks.take_extension()? {
+                    return Ok(block);
+                }
+            }
+        }
+        Ok(match self.next_block()? {
+            Block::Header(b) => BlockRef::Header(b),
+            Block::LogicalScreenDesc(b) => BlockRef::LogicalScreenDesc(b),
+            Block::GraphicControl(b) => BlockRef::GraphicControl(b),
+            Block::ImageDesc(b) => BlockRef::ImageDesc(b),
+            Block::ImageData(b) => BlockRef::ImageData(b),
+            Block::Trailer(b) => BlockRef::Trailer(b),
+            _ => return Err(Error::InvalidBlockSequence),
+        })
+    }
+}
+
+impl<'a> Blocks<&'a [u8]> {
+    /// Take a slice of the input
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.reader.len() < len {
+            return Err(Error::UnexpectedEndOfFile);
+        }
+        let (data, rest) = self.reader.split_at(len);
+        self.reader = rest;
+        Ok(data)
+    }
+
+    /// Take color table data from the input.
+    ///
+    /// A borrowed table cannot be padded, so truncation is an error even
+    /// when decoding leniently.
+    fn take_color_table(&mut self, sz: usize) -> Result<&'a [u8]> {
+        let len = self.reader.len();
+        if len < sz {
+            return Err(Error::TruncatedColorTable {
+                expected: sz,
+                actual: len,
+            });
+        }
+        self.take_slice(sz)
+    }
+
+    /// Take sub-blocks from the input, up to the block terminator
+    fn take_sub_blocks(&mut self) -> Result<SubBlockRefs<'a>> {
+        let data = self.reader;
+        let max_sz = self.max_extension_sz.unwrap_or(usize::MAX);
+        let mut pos = 0;
+        let mut sz = 0;
+        let mut end = None;
+        loop {
+            match data.get(pos) {
+                Some(0) => break,
+                Some(_)
+                    if self.lenient
+                        && is_block_start(&data[pos..], true) == Some(true) =>
+                {
+                    warn!("Extension block terminator missing");
+                    // no terminator to skip
+                    self.reader = &data[pos..];
+                    let end = end.unwrap_or(pos);
+                    return Ok(SubBlockRefs { data: &data[..end] });
+                }
+                Some(len) => {
+                    sz += usize::from(*len);
+                    if sz > max_sz && end.is_none() {
+                        if !self.lenient {
+                            return Err(Error::TooLargeExtension);
+                        }
+                        warn!("Extension larger than {max_sz} bytes truncated");
+                        end = Some(pos);
+                    }
+                    pos += 1 + usize::from(*len);
+                }
+                None => return Err(Error::UnexpectedEndOfFile),
+            }
+        }
+        self.reader = &data[pos + 1..];
+        let end = end.unwrap_or(pos);
+        Ok(SubBlockRefs { data: &data[..end] })
+    }
+
+    /// Take an extension block (other than graphic control) from the input
+    fn take_extension(&mut self) -> Result<Option<BlockRef<'a>>> {
+        use crate::block::ExtensionCode::*;
+        let ext_id = match self.reader {
+            [0x21, ext_id, ..] => *ext_id,
+            _ => return Ok(None),
+        };
+        let b = match ExtensionCode::from(ext_id) {
+            GraphicControl_ => return Ok(None),
+            PlainText_ => BlockRef::PlainText,
+            Comment_ => BlockRef::Comment,
+            Application_ => BlockRef::Application,
+            Unknown_(_) => {
+                self.take_slice(2)?;
+                return Ok(Some(BlockRef::Unknown(
+                    ext_id,
+                    self.take_sub_blocks()?,
+                )));
+            }
+        };
+        self.take_slice(2)?;
+        Ok(Some(b(self.take_sub_blocks()?)))
+    }
+}
+
 /// An Iterator for [Frame]s within a GIF file.
 ///
 /// Build with Decoder.[into_frames].
@@ -428,7 +1261,9 @@ pub struct Frames<R: Read> {
     /// Block decoder
     blocks: Blocks<R>,
     /// Preamble blocks
-    preamble: Option<Preamble>,
+    preamble: Preamble,
+    /// Flag when all preamble blocks have been read
+    preamble_done: bool,
     /// Graphic control block
     graphic_control_ext: Option<GraphicControl>,
     /// Image description block
@@ -462,7 +1297,8 @@ impl<R: Read> Frames<R> {
     pub(crate) fn new(blocks: Blocks<R>) -> Self {
         Frames {
             blocks,
-            preamble: None,
+            preamble: Preamble::default(),
+            preamble_done: false,
             graphic_control_ext: None,
             image_desc: None,
             local_color_table: None,
@@ -471,21 +1307,73 @@ impl<R: Read> Frames<R> {
 
     /// Read preamble blocks.  These are the blocks at the beginning of the
     /// file, before any frame blocks.
+    ///
+    /// The preamble is cached, so this can be called again at any time
+    /// (including after frames have been decoded) to get a copy.  A file
+    /// with no frames has a preamble containing all blocks before the
+    /// trailer.  Returns `None` if decoding stopped before the preamble was
+    /// complete (after a previous error).
+    ///
+    /// A background color index outside of the global color table is
+    /// logged as a warning, and [background_color] returns `None`.
+    ///
+    /// [background_color]: ../block/struct.Preamble.html#method.background_color
     pub fn preamble(&mut self) -> Result<Option<Preamble>> {
-        if self.has_frame() {
-            return Ok(None);
-        }
-        self.preamble = Some(Preamble::default());
-        while let Some(block) = self.blocks.next() {
-            self.handle_block(block?)?;
-            if self.has_frame() {
-                return Ok(self.preamble.take());
+        while !self.preamble_done {
+            match self.blocks.next() {
+                Some(block) => {
+                    self.handle_block(block?)?;
+                }
+                None => return Ok(None),
             }
         }
-        Err(Error::InvalidBlockSequence)
+        Ok(Some(self.preamble.clone()))
     }
 
-    /// Check if any frame blocks exist
+    /// Get compressed size statistics of the most recent frame.
+    ///
+    /// These are available whether or not image data is decompressed.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.blocks.image_stats
+    }
+
+    /// Take the partly decoded frame after an error.
+    ///
+    /// When decoding fails within a frame, the blocks decoded before the
+    /// error are kept, along with any image data decompressed from complete
+    /// sub-blocks.  Returns `None` if no image descriptor was decoded.
+    ///
+    /// ## Example
+    /// ```
+    /// # let gif = &[
+    /// #   0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00,
+    /// #   0x02, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00,
+    /// #   0xff, 0xff, 0xff, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    /// #   0x02, 0x00, 0x02, 0x00, 0x00, 0x02, 0x03, 0x0c,
+    /// # ][..];
+    /// let mut frames = gift::Decoder::new(gif).into_frames();
+    /// for frame in frames.by_ref() {
+    ///     if let Err(e) = frame {
+    ///         println!("error: {e}");
+    ///         break;
+    ///     }
+    /// }
+    /// if let Some(partial) = frames.take_partial() {
+    ///     println!("partial frame: {} rows decoded", partial.rows());
+    /// }
+    /// ```
+    pub fn take_partial(&mut self) -> Option<PartialFrame> {
+        let image_data = self.blocks.take_partial_image_data();
+        let image_desc = self.image_desc.take()?;
+        Some(PartialFrame {
+            graphic_control_ext: self.graphic_control_ext.take(),
+            image_desc,
+            local_color_table: self.local_color_table.take(),
+            image_data: image_data.unwrap_or_else(|| ImageData::new(0)),
+        })
+    }
+
+    /// Check if any frame blocks exist
     fn has_frame(&self) -> bool {
         self.graphic_control_ext.is_some()
             || self.image_desc.is_some()
@@ -495,43 +1383,38 @@ impl<R: Read> Frames<R> {
     /// Handle one block
     fn handle_block(&mut self, block: Block) -> Result<Option<Frame>> {
         match block {
-            Block::Header(b) => {
-                if let Some(ref mut f) = &mut self.preamble {
-                    f.header = b;
-                }
+            Block::Header(b) if !self.preamble_done => {
+                self.preamble.header = b;
             }
-            Block::LogicalScreenDesc(b) => {
-                if let Some(ref mut f) = &mut self.preamble {
-                    f.logical_screen_desc = b;
-                }
+            Block::LogicalScreenDesc(b) if !self.preamble_done => {
+                self.preamble.logical_screen_desc = b;
             }
-            Block::GlobalColorTable(b) => {
-                if let Some(ref mut f) = &mut self.preamble {
-                    f.global_color_table = Some(b);
-                }
+            Block::GlobalColorTable(b) if !self.preamble_done => {
+                check_background_color(&self.preamble, &b);
+                self.preamble.global_color_table = Some(b);
             }
-            Block::Application(b) => {
-                if let (Some(ref mut f), Some(_)) =
-                    (&mut self.preamble, b.loop_count())
-                {
-                    f.loop_count_ext = Some(b);
+            Block::Application(b) if !self.preamble_done => {
+                if b.loop_count().is_some() {
+                    self.preamble.loop_count_ext = Some(b);
+                } else {
+                    self.preamble.applications.push(b);
                 }
             }
-            Block::Comment(b) => {
-                if let Some(ref mut f) = &mut self.preamble {
-                    f.comments.push(b);
-                }
+            Block::Comment(b) if !self.preamble_done => {
+                self.preamble.comments.push(b);
             }
             Block::GraphicControl(b) => {
                 if self.has_frame() {
                     return Err(Error::InvalidBlockSequence);
                 }
+                self.preamble_done = true;
                 self.graphic_control_ext = Some(b);
             }
             Block::ImageDesc(b) => {
                 if self.image_desc.is_some() {
                     return Err(Error::InvalidBlockSequence);
                 }
+                self.preamble_done = true;
                 self.image_desc = Some(b);
             }
             Block::LocalColorTable(b) => {
@@ -542,6 +1425,12 @@ impl<R: Read> Frames<R> {
                 let image_desc = self.image_desc.take();
                 let local_color_table = self.local_color_table.take();
                 if let Some(image_desc) = image_desc {
+                    let image_data =
+                        if image_desc.interlaced() && !image_data.is_raw() {
+                            image_data.deinterlace(&image_desc)
+                        } else {
+                            image_data
+                        };
                     let f = Frame::new(
                         graphic_control_ext,
                         image_desc,
@@ -553,6 +1442,7 @@ impl<R: Read> Frames<R> {
                     return Err(Error::InvalidBlockSequence);
                 }
             }
+            Block::Trailer(_) => self.preamble_done = true,
             _ => {}
         }
         Ok(None)
@@ -567,12 +1457,35 @@ struct StepsOnce<R: Read> {
     global_color_table: Option<GlobalColorTable>,
     /// Loop count extension block
     loop_count_ext: Option<Application>,
+    /// Region of interest
+    crop: Option<Region>,
+    /// Maximum raster size (width, height)
+    max_size: Option<(u16, u16)>,
+    /// Mapping from screen to raster
+    view: Option<View>,
     /// Current raster of animation
     raster: Option<Raster<SRgba8>>,
+    /// Policy for frames extending beyond the screen
+    oversize: Oversize,
+    /// Maximum screen size when expanding, in bytes
+    max_screen_sz: Option<usize>,
+}
+
+/// Mapping from the logical screen to the compositing raster
+#[derive(Clone, Copy, Debug)]
+struct View {
+    /// Logical screen
+    screen: Region,
+    /// Region of screen covered by raster
+    region: Region,
+    /// Sampling interval (screen pixels per raster pixel)
+    skip: u32,
 }
 
 /// A step iterator which repeats the animation.
-struct StepsLooping {
+struct StepsLooping<R: Read> {
+    /// Steps to decode before first iteration
+    once: Option<Box<StepsOnce<R>>>,
     /// Decoding error
     err: Option<Error>,
     /// All steps from animation
@@ -581,6 +1494,58 @@ struct StepsLooping {
     loop_count: Option<u16>,
     /// Current step number
     step_n: usize,
+    /// Partly decoded step after an error
+    partial: Option<Step>,
+}
+
+/// Policy for reporting step delay times.
+///
+/// The [GraphicControl] of each step is not changed; only the delay reported
+/// by `Step::delay_time_cs()` and `Step::delay()` is adjusted.
+///
+/// [GraphicControl]: ../block/struct.GraphicControl.html
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DelayPolicy {
+    /// Report delays as encoded
+    #[default]
+    Raw,
+    /// Report delays like web browsers: 0 or 1 centisecond is replaced with
+    /// `min_cs` (browsers use 10)
+    Browser {
+        /// Delay for steps of 0 or 1 centisecond
+        min_cs: u16,
+    },
+}
+
+impl DelayPolicy {
+    /// Adjust a delay time (centiseconds)
+    pub(crate) fn adjust(self, delay_cs: u16) -> u16 {
+        match self {
+            DelayPolicy::Raw => delay_cs,
+            DelayPolicy::Browser { min_cs } if delay_cs < 2 => min_cs,
+            DelayPolicy::Browser { .. } => delay_cs,
+        }
+    }
+}
+
+/// Policy for frames which extend beyond the logical screen.
+///
+/// Set with Decoder.[oversize_frames].
+///
+/// [oversize_frames]: ../struct.Decoder.html#method.oversize_frames
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Oversize {
+    /// Return [InvalidFrameDimensions]
+    ///
+    /// [InvalidFrameDimensions]: ../enum.Error.html#variant.InvalidFrameDimensions
+    #[default]
+    Error,
+    /// Clip frames to the screen, dropping pixels outside of it (like web
+    /// browsers)
+    Clip,
+    /// Expand the screen to fit frames.  Steps after an oversize frame have
+    /// larger rasters; scaling is not changed.
+    Expand,
 }
 
 /// Steps iterator which can be once or looping
@@ -588,7 +1553,7 @@ enum StepsInner<R: Read> {
     /// Iterate only once
     Once(Box<StepsOnce<R>>),
     /// Loop steps more than once
-    Looping(StepsLooping),
+    Looping(StepsLooping<R>),
 }
 
 /// An Iterator for [Step]s within a GIF file.
@@ -625,6 +1590,8 @@ enum StepsInner<R: Read> {
 pub struct Steps<R: Read> {
     /// Inner iterator; either Once or Looping
     inner: StepsInner<R>,
+    /// Delay time policy
+    delay_policy: DelayPolicy,
 }
 
 impl<R: Read> Iterator for StepsOnce<R> {
@@ -650,7 +1617,12 @@ impl<R: Read> StepsOnce<R> {
             frames,
             global_color_table: None,
             loop_count_ext: None,
+            crop: None,
+            max_size: None,
+            view: None,
             raster: None,
+            oversize: Oversize::default(),
+            max_screen_sz: None,
         }
     }
 
@@ -661,7 +1633,10 @@ impl<R: Read> StepsOnce<R> {
             self.loop_count_ext = p.loop_count_ext.take();
             let w = p.screen_width().into();
             let h = p.screen_height().into();
-            self.raster = Some(Raster::with_clear(w, h));
+            let screen = Region::new(0, 0, w, h);
+            let view = View::new(screen, self.crop, self.max_size);
+            self.raster = Some(Raster::with_clear(view.width(), view.height()));
+            self.view = Some(view);
             Ok(())
         } else {
             warn!("Preamble not found!");
@@ -679,128 +1654,391 @@ impl<R: Read> StepsOnce<R> {
         }
     }
 
+    /// Composite the next frame without producing a step
+    fn skip_step(&mut self) -> Option<Result<()>> {
+        if self.raster.is_none() {
+            if let Err(e) = self.make_raster() {
+                return Some(Err(e));
+            }
+            self.raster.as_ref()?;
+        }
+        match self.frames.next() {
+            Some(Ok(f)) => Some(self.composite_frame(&f)),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+
+    /// Expand the screen to fit a frame, if needed
+    fn expand_screen(&mut self, frame: &Frame) -> Result<()> {
+        let (Some(base), Some(view)) = (self.raster.as_mut(), self.view) else {
+            return Err(Error::InvalidBlockSequence);
+        };
+        let reg = frame.region();
+        if self.oversize != Oversize::Expand
+            || view.screen.intersection(reg) == reg
+        {
+            return Ok(());
+        }
+        // frame position is never negative
+        let width = view.screen.width.max(reg.x as u32 + reg.width);
+        let height = view.screen.height.max(reg.y as u32 + reg.height);
+        if let Some(sz) = self.max_screen_sz {
+            let screen_sz = (width as usize * height as usize)
+                .saturating_mul(std::mem::size_of::<SRgba8>());
+            if screen_sz > sz {
+                return Err(Error::TooLargeImage);
+            }
+        }
+        let screen = Region::new(0, 0, width, height);
+        let region = self.crop.map_or(screen, |crop| screen.intersection(crop));
+        let expanded = View {
+            screen,
+            region,
+            skip: view.skip,
+        };
+        if expanded.width() != base.width()
+            || expanded.height() != base.height()
+        {
+            // the screen only grows right and down, so the origin is kept
+            let mut raster =
+                Raster::with_clear(expanded.width(), expanded.height());
+            raster.copy_raster(base.region(), base, base.region());
+            *base = raster;
+        }
+        self.view = Some(expanded);
+        Ok(())
+    }
+
+    /// Composite a frame onto the raster, applying its disposal method
+    fn composite_frame(&mut self, frame: &Frame) -> Result<()> {
+        self.expand_screen(frame)?;
+        let (Some(base), Some(view)) = (self.raster.as_mut(), self.view) else {
+            return Err(Error::InvalidBlockSequence);
+        };
+        let tbl = &self.global_color_table;
+        let lenient = self.frames.blocks.lenient;
+        match frame.disposal_method() {
+            // raster is restored after the frame, so only check it
+            DisposalMethod::Previous => {
+                frame_colors(view, frame, tbl, self.oversize, lenient)?;
+            }
+            DisposalMethod::Background => {
+                update_raster(base, view, frame, tbl, self.oversize, lenient)?;
+                let region = view.map_region(frame.region());
+                base.copy_color(region, SRgba8::default());
+            }
+            _ => update_raster(base, view, frame, tbl, self.oversize, lenient)?,
+        }
+        Ok(())
+    }
+
     /// Apply a frame to the raster
     fn apply_frame(&mut self, frame: Frame) -> Result<Step> {
-        let transparent_color = frame
-            .graphic_control_ext
-            .unwrap_or_default()
-            .transparent_color();
+        self.expand_screen(&frame)?;
+        let (Some(base), Some(view)) = (self.raster.as_mut(), self.view) else {
+            return Err(Error::InvalidBlockSequence);
+        };
+        let tbl = &self.global_color_table;
+        let (oversize, lenient) = (self.oversize, self.frames.blocks.lenient);
         let raster = if let DisposalMethod::Previous = frame.disposal_method() {
-            let raster = self.raster.as_ref().unwrap();
-            let mut raster = Raster::with_raster(raster);
-            update_raster(&mut raster, &frame, &self.global_color_table)?;
+            let mut raster = Raster::with_raster(base);
+            update_raster(&mut raster, view, &frame, tbl, oversize, lenient)?;
             raster
         } else {
-            let raster = self.raster.as_mut().unwrap();
-            update_raster(raster, &frame, &self.global_color_table)?;
-            Raster::with_raster(raster)
+            update_raster(base, view, &frame, tbl, oversize, lenient)?;
+            Raster::with_raster(base)
         };
         if let DisposalMethod::Background = frame.disposal_method() {
-            let rs = self.raster.as_mut().unwrap();
-            rs.copy_color(frame.region(), SRgba8::default());
+            base.copy_color(view.map_region(frame.region()), SRgba8::default());
+        }
+        Ok(make_step(view, raster, &frame))
+    }
+
+    /// Make a step from the partly decoded frame after an error
+    fn take_partial(&mut self) -> Option<Step> {
+        let partial = self.frames.take_partial()?;
+        let image_data = if partial.image_desc.interlaced() {
+            // rows are not in raster order
+            ImageData::new(0)
+        } else {
+            partial.image_data
+        };
+        let frame = Frame::new(
+            partial.graphic_control_ext,
+            partial.image_desc,
+            partial.local_color_table,
+            image_data,
+        );
+        self.apply_frame(frame).ok()
+    }
+
+    /// Composite all remaining frames, producing only the last step
+    fn last_step(mut self) -> Result<Option<Step>> {
+        if self.raster.is_none() {
+            self.make_raster()?;
+        }
+        // scratch raster for frames with `Previous` disposal
+        let mut scratch: Option<Raster<SRgba8>> = None;
+        let mut last: Option<Frame> = None;
+        while let Some(frame) = self.frames.next() {
+            let frame = frame?;
+            self.expand_screen(&frame)?;
+            let (Some(base), Some(view)) = (self.raster.as_mut(), self.view)
+            else {
+                return Err(Error::InvalidBlockSequence);
+            };
+            if let Some(prev) = &last {
+                if let DisposalMethod::Background = prev.disposal_method() {
+                    let region = view.map_region(prev.region());
+                    base.copy_color(region, SRgba8::default());
+                }
+            }
+            let tbl = &self.global_color_table;
+            let (oversize, lenient) =
+                (self.oversize, self.frames.blocks.lenient);
+            if let DisposalMethod::Previous = frame.disposal_method() {
+                let raster = match &mut scratch {
+                    Some(r) if r.region() == base.region() => r,
+                    // screen may have been expanded
+                    _ => scratch.insert(Raster::with_raster(&*base)),
+                };
+                raster.pixels_mut().copy_from_slice(base.pixels());
+                update_raster(raster, view, &frame, tbl, oversize, lenient)?;
+            } else {
+                update_raster(base, view, &frame, tbl, oversize, lenient)?;
+            }
+            last = Some(frame);
         }
-        Ok(Step::with_true_color(raster)
-            .with_transparent_color(transparent_color))
+        let (Some(frame), Some(view)) = (last, self.view) else {
+            return Ok(None);
+        };
+        let raster = match frame.disposal_method() {
+            DisposalMethod::Previous => scratch,
+            _ => self.raster,
+        };
+        Ok(raster.map(|raster| make_step(view, raster, &frame)))
+    }
+}
+
+/// Make a step from a composited raster
+fn make_step(view: View, raster: Raster<SRgba8>, frame: &Frame) -> Step {
+    let region = view.map_region(frame.region());
+    let step = Step::with_true_color(raster).with_region(region);
+    match frame.graphic_control_ext {
+        Some(control) => step.with_graphic_control(control),
+        None => step,
+    }
+}
+
+/// Grayscale color table for frames without one
+const GRAY_COLORS: [u8; 768] = gray_colors();
+
+/// Make grayscale color table (index i is `(i, i, i)`)
+const fn gray_colors() -> [u8; 768] {
+    let mut colors = [0; 768];
+    let mut i = 0;
+    while i < colors.len() {
+        colors[i] = (i / 3) as u8;
+        i += 1;
+    }
+    colors
+}
+
+/// Check frame dimensions and get its color table
+fn frame_colors<'a>(
+    view: View,
+    frame: &'a Frame,
+    global_tbl: &'a Option<GlobalColorTable>,
+    oversize: Oversize,
+    lenient: bool,
+) -> Result<&'a [u8]> {
+    let reg = frame.region();
+    if oversize != Oversize::Clip && view.screen.intersection(reg) != reg {
+        return Err(Error::InvalidFrameDimensions);
+    }
+    match frame.color_table(global_tbl.as_ref()) {
+        Err(Error::MissingColorTable) if lenient => {
+            warn!("Frame has no color table, using grayscale");
+            Ok(&GRAY_COLORS)
+        }
+        res => res,
     }
 }
 
 /// Update a raster with a new frame
 fn update_raster(
     raster: &mut Raster<SRgba8>,
+    view: View,
     frame: &Frame,
     global_tbl: &Option<GlobalColorTable>,
+    oversize: Oversize,
+    lenient: bool,
 ) -> Result<()> {
-    let reg = frame.region();
-    if raster.intersection(reg) == reg {
-        let clrs = if let Some(tbl) = &frame.local_color_table {
-            tbl.colors()
-        } else if let Some(tbl) = global_tbl {
-            tbl.colors()
-        } else {
-            return Err(Error::MissingColorTable);
-        };
-        update_frame(raster, reg, frame, clrs)
-    } else {
-        Err(Error::InvalidFrameDimensions)
-    }
+    let clrs = frame_colors(view, frame, global_tbl, oversize, lenient)?;
+    update_frame(raster, view, frame, clrs)
 }
 
 /// Update a region of a raster with a new frame
 fn update_frame(
     raster: &mut Raster<SRgba8>,
-    reg: Region,
+    view: View,
     frame: &Frame,
     clrs: &[u8],
 ) -> Result<()> {
     let trans_clr = frame.transparent_color();
     let width = usize::from(frame.width());
+    if width == 0 {
+        return Ok(());
+    }
+    let reg = frame.region();
+    let rreg = view.map_region(reg);
+    if rreg.width == 0 || rreg.height == 0 {
+        return Ok(());
+    }
+    // offset of first sampled pixel within frame
+    let col = view.frame_offset(rreg.x, view.region.x, reg.x);
+    let row = view.frame_offset(rreg.y, view.region.y, reg.y);
+    let skip = view.skip as usize;
     let data = frame.image_data.data();
-    for (row, frow) in raster.rows_mut(reg).zip(data.chunks_exact(width)) {
-        for (p, fp) in row.iter_mut().zip(frow) {
-            let idx = *fp;
-            let i = 3 * idx as usize;
-            if i + 2 > clrs.len() {
-                return Err(Error::InvalidColorIndex);
-            }
-            let entry = match trans_clr {
-                Some(trans_idx) if trans_idx == idx => SRgba8::default(),
-                _ => SRgba8::new(clrs[i], clrs[i + 1], clrs[i + 2], 255),
-            };
-            *p = entry;
+    let frows = data.chunks_exact(width).skip(row).step_by(skip);
+    for (row, frow) in raster.rows_mut(rreg).zip(frows) {
+        let fpix = frow.iter().skip(col).step_by(skip);
+        for (p, fp) in row.iter_mut().zip(fpix) {
+            *p = index_color(clrs, trans_clr, *fp)?;
         }
     }
     Ok(())
 }
 
-impl Iterator for StepsLooping {
+impl View {
+    /// Create a view of a screen region, scaled to fit a maximum size
+    fn new(
+        screen: Region,
+        crop: Option<Region>,
+        max_size: Option<(u16, u16)>,
+    ) -> Self {
+        let region = crop.map_or(screen, |crop| screen.intersection(crop));
+        let skip = match max_size {
+            Some((w, h)) => {
+                let sx = region.width.div_ceil(u32::from(w).max(1));
+                let sy = region.height.div_ceil(u32::from(h).max(1));
+                sx.max(sy).max(1)
+            }
+            None => 1,
+        };
+        View {
+            screen,
+            region,
+            skip,
+        }
+    }
+
+    /// Get the raster width
+    fn width(self) -> u32 {
+        self.region.width.div_ceil(self.skip)
+    }
+
+    /// Get the raster height
+    fn height(self) -> u32 {
+        self.region.height.div_ceil(self.skip)
+    }
+
+    /// Map a screen region to the raster.
+    ///
+    /// The result includes every raster pixel sampled from the region.
+    fn map_region(self, reg: Region) -> Region {
+        let (x, width) =
+            self.map_span(reg.x, reg.width, self.region.x, self.width());
+        let (y, height) =
+            self.map_span(reg.y, reg.height, self.region.y, self.height());
+        Region::new(x, y, width, height)
+    }
+
+    /// Map a span of screen pixels to raster pixels
+    fn map_span(self, pos: i32, len: u32, origin: i32, max: u32) -> (i32, u32) {
+        let skip = i64::from(self.skip);
+        let start = i64::from(pos) - i64::from(origin);
+        let end = start + i64::from(len);
+        let first = (start.max(0) + skip - 1) / skip;
+        let last = ((end.max(0) + skip - 1) / skip).min(i64::from(max));
+        let first = first.min(last);
+        // both values are limited by raster size
+        (first as i32, (last - first) as u32)
+    }
+
+    /// Get screen pixel offset within a frame for a raster position
+    fn frame_offset(self, pos: i32, origin: i32, frame_pos: i32) -> usize {
+        let screen = i64::from(origin) + i64::from(pos) * i64::from(self.skip);
+        (screen - i64::from(frame_pos)) as usize
+    }
+}
+
+impl<R: Read> Iterator for StepsLooping<R> {
     type Item = Result<Step>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.advance()
+            .map(|res| res.map(|step_n| self.steps[step_n].clone()))
+    }
+}
+
+impl<R: Read> StepsLooping<R> {
+    /// Create a new looping step iterator
+    fn new(frames: Frames<R>) -> Self {
+        StepsLooping {
+            once: Some(Box::new(StepsOnce::new(frames))),
+            err: None,
+            steps: vec![],
+            loop_count: None,
+            step_n: 0,
+            partial: None,
+        }
+    }
+
+    /// Advance to the next step, returning its index
+    fn advance(&mut self) -> Option<Result<usize>> {
+        if let Some(once) = self.once.take() {
+            self.decode_steps(*once);
+        }
         if let Some(err) = self.err.take() {
             return Some(Err(err));
         }
-        if let Some(step) = self.steps.get(self.step_n) {
+        if self.step_n < self.steps.len() {
             self.step_n += 1;
-            Some(Ok(step.clone()))
+            Some(Ok(self.step_n - 1))
         } else if let Some(loop_count) = self.loop_count {
             match loop_count.cmp(&1) {
                 Ordering::Greater => self.loop_count = Some(loop_count - 1),
                 Ordering::Equal => self.loop_count = None,
                 _ => (),
             }
-            if let Some(step) = self.steps.first() {
-                self.step_n = 1;
-                Some(Ok(step.clone()))
-            } else {
+            if self.steps.is_empty() {
                 None
+            } else {
+                self.step_n = 1;
+                Some(Ok(0))
             }
         } else {
             None
         }
     }
-}
 
-impl StepsLooping {
-    /// Create a new looping step iterator
-    fn new<R: Read>(frames: Frames<R>) -> Self {
-        let mut once = StepsOnce::new(frames);
-        let mut this = StepsLooping {
-            err: None,
-            steps: vec![],
-            loop_count: None,
-            step_n: 0,
-        };
+    /// Decode all steps
+    fn decode_steps(&mut self, mut once: StepsOnce<R>) {
         if let Some(res) = once.next() {
-            this.push_step(res);
+            self.push_step(res);
         }
         // Loop count is only available after first step is read
         if let Some(ref lp) = once.loop_count_ext {
-            this.loop_count = lp.loop_count();
+            self.loop_count = lp.loop_count();
+        }
+        for res in once.by_ref() {
+            self.push_step(res);
         }
-        for res in once {
-            this.push_step(res);
+        if self.err.is_some() {
+            self.partial = once.take_partial();
         }
-        this
     }
 
     /// Push one step
@@ -816,10 +2054,16 @@ impl<R: Read> Iterator for Steps<R> {
     type Item = Result<Step>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match &mut self.inner {
+        let res = match &mut self.inner {
             StepsInner::Once(s) => s.next(),
             StepsInner::Looping(s) => s.next(),
-        }
+        };
+        res.map(|res| {
+            res.map(|mut step| {
+                step.delay_policy = self.delay_policy;
+                step
+            })
+        })
     }
 }
 
@@ -828,20 +2072,165 @@ impl<R: Read> Steps<R> {
     pub(crate) fn new_once(frames: Frames<R>) -> Self {
         let once = StepsOnce::new(frames);
         let inner = StepsInner::Once(Box::new(once));
-        Steps { inner }
+        Steps {
+            inner,
+            delay_policy: DelayPolicy::default(),
+        }
     }
 
     /// Create a new step decoder with looping
     pub(crate) fn new_looping(frames: Frames<R>) -> Self {
         let looping = StepsLooping::new(frames);
         let inner = StepsInner::Looping(looping);
-        Steps { inner }
+        Steps {
+            inner,
+            delay_policy: DelayPolicy::default(),
+        }
+    }
+
+    /// Set the policy for frames extending beyond the screen
+    pub(crate) fn with_oversize(
+        mut self,
+        oversize: Oversize,
+        max_screen_sz: Option<usize>,
+    ) -> Self {
+        if let Some(once) = self.once_mut() {
+            once.oversize = oversize;
+            once.max_screen_sz = max_screen_sz;
+        }
+        self
+    }
+
+    /// Set the policy for reporting step delay times.
+    ///
+    /// The default is `DelayPolicy::Raw`.
+    pub fn delay_policy(mut self, policy: DelayPolicy) -> Self {
+        self.delay_policy = policy;
+        self
+    }
+
+    /// Decode at reduced resolution, for thumbnails.
+    ///
+    /// Steps are composited by sampling every Nth pixel and row of each
+    /// frame (nearest neighbor), using the smallest integer N which fits the
+    /// screen within `max_width` by `max_height`.  Step regions are scaled to
+    /// match.
+    ///
+    /// Must be called before iterating.
+    pub fn scaled(mut self, max_width: u16, max_height: u16) -> Self {
+        if let Some(once) = self.once_mut() {
+            once.max_size = Some((max_width, max_height));
+        }
+        self
+    }
+
+    /// Decode only a region of interest of the logical screen.
+    ///
+    /// Step rasters have the dimensions of the region (clipped to the
+    /// screen), and pixels outside of it are skipped.  Frames which lie
+    /// entirely outside the region still produce steps, with their delay and
+    /// disposal applied.
+    ///
+    /// Must be called before iterating.  When combined with
+    /// [scaled](#method.scaled), the cropped region is scaled.
+    pub fn cropped(mut self, region: Region) -> Self {
+        if let Some(once) = self.once_mut() {
+            once.crop = Some(region);
+        }
+        self
+    }
+
+    /// Skip the next `n` steps.
+    ///
+    /// Unlike `Iterator::skip`, frames are composited in place, without
+    /// allocating a raster for each skipped step.
+    ///
+    /// Returns the number of steps skipped, which is less than `n` if the
+    /// end of the animation was reached.
+    pub fn skip_steps(&mut self, n: usize) -> Result<usize> {
+        for i in 0..n {
+            let res = match &mut self.inner {
+                StepsInner::Once(s) => s.skip_step(),
+                StepsInner::Looping(s) => s.advance().map(|r| r.map(|_| ())),
+            };
+            match res {
+                Some(Ok(())) => (),
+                Some(Err(e)) => return Err(e),
+                None => return Ok(i),
+            }
+        }
+        Ok(n)
+    }
+
+    /// Get the last step, compositing frames in place.
+    ///
+    /// This is equivalent to `Iterator::last`, but only allocates a raster
+    /// for the final step.  For looping iterators, it is the final step of
+    /// the animation.
+    pub fn last_step(self) -> Result<Option<Step>> {
+        let delay_policy = self.delay_policy;
+        let step = match self.inner {
+            StepsInner::Once(s) => s.last_step()?,
+            StepsInner::Looping(mut s) => {
+                if let Some(once) = s.once.take() {
+                    s.decode_steps(*once);
+                }
+                if let Some(err) = s.err.take() {
+                    return Err(err);
+                }
+                s.steps.pop()
+            }
+        };
+        Ok(step.map(|mut step| {
+            step.delay_policy = delay_policy;
+            step
+        }))
+    }
+
+    /// Take a step with the partly decoded frame after an error.
+    ///
+    /// The complete rows of the frame are composited onto the raster of
+    /// the last good step; rows which were not decoded are left unchanged.
+    /// For interlaced frames, no rows are composited.  Returns `None` if
+    /// decoding did not fail within a frame.
+    ///
+    /// See Frames.[take_partial].
+    ///
+    /// [take_partial]: struct.Frames.html#method.take_partial
+    pub fn take_partial(&mut self) -> Option<Step> {
+        let step = match &mut self.inner {
+            StepsInner::Once(s) => s.take_partial(),
+            StepsInner::Looping(s) => s.partial.take(),
+        };
+        step.map(|mut step| {
+            step.delay_policy = self.delay_policy;
+            step
+        })
+    }
+
+    /// Iterate steps along with their dirty regions.
+    ///
+    /// See [dirty_region](../diff/fn.dirty_region.html).
+    pub fn with_dirty_regions(self) -> DirtyRegions<R> {
+        DirtyRegions::new(self)
+    }
+
+    /// Get the inner step iterator, if iteration has not started
+    fn once_mut(&mut self) -> Option<&mut StepsOnce<R>> {
+        let once = match &mut self.inner {
+            StepsInner::Once(once) => Some(once),
+            StepsInner::Looping(looping) => looping.once.as_mut(),
+        };
+        once.filter(|once| once.raster.is_none())
+            .map(|once| &mut **once)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::super::Decoder;
+    use super::FrameStats;
+    use pix::{rgb::SRgba8, Raster, Region};
     use std::error::Error;
 
     #[rustfmt::skip]
@@ -935,6 +2324,31 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn frame_stats() {
+        for raw in [false, true] {
+            let mut frames =
+                Decoder::new(GIF_1).raw_image_data(raw).into_frames();
+            assert_eq!(frames.frame_stats(), FrameStats::default());
+            frames.next().unwrap().unwrap();
+            let stats = frames.frame_stats();
+            assert_eq!(stats.compressed_len, 22);
+            assert_eq!(stats.sub_block_count, 1);
+        }
+        let mut gif = GIF_1[..44].to_vec();
+        for chunk in GIF_1[45..67].chunks(5) {
+            gif.push(chunk.len() as u8);
+            gif.extend_from_slice(chunk);
+        }
+        gif.extend_from_slice(&GIF_1[67..]);
+        let mut frames = Decoder::new(&gif[..]).into_frames();
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.image_data.data(), IMAGE_1);
+        let stats = frames.frame_stats();
+        assert_eq!(stats.compressed_len, 22);
+        assert_eq!(stats.sub_block_count, 5);
+    }
+
     #[test]
     fn image_1() -> Result<(), Box<dyn Error>> {
         use pix::rgb::SRgba8;
@@ -963,14 +2377,798 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn step_rgba8() {
+        use crate::ByteOrder;
+        let step = Decoder::new(GIF_1).into_steps().next().unwrap().unwrap();
+        let bytes = step.as_rgba8().unwrap();
+        assert_eq!(bytes.len(), 10 * 10 * 4);
+        assert_eq!(step.to_rgba8_vec(ByteOrder::Rgba), bytes);
+        // row 0: red, then blue at x = 5; row 3: white at x = 3
+        assert_eq!(bytes[..4], [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(bytes[5 * 4..6 * 4], [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(bytes[33 * 4..34 * 4], [0xFF, 0xFF, 0xFF, 0xFF]);
+        let bgra = step.to_rgba8_vec(ByteOrder::Bgra);
+        assert_eq!(bgra[..4], [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(bgra[5 * 4..6 * 4], [0xFF, 0x00, 0x00, 0xFF]);
+        let argb = step.to_rgba8_vec(ByteOrder::Argb);
+        assert_eq!(argb[..4], [0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(argb[5 * 4..6 * 4], [0xFF, 0x00, 0x00, 0xFF]);
+    }
+
     const HEADER: &[u8] = &[0x47, 0x49, 0x46, 0x38, 0x39, 0x60];
 
+    #[test]
+    fn step_control() {
+        use crate::block::DisposalMethod;
+        let mut gif = GIF_1.to_vec();
+        // graphic control: background disposal, 10 cs delay
+        assert_eq!(&gif[25..28], [0x21, 0xF9, 0x04]);
+        gif[28] = 0x08;
+        gif[29] = 10;
+        let step = Decoder::new(&gif[..]).into_steps().next().unwrap().unwrap();
+        let control = step.graphic_control().unwrap();
+        assert_eq!(control.disposal_method(), DisposalMethod::Background);
+        assert_eq!(control.delay_time_cs(), 10);
+        assert_eq!(step.delay_time_cs(), Some(10));
+    }
+
+    #[test]
+    fn step_disposal() {
+        use crate::block::DisposalMethod::*;
+        use crate::{Encoder, Step};
+        use pix::{gray::Gray8, rgb::SRgb8, Palette, Raster};
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0xFF));
+        let methods = [Background, Previous, Keep, NoAction];
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        for (i, method) in methods.iter().enumerate() {
+            let raster = Raster::with_color(2, 2, Gray8::new(i as u8 & 1));
+            let step = Step::with_indexed(raster, palette.clone())
+                .with_disposal_method(*method);
+            enc.encode_step(&step).unwrap();
+        }
+        drop(enc);
+        let steps = Decoder::new(&bytes[..]).into_steps();
+        let decoded: Vec<_> =
+            steps.map(|s| s.unwrap().disposal_method()).collect();
+        assert_eq!(decoded, methods);
+    }
+
+    #[test]
+    fn lenient_version() {
+        use crate::block::Version;
+        use crate::error::Error;
+        let mut gif = GIF_1.to_vec();
+        gif[..HEADER.len()].copy_from_slice(HEADER);
+        match Decoder::new(&gif[..]).into_frames().next() {
+            Some(Err(Error::UnsupportedVersion(_))) => (),
+            _ => panic!("expected UnsupportedVersion"),
+        }
+        let mut frames = Decoder::new(&gif[..]).lenient(true).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.header.version_enum(), Version::Other(*b"89`"));
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.image_desc.width(), 10);
+        assert!(frames.next().is_none());
+        let steps = Decoder::new(&gif[..]).lenient(true).into_steps();
+        assert_eq!(steps.count(), 1);
+    }
+
+    /// Make a 4x4 GIF with image data expanding far beyond its size
+    fn lzw_bomb() -> Vec<u8> {
+        use crate::block::*;
+        use crate::lzw::Compressor;
+        use crate::Encoder;
+        let mut data = ImageData::new_raw(2);
+        Compressor::new(2)
+            .compress_to(&vec![0; 1 << 22], data.data_mut())
+            .unwrap();
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default().with_screen_width(4))
+            .unwrap();
+        enc.encode(ImageDesc::default().with_width(4).with_height(4))
+            .unwrap();
+        enc.encode(data).unwrap();
+        enc.encode(Trailer::default()).unwrap();
+        enc.into_inner()
+    }
+
+    #[test]
+    fn lzw_bomb_strict() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        let gif = lzw_bomb();
+        assert!(gif.len() < 1 << 14);
+        let res: Result<Vec<Block>> =
+            Decoder::new(&gif[..]).into_blocks().collect();
+        match res {
+            Err(Error::InvalidLzwData) => (),
+            _ => panic!("expected InvalidLzwData"),
+        }
+    }
+
+    #[test]
+    fn lzw_bomb_lenient() {
+        use crate::block::Block;
+        let gif = lzw_bomb();
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[3] {
+            Block::ImageData(b) => assert_eq!(b.data(), [0; 16]),
+            _ => panic!("expected ImageData"),
+        }
+    }
+
+    #[test]
+    fn lzw_overrun_peak() {
+        use crate::block::*;
+        use crate::lzw::Compressor;
+        use crate::Encoder;
+        // 2x2 image with 10x the declared data
+        let mut data = ImageData::new_raw(2);
+        Compressor::new(2)
+            .compress_to(&[1, 0, 0, 1].repeat(10), data.data_mut())
+            .unwrap();
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default().with_screen_width(2))
+            .unwrap();
+        enc.encode(ImageDesc::default().with_width(2).with_height(2))
+            .unwrap();
+        enc.encode(data).unwrap();
+        enc.encode(Trailer::default()).unwrap();
+        let gif = enc.into_inner();
+        let mut blocks: Vec<Block> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &mut blocks[3] {
+            Block::ImageData(b) => {
+                assert_eq!(b.data(), [1, 0, 0, 1]);
+                assert!(b.data_mut().capacity() <= 4);
+            }
+            _ => panic!("expected ImageData"),
+        }
+    }
+
+    /// Make a GIF with one comment of many sub-blocks
+    fn long_comment(sub_blocks: usize) -> Vec<u8> {
+        let mut gif = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        gif.extend_from_slice(&[0x21, 0xFE]);
+        for _ in 0..sub_blocks {
+            gif.push(255);
+            gif.extend_from_slice(&[b'A'; 255]);
+        }
+        gif.extend_from_slice(&[0x00, 0x3B]);
+        gif
+    }
+
+    #[test]
+    fn extension_too_large() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        let gif = long_comment(8);
+        let res: Result<Vec<Block>> = Decoder::new(&gif[..])
+            .max_extension_sz(Some(1000))
+            .into_blocks()
+            .collect();
+        assert!(matches!(res, Err(Error::TooLargeExtension)));
+        let res: Result<Vec<_>> = Decoder::new(&gif[..])
+            .max_extension_sz(Some(1000))
+            .into_block_refs()
+            .collect();
+        assert!(matches!(res, Err(Error::TooLargeExtension)));
+        let blocks: Result<Vec<Block>> = Decoder::new(&gif[..])
+            .max_extension_sz(Some(2040))
+            .into_blocks()
+            .collect();
+        assert_eq!(blocks.unwrap().len(), 4);
+        // default limit is 1 MiB
+        let gif = long_comment(4200);
+        let res: Result<Vec<Block>> =
+            Decoder::new(&gif[..]).into_blocks().collect();
+        assert!(matches!(res, Err(Error::TooLargeExtension)));
+        let res: Result<Vec<Block>> = Decoder::new(&gif[..])
+            .max_extension_sz(None)
+            .into_blocks()
+            .collect();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn block_positions() {
+        let mut blocks = Decoder::new(GIF_1).into_block_refs();
+        let mut positions = vec![blocks.position()];
+        while let Some(block) = blocks.next() {
+            block.unwrap();
+            positions.push(blocks.position());
+        }
+        assert_eq!(positions, [0, 6, 13, 25, 33, 43, 68, 69]);
+    }
+
+    /// Make GIF_1 with extension blocks missing their terminators
+    fn missing_terminators() -> Vec<u8> {
+        let mut gif = GIF_1[..25].to_vec();
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00]);
+        gif.extend_from_slice(&[0x21, 0xFE, 0x05]);
+        gif.extend_from_slice(b"hello");
+        // graphic control without terminator
+        gif.extend_from_slice(&GIF_1[25..32]);
+        gif.extend_from_slice(&GIF_1[33..68]);
+        gif.extend_from_slice(&[0x21, 0xFE, 0x03]);
+        gif.extend_from_slice(b"bye");
+        gif.push(0x3B);
+        gif
+    }
+
+    #[test]
+    fn missing_terminators_strict() {
+        let gif = missing_terminators();
+        let res: Result<Vec<_>, _> =
+            Decoder::new(&gif[..]).into_blocks().collect();
+        assert!(res.is_err());
+        let res: Result<Vec<_>, _> =
+            Decoder::new(&gif[..]).into_block_refs().collect();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn block_from_bytes() {
+        use crate::block::*;
+        let mut text = PlainText::default()
+            .with_grid_size(16, 8)
+            .with_cell_size(4, 8);
+        text.add_text(b"text");
+        let mut comment = Comment::default();
+        comment.add_comment(b"a comment");
+        let mut unknown = super::Unknown::new(0x42);
+        unknown.add_sub_block(b"unknown");
+        let mut control = GraphicControl::default();
+        control.set_delay_time_cs(5);
+        let blocks: [Block; 8] = [
+            Header::default().into(),
+            text.into(),
+            control.into(),
+            comment.into(),
+            Application::with_loop_count(3).into(),
+            unknown.into(),
+            ImageDesc::default().with_width(4).with_height(2).into(),
+            Trailer::default().into(),
+        ];
+        for block in blocks {
+            let mut bytes = block.to_bytes().unwrap();
+            let len = bytes.len();
+            // following data is not consumed
+            bytes.extend_from_slice(&[0x2C, 0x00]);
+            let (parsed, consumed) = Block::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed, block);
+            assert_eq!(consumed, len);
+        }
+    }
+
+    #[test]
+    fn block_from_bytes_invalid() {
+        use crate::block::{Block, BlockCode};
+        use crate::error::Error;
+        // logical screen descriptor is not self-describing
+        let res = Block::from_bytes(&GIF_1[6..13]);
+        assert!(matches!(res, Err(Error::InvalidBlockCode)));
+        // truncated image descriptor
+        let res = Block::from_bytes(&GIF_1[33..40]);
+        assert!(matches!(res, Err(Error::UnexpectedEndOfFile)));
+        let res = Block::from_bytes(&[]);
+        assert!(matches!(res, Err(Error::UnexpectedEndOfFile)));
+        let (block, len) = Block::from_bytes(&GIF_1[68..]).unwrap();
+        assert_eq!((block.code(), len), (BlockCode::Trailer_, 1));
+    }
+
+    #[test]
+    fn missing_terminators_lenient() {
+        use crate::block::Block;
+        use crate::decode::BlockRef;
+        let gif = missing_terminators();
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 10);
+        match &blocks[3] {
+            Block::Application(b) => assert_eq!(b.loop_count(), Some(0)),
+            _ => panic!("expected Application"),
+        }
+        match (&blocks[4], &blocks[8]) {
+            (Block::Comment(a), Block::Comment(b)) => {
+                assert_eq!(a.comments().collect::<Vec<_>>(), [b"hello"]);
+                assert_eq!(b.comments().collect::<Vec<_>>(), [b"bye"]);
+            }
+            _ => panic!("expected Comment"),
+        }
+        assert!(matches!(blocks[5], Block::GraphicControl(_)));
+        assert!(matches!(blocks[9], Block::Trailer(_)));
+        let refs: Vec<BlockRef> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_block_refs()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(refs.len(), 10);
+        for (r, b) in refs.into_iter().zip(&blocks) {
+            assert_eq!(&Block::try_from(r).unwrap(), b);
+        }
+        let mut steps = Decoder::new(&gif[..]).lenient(true).into_steps();
+        let step = steps.next().unwrap().unwrap();
+        let expected =
+            Decoder::new(GIF_1).into_steps().next().unwrap().unwrap();
+        assert_eq!(step.raster().pixels(), expected.raster().pixels());
+        assert!(steps.next().is_none());
+    }
+
+    #[test]
+    fn sub_block_like_block_start() {
+        use crate::block::Block;
+        use crate::decode::BlockRef;
+        // ICC profile, with a final 44-byte (0x2C) sub-block which looks
+        // like an image descriptor with a local color table
+        let gif = include_bytes!("../tests/fixtures/icc_profile.gif");
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 8);
+        match &blocks[3] {
+            Block::Application(b) => {
+                let lens: Vec<_> = b.app_data().map(|d| d.len()).collect();
+                assert_eq!(lens, [11, 255, 255, 44]);
+            }
+            _ => panic!("expected Application"),
+        }
+        let refs: Vec<BlockRef> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_block_refs()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(refs.len(), 8);
+        for (r, b) in refs.into_iter().zip(&blocks) {
+            assert_eq!(&Block::try_from(r).unwrap(), b);
+        }
+        let report = crate::validate(&gif[..]).unwrap();
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+    }
+
+    #[test]
+    fn extension_too_large_lenient() {
+        use crate::block::Block;
+        use crate::decode::BlockRef;
+        let gif = long_comment(8);
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .max_extension_sz(Some(1000))
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 4);
+        match &blocks[2] {
+            Block::Comment(b) => assert_eq!(b.comments().count(), 3),
+            _ => panic!("expected Comment"),
+        }
+        let blocks: Vec<BlockRef> = Decoder::new(&gif[..])
+            .max_extension_sz(Some(1000))
+            .lenient(true)
+            .into_block_refs()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 4);
+        match &blocks[2] {
+            BlockRef::Comment(b) => assert_eq!(b.iter().count(), 3),
+            _ => panic!("expected Comment"),
+        }
+    }
+
+    /// Make a GIF with comments before and between frames
+    fn interleaved_comments() -> Vec<u8> {
+        use crate::block::*;
+        use crate::Encoder;
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        let mut comment = Comment::default();
+        comment.add_comment(b"first ");
+        comment.add_comment(b"comment");
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default().with_screen_width(2))
+            .unwrap();
+        enc.encode(comment).unwrap();
+        let mut comment = Comment::default();
+        comment.add_comment(b"caf\xC3\xA9 \xFF");
+        enc.encode(comment).unwrap();
+        for text in ["between", "last"] {
+            enc.encode(ImageDesc::default().with_width(2).with_height(1))
+                .unwrap();
+            let mut data = ImageData::new(2);
+            data.data_mut().extend_from_slice(&[0, 1]);
+            enc.encode(data).unwrap();
+            let mut comment = Comment::default();
+            comment.set_text(text);
+            enc.encode(comment).unwrap();
+        }
+        enc.encode(Trailer::default()).unwrap();
+        enc.into_inner()
+    }
+
+    #[test]
+    fn comments() {
+        let gif = interleaved_comments();
+        let comments: Vec<String> = Decoder::new(&gif[..])
+            .comments()
+            .map(|c| c.unwrap())
+            .collect();
+        assert_eq!(comments, ["first comment", "caf\u{e9} \u{FFFD}"]);
+        let comments: Vec<String> = Decoder::new(&gif[..])
+            .comments()
+            .include_interleaved(true)
+            .map(|c| c.unwrap())
+            .collect();
+        assert_eq!(
+            comments,
+            ["first comment", "caf\u{e9} \u{FFFD}", "between", "last"]
+        );
+        // stops before corrupt image data
+        let mut gif = gif;
+        let pos = gif.iter().position(|b| *b == 0x2C).unwrap();
+        gif.truncate(pos + 12);
+        let comments: Vec<_> = Decoder::new(&gif[..]).comments().collect();
+        assert_eq!(comments.len(), 2);
+        assert!(comments.iter().all(|c| c.is_ok()));
+        assert_eq!(Decoder::new(GIF_1).comments().count(), 0);
+    }
+
+    /// Make a GIF with a loop count extension before or after the frame
+    fn loop_count_gif(after_frame: bool) -> Vec<u8> {
+        use crate::block::*;
+        use crate::Encoder;
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default().with_screen_width(2))
+            .unwrap();
+        if !after_frame {
+            enc.encode(Application::with_loop_count(5)).unwrap();
+        }
+        enc.encode(ImageDesc::default().with_width(2).with_height(1))
+            .unwrap();
+        let mut data = ImageData::new(2);
+        data.data_mut().extend_from_slice(&[0, 1]);
+        enc.encode(data).unwrap();
+        if after_frame {
+            enc.encode(Application::with_loop_count(0)).unwrap();
+        }
+        enc.encode(Trailer::default()).unwrap();
+        enc.into_inner()
+    }
+
+    #[test]
+    fn loop_count() {
+        assert_eq!(Decoder::new(GIF_1).loop_count().unwrap(), None);
+        assert_eq!(Decoder::new(GIF_1).scan_loop_count().unwrap(), None);
+        let gif = loop_count_gif(false);
+        assert_eq!(Decoder::new(&gif[..]).loop_count().unwrap(), Some(5));
+        assert_eq!(Decoder::new(&gif[..]).scan_loop_count().unwrap(), Some(5));
+        let gif = loop_count_gif(true);
+        assert_eq!(Decoder::new(&gif[..]).loop_count().unwrap(), None);
+        assert_eq!(Decoder::new(&gif[..]).scan_loop_count().unwrap(), Some(0));
+    }
+
+    #[test]
+    fn metadata() {
+        use super::Metadata;
+        use crate::block::Version;
+        use std::time::Duration;
+        let gif = include_bytes!("../res/gift_logo.gif");
+        let meta = Decoder::new(&gif[..]).metadata().unwrap();
+        assert_eq!(
+            meta,
+            Metadata {
+                version: Version::Gif89a,
+                screen_width: 64,
+                screen_height: 64,
+                global_palette_len: 16,
+                background_color_idx: 10,
+                loop_count: Some(0),
+                frame_count: 22,
+                duration: Duration::from_millis(24_400),
+                interlaced: false,
+                comment_count: 1,
+            }
+        );
+        let meta = Decoder::new(GIF_1).metadata().unwrap();
+        assert_eq!(meta.global_palette_len, 4);
+        assert_eq!(meta.loop_count, None);
+        assert_eq!(meta.frame_count, 1);
+        assert_eq!(meta.duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn delay_policy() {
+        use super::DelayPolicy;
+        use std::time::Duration;
+        for (delay, browser) in [(0, 10), (1, 10), (10, 10)] {
+            let mut gif = GIF_1.to_vec();
+            gif[29] = delay;
+            let step = Decoder::new(&gif[..])
+                .into_steps()
+                .delay_policy(DelayPolicy::Browser { min_cs: 10 })
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(step.delay_time_cs(), Some(browser));
+            assert_eq!(step.delay(), Some(Duration::from_millis(100)));
+            let raw = step.graphic_control().unwrap().delay_time_cs();
+            assert_eq!(raw, u16::from(delay));
+            for step in Decoder::new(&gif[..]) {
+                let step = step.unwrap();
+                assert_eq!(step.delay_time_cs(), Some(u16::from(delay)));
+            }
+        }
+    }
+
+    /// Downsample a raster by sampling every Nth pixel and row
+    fn downsample(raster: &Raster<SRgba8>, skip: u32) -> Raster<SRgba8> {
+        let width = raster.width().div_ceil(skip);
+        let height = raster.height().div_ceil(skip);
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = ((x * skip) as i32, (y * skip) as i32);
+                pixels.push(raster.pixel(x, y));
+            }
+        }
+        Raster::with_pixels(width, height, pixels)
+    }
+
+    fn check_scaled(gif: &[u8], max: u16, skip: u32) {
+        let full = Decoder::new(gif).into_steps();
+        let scaled = Decoder::new(gif).into_steps().scaled(max, max);
+        let mut n_steps = 0;
+        for (full, scaled) in full.zip(scaled) {
+            let (full, scaled) = (full.unwrap(), scaled.unwrap());
+            let expected = downsample(&full.raster(), skip);
+            assert!(scaled.raster().width() <= max.into());
+            assert!(scaled.raster().height() <= max.into());
+            assert_eq!(scaled.raster().width(), expected.width());
+            assert_eq!(scaled.raster().height(), expected.height());
+            assert_eq!(scaled.raster().pixels(), expected.pixels());
+            n_steps += 1;
+        }
+        assert_eq!(n_steps, Decoder::new(gif).into_steps().count());
+    }
+
+    #[test]
+    fn scaled() {
+        check_scaled(GIF_1, 10, 1);
+        check_scaled(GIF_1, 4, 3);
+        check_scaled(GIF_1, 2, 5);
+        check_scaled(GIF_1, 1, 10);
+        let logo = include_bytes!("../res/gift_logo.gif");
+        check_scaled(logo, 64, 1);
+        check_scaled(logo, 32, 2);
+        check_scaled(logo, 20, 4);
+        check_scaled(logo, 9, 8);
+        let step = Decoder::new(GIF_1)
+            .into_steps()
+            .scaled(4, 4)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(step.region(), Region::new(0, 0, 4, 4));
+    }
+
+    fn check_cropped(gif: &[u8], crop: Region) {
+        let full = Decoder::new(gif).into_steps();
+        let cropped = Decoder::new(gif).into_steps().cropped(crop);
+        let mut n_steps = 0;
+        for (full, cropped) in full.zip(cropped) {
+            let (full, cropped) = (full.unwrap(), cropped.unwrap());
+            let full = full.raster();
+            let reg = full.intersection(crop);
+            let mut expected = Vec::new();
+            for y in reg.y..reg.y + reg.height as i32 {
+                for x in reg.x..reg.x + reg.width as i32 {
+                    expected.push(full.pixel(x, y));
+                }
+            }
+            assert_eq!(cropped.raster().width(), reg.width);
+            assert_eq!(cropped.raster().height(), reg.height);
+            assert_eq!(cropped.raster().pixels(), &expected[..]);
+            n_steps += 1;
+        }
+        assert_eq!(n_steps, Decoder::new(gif).into_steps().count());
+    }
+
+    #[test]
+    fn cropped() {
+        check_cropped(GIF_1, Region::new(0, 0, 10, 10));
+        check_cropped(GIF_1, Region::new(3, 2, 5, 7));
+        check_cropped(GIF_1, Region::new(5, 5, 20, 20));
+        let logo = include_bytes!("../res/gift_logo.gif");
+        check_cropped(logo, Region::new(32, 32, 32, 32));
+        check_cropped(logo, Region::new(21, 7, 13, 40));
+        check_cropped(logo, Region::new(60, 0, 4, 4));
+        // crop then scale
+        let step = Decoder::new(GIF_1)
+            .into_steps()
+            .cropped(Region::new(1, 1, 6, 6))
+            .scaled(3, 3)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(step.raster().width(), 3);
+        assert_eq!(step.raster().height(), 3);
+    }
+
+    #[test]
+    fn skip_steps() {
+        let logo = include_bytes!("../res/gift_logo.gif");
+        let n_steps = Decoder::new(&logo[..]).into_steps().count();
+        for n in 0..n_steps {
+            let mut steps = Decoder::new(&logo[..]).into_steps();
+            assert_eq!(steps.skip_steps(n).unwrap(), n);
+            let skipped = steps.next().unwrap().unwrap();
+            let step = Decoder::new(&logo[..])
+                .into_steps()
+                .nth(n)
+                .unwrap()
+                .unwrap();
+            assert_eq!(skipped.raster().pixels(), step.raster().pixels());
+            assert_eq!(skipped.region(), step.region());
+            assert_eq!(skipped.delay_time_cs(), step.delay_time_cs());
+        }
+        let mut steps = Decoder::new(&logo[..]).into_steps();
+        assert_eq!(steps.skip_steps(n_steps + 5).unwrap(), n_steps);
+        // looping steps wrap around
+        let mut steps = Decoder::new(&logo[..]).into_iter();
+        assert_eq!(steps.skip_steps(n_steps + 1).unwrap(), n_steps + 1);
+        let skipped = steps.next().unwrap().unwrap();
+        let step = Decoder::new(&logo[..])
+            .into_steps()
+            .nth(1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(skipped.raster().pixels(), step.raster().pixels());
+    }
+
+    #[test]
+    fn last_step() {
+        let logo = include_bytes!("../res/gift_logo.gif");
+        assert_eq!(gce_offsets(logo).len(), 22);
+        // mix disposal methods, rotating which one the last frame has
+        let mut gifs = vec![logo.to_vec()];
+        for rot in 0..3 {
+            let mut gif = logo.to_vec();
+            for (i, offset) in gce_offsets(logo).into_iter().enumerate() {
+                let d = ((i + rot) % 3 + 1) as u8;
+                gif[offset] = (gif[offset] & !0x1C) | (d << 2);
+            }
+            gifs.push(gif);
+        }
+        for gif in gifs {
+            let last = Decoder::new(&gif[..]).into_steps().last();
+            let last = last.unwrap().unwrap();
+            let step = Decoder::new(&gif[..]).into_steps().last_step();
+            let step = step.unwrap().unwrap();
+            assert_eq!(step.raster().pixels(), last.raster().pixels());
+            assert_eq!(step.region(), last.region());
+            assert_eq!(step.delay_time_cs(), last.delay_time_cs());
+            let step = Decoder::new(&gif[..]).into_iter().last_step();
+            let step = step.unwrap().unwrap();
+            assert_eq!(step.raster().pixels(), last.raster().pixels());
+        }
+        assert!(Decoder::new(&GIF_1[..13]).into_steps().last_step().is_err());
+    }
+
+    /// Get offsets of packed fields in graphic control extensions
+    fn gce_offsets(gif: &[u8]) -> Vec<usize> {
+        gif.windows(3)
+            .enumerate()
+            .filter(|(_, w)| w == &[0x21, 0xF9, 0x04])
+            .map(|(i, _)| i + 3)
+            .collect()
+    }
+
+    #[test]
+    fn frames_preamble() {
+        let mut frames = Decoder::new(GIF_1).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.screen_width(), 10);
+        assert_eq!(frames.preamble().unwrap().unwrap(), preamble);
+        let mut frames = Decoder::new(GIF_1).into_frames();
+        frames.next().unwrap().unwrap();
+        assert_eq!(frames.preamble().unwrap().unwrap(), preamble);
+        assert!(frames.next().is_none());
+        assert_eq!(frames.preamble().unwrap().unwrap(), preamble);
+    }
+
+    #[test]
+    fn frames_preamble_no_frames() {
+        let mut gif = GIF_1[..25].to_vec();
+        gif.push(0x3B);
+        let mut frames = Decoder::new(&gif[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.screen_height(), 10);
+        assert!(frames.next().is_none());
+        assert!(Decoder::new(&GIF_1[..5]).into_frames().preamble().is_err());
+    }
+
+    #[test]
+    fn trailing_data() {
+        use crate::error::Error;
+        let mut gif = GIF_1.to_vec();
+        gif.extend_from_slice(&[0x55; 100]);
+        assert!(Decoder::new(&gif[..]).into_frames().all(|f| f.is_ok()));
+        let frames = Decoder::new(&gif[..]).deny_trailing_data(true);
+        match frames.into_frames().last() {
+            Some(Err(Error::TrailingData { bytes: 100 })) => (),
+            _ => panic!(),
+        }
+        let frames = Decoder::new(&gif[..])
+            .deny_trailing_data(true)
+            .lenient(true);
+        assert!(frames.into_frames().all(|f| f.is_ok()));
+        let frames = Decoder::new(GIF_1).deny_trailing_data(true);
+        assert!(frames.into_frames().all(|f| f.is_ok()));
+    }
+
+    #[test]
+    fn frame_to_raster() {
+        let step = Decoder::new(GIF_1).into_steps().next().unwrap().unwrap();
+        for raw in [false, true] {
+            let mut frames =
+                Decoder::new(GIF_1).raw_image_data(raw).into_frames();
+            let preamble = frames.preamble().unwrap().unwrap();
+            let tbl = preamble.global_color_table.as_ref();
+            let frame = frames.next().unwrap().unwrap();
+            assert_eq!(frame.is_raw(), raw);
+            let raster = frame.to_raster(tbl).unwrap();
+            assert_eq!(raster.pixels(), step.raster().pixels());
+            let (raster, palette) = frame.to_indexed(tbl).unwrap();
+            assert_eq!(raster.as_u8_slice(), IMAGE_1);
+            assert_eq!(palette.len(), 4);
+            assert!(frame.to_raster(None).is_err());
+        }
+    }
+
+    #[test]
+    fn frame_color_usage() {
+        let frame = Decoder::new(GIF_1).into_frames().next().unwrap().unwrap();
+        assert_eq!(frame.image_data.max_index(), Some(2));
+        let usage = frame.color_usage().unwrap();
+        assert_eq!(usage.count(0), 16);
+        assert_eq!(usage.count(1), 42);
+        assert_eq!(usage.count(2), 42);
+        assert_eq!(usage.count(3), 0);
+        assert_eq!(usage.distinct(), 3);
+        assert_eq!(usage.most_common(), Some(1));
+        assert_eq!(usage.max_index(), Some(2));
+        let counts: Vec<_> = usage.iter().collect();
+        assert_eq!(counts, [(0, 16), (1, 42), (2, 42)]);
+    }
+
     #[test]
     fn iterator() {
+        use crate::block::Version;
         use crate::error::Error;
         let mut dec = Decoder::new(HEADER).into_blocks();
         match dec.next().unwrap() {
-            Err(Error::UnsupportedVersion(_)) => (),
+            Err(Error::UnsupportedVersion(Version::Other(v))) => {
+                assert_eq!(&v, b"89`")
+            }
             _ => panic!(),
         }
         match dec.next() {
@@ -979,6 +3177,391 @@ mod test {
         }
     }
 
+    #[test]
+    fn block_refs() {
+        use crate::block::Block;
+        use crate::block::Comment;
+        use crate::Encoder;
+        let mut gif = vec![];
+        let mut enc = Encoder::new(&mut gif).into_block_enc();
+        for block in Decoder::new(GIF_1).into_blocks() {
+            let block = block.unwrap();
+            if let Block::ImageDesc(_) = block {
+                let mut c = Comment::default();
+                c.add_comment(b"zero copy");
+                enc.encode(c).unwrap();
+            }
+            enc.encode(block).unwrap();
+        }
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        let refs: Vec<Block> = Decoder::new(&gif[..])
+            .into_block_refs()
+            .map(|b| Block::try_from(b.unwrap()).unwrap())
+            .collect();
+        assert_eq!(blocks, refs);
+        let comment = Decoder::new(&gif[..])
+            .into_block_refs()
+            .find_map(|b| match b.unwrap() {
+                super::BlockRef::Comment(c) => Some(c),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(comment.iter().collect::<Vec<_>>(), [&b"zero copy"[..]]);
+    }
+
+    #[test]
+    fn block_refs_truncated() {
+        use crate::error::Error;
+        let dec = Decoder::new(&GIF_1[..30]).into_block_refs();
+        let res: Vec<_> = dec.collect();
+        match res.last().unwrap() {
+            Err(Error::UnexpectedEndOfFile) => (),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn image_data_size() {
+        use crate::error::Error;
+        // exact fit
+        assert!(Decoder::new(GIF_1).into_frames().all(|f| f.is_ok()));
+        // underflow: frame taller than image data
+        let mut gif = GIF_1.to_vec();
+        gif[8] = 11;
+        gif[40] = 11;
+        let frame = Decoder::new(&gif[..]).into_frames().next().unwrap();
+        assert!(matches!(frame, Err(Error::IncompleteImageData)));
+        // overflow: frame shorter than image data
+        gif[40] = 9;
+        let frame = Decoder::new(&gif[..]).into_frames().next().unwrap();
+        assert!(matches!(frame, Err(Error::InvalidLzwData)));
+        let mut frames = Decoder::new(&gif[..]).lenient(true).into_frames();
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.image_data.data().len(), 90);
+    }
+
+    /// Encode a solid 32x32 step followed by a noisy one, truncated within
+    /// the second sub-block of its image data
+    fn truncated_noisy() -> Vec<u8> {
+        use crate::{Encoder, Step};
+        use pix::{gray::Gray8, rgb::SRgb8, Palette};
+        let mut palette = Palette::new(16);
+        for i in 0..16 {
+            palette.set_entry(SRgb8::new(i * 16, 0xFF, 0));
+        }
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        let raster = Raster::with_color(32, 32, Gray8::new(1));
+        enc.encode_step(&Step::with_indexed(raster, palette.clone()))
+            .unwrap();
+        let mut raster = Raster::<Gray8>::with_clear(32, 32);
+        for y in 0..32 {
+            for x in 0..32 {
+                let v = (x * 7 + y * 13 + x * y) % 16;
+                *raster.pixel_mut(x, y) = Gray8::new(v as u8);
+            }
+        }
+        enc.encode_step(&Step::with_indexed(raster, palette))
+            .unwrap();
+        drop(enc);
+        let mut blocks = Decoder::new(&bytes[..]).into_block_refs();
+        let mut start = 0;
+        let mut image_data = 0;
+        while let Some(block) = blocks.next() {
+            if let super::BlockRef::ImageData(_) = block.unwrap() {
+                image_data = start;
+            }
+            start = blocks.position();
+        }
+        // LZW minimum code size, then one full sub-block
+        bytes.truncate(image_data + 1 + 256 + 10);
+        bytes
+    }
+
+    #[test]
+    fn partial_frame() {
+        use crate::error::Error;
+        let gif = truncated_noisy();
+        let mut frames = Decoder::new(&gif[..]).into_frames();
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.take_partial().is_none());
+        let frame = frames.next().unwrap();
+        assert!(matches!(frame, Err(Error::UnexpectedEndOfFile)));
+        let partial = frames.take_partial().unwrap();
+        assert_eq!(partial.image_desc.width(), 32);
+        assert_eq!(partial.image_data.data().len(), 444);
+        assert_eq!(partial.rows(), 13);
+        assert!(frames.take_partial().is_none());
+    }
+
+    #[test]
+    fn partial_step() {
+        let gif = truncated_noisy();
+        let solid = SRgba8::new(16, 0xFF, 0, 0xFF);
+        for mut steps in [
+            Decoder::new(&gif[..]).into_steps(),
+            Decoder::new(&gif[..]).into_iter(),
+        ] {
+            let results: Vec<_> = steps.by_ref().collect();
+            assert_eq!(results.len(), 2);
+            assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+            let step = steps.take_partial().unwrap();
+            let raster = step.raster();
+            // first row of noisy frame decoded
+            assert_eq!(raster.pixel(1, 0), SRgba8::new(7 * 16, 0xFF, 0, 0xFF));
+            assert_eq!(
+                raster.pixel(1, 12),
+                SRgba8::new(15 * 16, 0xFF, 0, 0xFF)
+            );
+            // remaining rows from last good step
+            assert_eq!(raster.pixel(1, 13), solid);
+            assert_eq!(raster.pixel(31, 31), solid);
+            assert!(steps.take_partial().is_none());
+        }
+    }
+
+    /// Decode a GIF in every way, checking for panics
+    fn decode_all(gif: &[u8]) {
+        for step in Decoder::new(gif).into_steps() {
+            match step {
+                Ok(step) => assert!(step.into_indexed(16).is_ok()),
+                Err(_) => break,
+            }
+        }
+        let frames = Decoder::new(gif).raw_image_data(true).into_frames();
+        for frame in frames {
+            match frame {
+                Ok(frame) => {
+                    let _ = frame.color_usage();
+                    let _ = frame.to_indexed(None);
+                }
+                Err(_) => break,
+            }
+        }
+        for block in Decoder::new(gif).lenient(true).into_block_refs() {
+            match block {
+                Ok(block) => {
+                    let _ = crate::block::Block::try_from(block);
+                }
+                Err(_) => break,
+            }
+        }
+        let _ = crate::validate(gif);
+    }
+
+    #[test]
+    fn fuzz_lzw_first_code() {
+        use crate::error::Error;
+        // clear code, then code 6 (next code) twice
+        let mut gif = GIF_1[..44].to_vec();
+        gif.extend_from_slice(&[0x02, 0xB4, 0x01, 0x00, 0x3B]);
+        decode_all(&gif);
+        let frame = Decoder::new(&gif[..]).into_frames().next().unwrap();
+        assert!(matches!(frame, Err(Error::InvalidLzwData)));
+    }
+
+    #[test]
+    fn fuzz_zero_width() {
+        let mut gif = GIF_1.to_vec();
+        gif[38] = 0;
+        decode_all(&gif);
+    }
+
+    #[test]
+    fn lzw_code_size() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        for code_size in [1, 8, 9, 13] {
+            let mut gif = GIF_1.to_vec();
+            gif[43] = code_size;
+            let valid = (2..=8).contains(&code_size);
+            let res: Result<Vec<Block>> =
+                Decoder::new(&gif[..]).into_blocks().collect();
+            match res {
+                Err(Error::InvalidLzwCodeSize(sz)) => {
+                    assert!(!valid);
+                    assert_eq!(sz, code_size);
+                }
+                _ => assert!(valid),
+            }
+            let res: Result<Vec<Block>> =
+                Decoder::new(&gif[..]).lenient(true).into_blocks().collect();
+            assert!(!matches!(res, Err(Error::InvalidLzwCodeSize(_))));
+        }
+    }
+
+    #[test]
+    fn lzw_code_size_9() {
+        use crate::block::*;
+        use crate::lzw::Compressor;
+        use crate::Encoder;
+        // 256-color image written with a code size of 9
+        let pixels: Vec<u8> = (0..=255).collect();
+        let mut data = ImageData::new_raw(9);
+        Compressor::new(8)
+            .compress_to(&pixels, data.data_mut())
+            .unwrap();
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default().with_screen_width(16))
+            .unwrap();
+        enc.encode(ImageDesc::default().with_width(16).with_height(16))
+            .unwrap();
+        enc.encode(data).unwrap();
+        enc.encode(Trailer::default()).unwrap();
+        let gif = enc.into_inner();
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[3] {
+            Block::ImageData(b) => assert_eq!(b.data(), pixels),
+            _ => panic!("expected ImageData"),
+        }
+    }
+
+    #[test]
+    fn color_table_not_present() {
+        use crate::block::Block;
+        // size bits set without global color table
+        let mut gif = GIF_1[..13].to_vec();
+        gif[10] = 0x11;
+        gif.extend_from_slice(&GIF_1[25..]);
+        let blocks: Vec<Block> = Decoder::new(&gif[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 6);
+        assert!(!blocks
+            .iter()
+            .any(|b| matches!(b, Block::GlobalColorTable(_))));
+    }
+
+    #[test]
+    fn global_color_table_truncated() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        let gif = &GIF_1[..20];
+        let res: Result<Vec<Block>> = Decoder::new(gif).into_blocks().collect();
+        assert!(matches!(
+            res,
+            Err(Error::TruncatedColorTable {
+                expected: 12,
+                actual: 7
+            })
+        ));
+        let res: Result<Vec<_>> = Decoder::new(gif).into_block_refs().collect();
+        assert!(matches!(
+            res,
+            Err(Error::TruncatedColorTable {
+                expected: 12,
+                actual: 7
+            })
+        ));
+        let mut blocks = Decoder::new(gif).lenient(true).into_blocks();
+        blocks.next().unwrap().unwrap();
+        blocks.next().unwrap().unwrap();
+        match blocks.next().unwrap().unwrap() {
+            Block::GlobalColorTable(b) => assert_eq!(
+                b.colors(),
+                [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0]
+            ),
+            _ => panic!("expected GlobalColorTable"),
+        }
+        assert!(matches!(
+            blocks.next(),
+            Some(Err(Error::UnexpectedEndOfFile))
+        ));
+    }
+
+    #[test]
+    fn local_color_table_truncated() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        let mut gif = GIF_1[..43].to_vec();
+        gif[42] = 0x81;
+        gif.extend_from_slice(&[0x00, 0xFF, 0x00, 0x00, 0xFF]);
+        let res: Result<Vec<Block>> =
+            Decoder::new(&gif[..]).into_blocks().collect();
+        assert!(matches!(
+            res,
+            Err(Error::TruncatedColorTable {
+                expected: 12,
+                actual: 5
+            })
+        ));
+        let blocks: Vec<Result<Block>> =
+            Decoder::new(&gif[..]).lenient(true).into_blocks().collect();
+        assert_eq!(blocks.len(), 7);
+        match &blocks[5] {
+            Ok(Block::LocalColorTable(b)) => assert_eq!(
+                b.colors(),
+                [0x00, 0xFF, 0x00, 0x00, 0xFF, 0, 0, 0, 0, 0, 0, 0]
+            ),
+            _ => panic!("expected LocalColorTable"),
+        }
+        assert!(matches!(blocks[6], Err(Error::UnexpectedEndOfFile)));
+    }
+
+    #[test]
+    fn max_screen_sz() {
+        use crate::block::Block;
+        use crate::error::{Error, Result};
+        // 10x10 screen composited as RGBA
+        let res: Result<Vec<Block>> = Decoder::new(GIF_1)
+            .max_screen_sz(Some(399))
+            .into_blocks()
+            .collect();
+        assert!(matches!(res, Err(Error::TooLargeImage)));
+        let res: Result<Vec<Block>> = Decoder::new(GIF_1)
+            .max_screen_sz(Some(400))
+            .max_image_sz(Some(100))
+            .into_blocks()
+            .collect();
+        assert_eq!(res.unwrap().len(), 7);
+        let mut gif = GIF_1.to_vec();
+        gif[6..10].copy_from_slice(&[0xFF; 4]);
+        let res: Result<Vec<Block>> = Decoder::new(&gif[..])
+            .max_image_sz(None)
+            .into_blocks()
+            .collect();
+        assert!(matches!(res, Err(Error::TooLargeImage)));
+    }
+
+    #[test]
+    fn fuzz_screen_size() {
+        use crate::error::Error;
+        let mut gif = GIF_1.to_vec();
+        gif[6..10].copy_from_slice(&[0xFF; 4]);
+        decode_all(&gif);
+        let step = Decoder::new(&gif[..]).into_steps().next().unwrap();
+        assert!(matches!(step, Err(Error::TooLargeImage)));
+    }
+
+    #[test]
+    fn fuzz_mutations() {
+        let mut seed = 0x2545_F491_4F6C_DD1D_u64;
+        let mut rand = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        for _ in 0..2000 {
+            let mut gif = GIF_1.to_vec();
+            for _ in 0..1 + rand() % 4 {
+                let pos = rand() % gif.len();
+                gif[pos] = rand() as u8;
+            }
+            let len = gif.len() - rand() % 8;
+            decode_all(&gif[..len]);
+        }
+    }
+
     #[test]
     fn empty() {
         use crate::error::Error;
@@ -992,4 +3575,151 @@ mod test {
             _ => panic!(),
         }
     }
+
+    /// Make a 4x4 GIF with a second frame extending 2 pixels past the right
+    /// edge of the screen
+    fn oversize_gif() -> Vec<u8> {
+        use crate::block::*;
+        use crate::Encoder;
+        let config = ColorTableConfig::new(
+            ColorTableExistence::Present,
+            ColorTableOrdering::NotSorted,
+            2,
+        );
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(
+            LogicalScreenDesc::default()
+                .with_screen_width(4)
+                .with_screen_height(4)
+                .with_color_table_config(config),
+        )
+        .unwrap();
+        enc.encode(GlobalColorTable::with_colors(&[0, 0, 0, 255, 255, 255]))
+            .unwrap();
+        for (left, top, width, height, idx) in
+            [(0, 0, 4, 4, 0), (2, 1, 4, 2, 1)]
+        {
+            enc.encode(
+                ImageDesc::default()
+                    .with_left(left)
+                    .with_top(top)
+                    .with_width(width)
+                    .with_height(height),
+            )
+            .unwrap();
+            let mut data = ImageData::new(16);
+            data.data_mut().resize(usize::from(width * height), idx);
+            enc.encode(data).unwrap();
+        }
+        enc.encode(Trailer::default()).unwrap();
+        enc.into_inner()
+    }
+
+    #[test]
+    fn oversize_error() {
+        use crate::error::Error;
+        let gif = oversize_gif();
+        let mut steps = Decoder::new(&gif[..]).into_steps();
+        assert!(steps.next().unwrap().is_ok());
+        let step = steps.next().unwrap();
+        assert!(matches!(step, Err(Error::InvalidFrameDimensions)));
+    }
+
+    #[test]
+    fn oversize_clip() {
+        use super::Oversize;
+        let gif = oversize_gif();
+        let (black, white) =
+            (SRgba8::new(0, 0, 0, 255), SRgba8::new(255, 255, 255, 255));
+        let steps: Vec<_> = Decoder::new(&gif[..])
+            .oversize_frames(Oversize::Clip)
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(steps.len(), 2);
+        let raster = steps[1].raster();
+        assert_eq!((raster.width(), raster.height()), (4, 4));
+        for (x, y) in [(2, 1), (3, 1), (2, 2), (3, 2)] {
+            assert_eq!(raster.pixel(x, y), white);
+        }
+        assert_eq!(raster.pixel(1, 1), black);
+        assert_eq!(raster.pixel(3, 3), black);
+        assert_eq!(steps[1].region(), Region::new(2, 1, 2, 2));
+    }
+
+    #[test]
+    fn oversize_expand() {
+        use super::Oversize;
+        let gif = oversize_gif();
+        let white = SRgba8::new(255, 255, 255, 255);
+        let dec = || Decoder::new(&gif[..]).oversize_frames(Oversize::Expand);
+        let steps: Vec<_> = dec().into_steps().map(|s| s.unwrap()).collect();
+        assert_eq!(steps[0].raster().width(), 4);
+        let raster = steps[1].raster();
+        assert_eq!((raster.width(), raster.height()), (6, 4));
+        assert_eq!(raster.pixel(5, 2), white);
+        assert_eq!(raster.pixel(0, 0), SRgba8::new(0, 0, 0, 255));
+        assert_eq!(raster.pixel(5, 0), SRgba8::default());
+        let last = dec().into_steps().last_step().unwrap().unwrap();
+        assert_eq!(last.raster().pixels(), raster.pixels());
+        let looping = dec().into_iter().nth(1).unwrap().unwrap();
+        assert_eq!(looping.raster().pixels(), raster.pixels());
+        // expanded screen is limited
+        let mut steps = dec().max_screen_sz(Some(4 * 4 * 4)).into_steps();
+        assert!(steps.next().unwrap().is_ok());
+        assert!(steps.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn missing_color_table() {
+        use crate::block::*;
+        use crate::error::Error;
+        use crate::Encoder;
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(
+            LogicalScreenDesc::default()
+                .with_screen_width(2)
+                .with_screen_height(2),
+        )
+        .unwrap();
+        enc.encode(ImageDesc::default().with_width(2).with_height(2))
+            .unwrap();
+        let mut data = ImageData::new(4);
+        data.data_mut().extend_from_slice(&[0, 85, 170, 255]);
+        enc.encode(data).unwrap();
+        enc.encode(Trailer::default()).unwrap();
+        let gif = enc.into_inner();
+        let step = Decoder::new(&gif[..]).into_steps().next().unwrap();
+        assert!(matches!(step, Err(Error::MissingColorTable)));
+        let step = Decoder::new(&gif[..])
+            .lenient(true)
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap();
+        let gray: Vec<_> = [0, 85, 170, 255]
+            .iter()
+            .map(|v| SRgba8::new(*v, *v, *v, 255))
+            .collect();
+        assert_eq!(step.raster().pixels(), gray);
+    }
+
+    #[test]
+    fn background_color() {
+        use pix::rgb::SRgb8;
+        let mut frames = Decoder::new(GIF_1).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let bg = preamble.background_color();
+        assert_eq!(bg, Some(SRgb8::new(0xFF, 0xFF, 0xFF)));
+        // background color index out of range
+        let mut gif = GIF_1.to_vec();
+        gif[11] = 200;
+        let mut frames = Decoder::new(&gif[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.background_color(), None);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(Decoder::new(&gif[..]).into_steps().all(|s| s.is_ok()));
+    }
 }
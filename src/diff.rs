@@ -0,0 +1,169 @@
+// diff.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! Differences between composited rasters
+use crate::decode::Steps;
+use crate::error::Result;
+use crate::private::Step;
+use pix::{el::Pixel, rgb::SRgba8, Raster, Region};
+use std::io::Read;
+
+/// Get the region which differs between two rasters.
+///
+/// Returns `None` when the rasters are identical.  Fully transparent pixels
+/// are considered equal, regardless of their color channels.  If the rasters
+/// have different dimensions, the whole `next` raster is dirty.
+///
+/// ## Example
+/// ```
+/// use gift::diff::dirty_region;
+/// use pix::{rgb::SRgba8, Raster, Region};
+///
+/// let prev = Raster::<SRgba8>::with_clear(8, 8);
+/// let mut next = Raster::with_raster(&prev);
+/// *next.pixel_mut(2, 3) = SRgba8::new(255, 0, 0, 255);
+/// *next.pixel_mut(4, 5) = SRgba8::new(0, 255, 0, 255);
+/// assert_eq!(dirty_region(&prev, &next), Some(Region::new(2, 3, 3, 3)));
+/// assert_eq!(dirty_region(&prev, &prev), None);
+/// ```
+pub fn dirty_region(
+    prev: &Raster<SRgba8>,
+    next: &Raster<SRgba8>,
+) -> Option<Region> {
+    let width = next.width() as usize;
+    if prev.width() != next.width() || prev.height() != next.height() {
+        return Some(next.region());
+    }
+    if width == 0 {
+        return None;
+    }
+    let mut rows = None;
+    let mut cols = (width, 0);
+    let prows = prev.pixels().chunks_exact(width);
+    let nrows = next.pixels().chunks_exact(width);
+    for (y, (prow, nrow)) in prows.zip(nrows).enumerate() {
+        let Some(first) = (0..width).find(|&x| !same(prow[x], nrow[x])) else {
+            continue;
+        };
+        // `first` differs, so a last differing column exists
+        let last = (first..width).rev().find(|&x| !same(prow[x], nrow[x]));
+        let last = last.unwrap_or(first);
+        cols = (cols.0.min(first), cols.1.max(last));
+        rows = match rows {
+            Some((top, _)) => Some((top, y)),
+            None => Some((y, y)),
+        };
+    }
+    let (top, bottom) = rows?;
+    Some(Region::new(
+        cols.0 as i32,
+        top as i32,
+        (cols.1 - cols.0 + 1) as u32,
+        (bottom - top + 1) as u32,
+    ))
+}
+
+/// Check if two pixels are the same (all transparent pixels are equal)
+fn same(p: SRgba8, q: SRgba8) -> bool {
+    p == q || (u8::from(p.alpha()) == 0 && u8::from(q.alpha()) == 0)
+}
+
+/// An Iterator for [Step]s with their dirty regions.
+///
+/// Build with Steps.[with_dirty_regions].  Each item contains a step and
+/// the region of its raster which differs from the previous step, or `None`
+/// if it is unchanged.  The first step is dirty over its whole raster.
+///
+/// [Step]: ../struct.Step.html
+/// [with_dirty_regions]: ../decode/struct.Steps.html#method.with_dirty_regions
+pub struct DirtyRegions<R: Read> {
+    /// Step iterator
+    steps: Steps<R>,
+    /// Previous composited raster
+    prev: Option<Raster<SRgba8>>,
+}
+
+impl<R: Read> Iterator for DirtyRegions<R> {
+    type Item = Result<(Step, Option<Region>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let step = match self.steps.next()? {
+            Ok(step) => step,
+            Err(e) => return Some(Err(e)),
+        };
+        let raster = step.raster().into_owned();
+        let region = match &self.prev {
+            Some(prev) => dirty_region(prev, &raster),
+            None => Some(raster.region()),
+        };
+        self.prev = Some(raster);
+        Some(Ok((step, region)))
+    }
+}
+
+impl<R: Read> DirtyRegions<R> {
+    /// Create a new dirty region iterator
+    pub(crate) fn new(steps: Steps<R>) -> Self {
+        DirtyRegions { steps, prev: None }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Decoder, Encoder};
+    use pix::{gray::Gray8, rgb::SRgb8, Palette};
+
+    /// Make an animation with a 3x3 block moving one pixel per frame
+    fn moving_block() -> Vec<u8> {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        palette.set_entry(SRgb8::new(255, 255, 255));
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        for x in 0..5 {
+            let mut raster = Raster::<Gray8>::with_clear(8, 6);
+            for y in 2..5 {
+                for x in x..x + 3 {
+                    *raster.pixel_mut(x, y) = Gray8::new(1);
+                }
+            }
+            let step = Step::with_indexed(raster, palette.clone())
+                .with_delay_time_cs(Some(10));
+            enc.encode_step(&step).unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn dirty_regions() {
+        let gif = moving_block();
+        let regions: Vec<_> = Decoder::new(&gif[..])
+            .into_steps()
+            .with_dirty_regions()
+            .map(|r| r.unwrap().1)
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                Some(Region::new(0, 0, 8, 6)),
+                Some(Region::new(0, 2, 4, 3)),
+                Some(Region::new(1, 2, 4, 3)),
+                Some(Region::new(2, 2, 4, 3)),
+                Some(Region::new(3, 2, 4, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn transparent() {
+        let prev = Raster::<SRgba8>::with_clear(4, 4);
+        let mut next = Raster::with_raster(&prev);
+        *next.pixel_mut(1, 1) = SRgba8::new(255, 0, 0, 0);
+        assert_eq!(dirty_region(&prev, &next), None);
+        *next.pixel_mut(2, 1) = SRgba8::new(0, 0, 0, 1);
+        assert_eq!(dirty_region(&prev, &next), Some(Region::new(2, 1, 1, 1)));
+        let next = Raster::<SRgba8>::with_clear(4, 5);
+        assert_eq!(dirty_region(&prev, &next), Some(Region::new(0, 0, 4, 5)));
+    }
+}
@@ -6,10 +6,48 @@
 use crate::block::*;
 use crate::lzw::Compressor;
 use crate::private::StepRaster;
+use crate::quantize::{unused_color, Indexed, Quantizer};
 use crate::{Error, Result, Step};
-use pix::{gray::Gray8, rgb::Rgb, Palette, Raster};
+use pix::{
+    el::Pixel,
+    gray::Gray8,
+    rgb::{SRgb8, SRgba8},
+    Palette, Raster, Region,
+};
 use std::convert::TryInto;
-use std::io::{self, Write};
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Maximum size of steps buffered by `StepEnc::encode_steps` (bytes)
+const MAX_BUFFERED_SZ: usize = 1 << 28;
+
+/// Minimum delay for `StepEnc::with_normalized_delays` (centiseconds)
+const NORMALIZED_DELAY_CS: u16 = 2;
+
+/// Maximum LZW code bits allowed for GIF
+const MAX_LZW_BITS: u8 = 12;
+
+/// Number of LZW codes between table resets for `Compression::Compatible`
+const COMPATIBLE_RESET_INTERVAL: usize = 1024;
+
+/// Compression mode for encoding image data
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Compression {
+    /// Normal LZW compression
+    #[default]
+    Normal,
+    /// Fast encoding, with no compression
+    ///
+    /// Image data is still valid LZW, readable by any decoder, but is larger
+    /// than the uncompressed indices.  This is useful for real-time capture,
+    /// where encoding time matters more than file size.
+    Fast,
+    /// LZW compression, with periodic table resets
+    ///
+    /// The code table is reset often enough that it never fills, for old
+    /// decoders which mishandle a full table.  Output is slightly larger than
+    /// `Normal`.
+    Compatible,
+}
 
 /// Encoder for writing [Block]s into a GIF file.
 ///
@@ -20,12 +58,93 @@ use std::io::{self, Write};
 pub struct BlockEnc<W: Write> {
     /// Writer for output data
     writer: W,
+    /// Compression mode
+    compression: Compression,
+    /// Maximum LZW code bits
+    max_lzw_bits: u8,
+    /// Strict block sequence checking
+    strict: bool,
+    /// Expected next block code and size
+    expected_next: Option<(BlockCode, usize)>,
+    /// Image size of most recent image descriptor
+    image_sz: Option<usize>,
 }
 
 impl<W: Write> BlockEnc<W> {
     /// Create a new GIF encoder.
     pub(crate) fn new(writer: W) -> Self {
-        BlockEnc { writer }
+        BlockEnc {
+            writer,
+            compression: Compression::default(),
+            max_lzw_bits: MAX_LZW_BITS,
+            strict: false,
+            expected_next: Some((
+                BlockCode::Header_,
+                BlockCode::Header_.size(),
+            )),
+            image_sz: None,
+        }
+    }
+
+    /// Set strict block sequence checking.
+    ///
+    /// When enabled, [encode] returns [Error::InvalidBlockSequence] for any
+    /// block out of sequence: a [Header] must be first, followed by a
+    /// [LogicalScreenDesc].  Color tables must match the size declared by the
+    /// preceding descriptor, and [ImageData] must follow an [ImageDesc].
+    ///
+    /// Also, uncompressed [ImageData] must match the size of the preceding
+    /// [ImageDesc], or [Error::ImageDataMismatch] is returned.
+    ///
+    /// [encode]: struct.BlockEnc.html#method.encode
+    /// [Error::ImageDataMismatch]: ../enum.Error.html#variant.ImageDataMismatch
+    /// [Error::InvalidBlockSequence]: ../enum.Error.html#variant.InvalidBlockSequence
+    /// [Header]: ../block/struct.Header.html
+    /// [ImageData]: ../block/struct.ImageData.html
+    /// [ImageDesc]: ../block/struct.ImageDesc.html
+    /// [LogicalScreenDesc]: ../block/struct.LogicalScreenDesc.html
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the compression mode for image data.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the maximum LZW code width for image data.
+    ///
+    /// Some decoders only support codes up to 10 or 11 bits.  The width is
+    /// clamped between 2 and 12 (the default); a clear code is written
+    /// whenever the code table would grow past it.
+    pub fn with_max_lzw_bits(mut self, bits: u8) -> Self {
+        self.max_lzw_bits = bits;
+        self
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing directly to it may corrupt the GIF file.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Unwrap the encoder, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
     }
 
     /// Encode one [Block](block/enum.Block.html).
@@ -33,24 +152,85 @@ impl<W: Write> BlockEnc<W> {
     where
         B: Into<Block>,
     {
+        self.encode_ref(&block.into())
+    }
+
+    /// Encode one [Block](block/enum.Block.html) by reference.
+    pub fn encode_ref(&mut self, block: &Block) -> Result<()> {
         use crate::block::Block::*;
-        let mut w = &mut self.writer;
-        match block.into() {
-            Header(b) => b.format(&mut w),
-            LogicalScreenDesc(b) => b.format(&mut w),
-            GlobalColorTable(b) => b.format(&mut w),
-            PlainText(b) => b.format(&mut w),
-            GraphicControl(b) => b.format(&mut w),
-            Comment(b) => b.format(&mut w),
-            Application(b) => b.format(&mut w),
-            Unknown(b) => b.format(&mut w),
-            ImageDesc(b) => b.format(&mut w),
-            LocalColorTable(b) => b.format(&mut w),
-            ImageData(b) => b.format(&mut w),
-            Trailer(b) => b.format(&mut w),
-        }?;
+        let size = match block {
+            GlobalColorTable(b) => b.colors().len(),
+            LocalColorTable(b) => b.colors().len(),
+            _ => 0,
+        };
+        if let PlainText(b) = block {
+            b.check_header()?;
+        }
+        if let (true, ImageData(b)) = (self.strict, block) {
+            if let Some(sz) = self.image_sz {
+                // block sequence errors are checked first
+                if !b.is_raw() && self.is_valid_next(block.code(), 0) {
+                    check_image_data(sz, b.data().len())?;
+                }
+            }
+        }
+        let (compression, max_bits) = (self.compression, self.max_lzw_bits);
+        self.format_next(block.code(), size, block.expected_next(), |w| {
+            match block {
+                Header(b) => b.format(w),
+                LogicalScreenDesc(b) => b.format(w),
+                GlobalColorTable(b) => b.format(w),
+                PlainText(b) => b.format(w),
+                GraphicControl(b) => b.format(w),
+                Comment(b) => b.format(w),
+                Application(b) => b.format(w),
+                Unknown(b) => b.format(w),
+                ImageDesc(b) => b.format(w),
+                LocalColorTable(b) => b.format(w),
+                ImageData(b) => b.format(w, compression, max_bits),
+                Trailer(b) => b.format(w),
+            }
+        })?;
+        if let ImageDesc(b) = block {
+            self.image_sz = Some(b.image_sz());
+        }
+        Ok(())
+    }
+
+    /// Check block sequence, then format one block
+    fn format_next<F>(
+        &mut self,
+        code: BlockCode,
+        size: usize,
+        next: Option<(BlockCode, usize)>,
+        format: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut W) -> io::Result<()>,
+    {
+        if self.strict && !self.is_valid_next(code, size) {
+            return Err(Error::InvalidBlockSequence);
+        }
+        format(&mut self.writer)?;
+        self.expected_next = next;
+        if code == BlockCode::ImageData_ {
+            self.image_sz = None;
+        }
         Ok(())
     }
+
+    /// Check if a block is valid next in sequence
+    fn is_valid_next(&self, code: BlockCode, size: usize) -> bool {
+        use crate::block::BlockCode::*;
+        match (self.expected_next, code) {
+            (Some((bc, sz)), GlobalColorTable_ | LocalColorTable_) => {
+                bc == code && sz == size
+            }
+            (Some((bc, _)), _) => bc == code,
+            (None, Extension_ | ImageDesc_ | Trailer_) => true,
+            (None, _) => false,
+        }
+    }
 }
 
 /// Encoder for writing [Frame]s into a GIF file.
@@ -68,11 +248,24 @@ pub struct FrameEnc<W: Write> {
     has_trailer: bool,
 }
 
+impl Block {
+    /// Encode one block into bytes.
+    ///
+    /// Image data is compressed with the default [Compression].
+    ///
+    /// [Compression]: ../encode/enum.Compression.html
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut enc = BlockEnc::new(vec![]);
+        enc.encode_ref(self)?;
+        Ok(enc.into_inner())
+    }
+}
+
 impl Header {
     /// Format a header block
     fn format<W: Write>(self, w: &mut W) -> io::Result<()> {
         w.write_all(b"GIF")?;
-        w.write_all(&self.version())
+        w.write_all(&<[u8; 3]>::from(self.version_enum()))
     }
 }
 
@@ -101,11 +294,43 @@ impl GlobalColorTable {
 }
 
 impl PlainText {
+    /// Check that the header is valid
+    fn check_header(&self) -> Result<()> {
+        let header_sz = self.sub_blocks().first().map(|h| h.len());
+        if header_sz == Some(PlainText::HEADER_SZ)
+            && self.cell_width() > 0
+            && self.cell_height() > 0
+        {
+            Ok(())
+        } else {
+            Err(Error::MalformedPlainTextExtension)
+        }
+    }
+
     /// Format a plain text extension block
     fn format<W: Write>(&self, w: &mut W) -> io::Result<()> {
         w.write_all(BlockCode::Extension_.signature())?;
         w.write_all(&[ExtensionCode::PlainText_.into()])?;
-        for b in self.sub_blocks() {
+        let left = self.grid_left();
+        let top = self.grid_top();
+        let width = self.grid_width();
+        let height = self.grid_height();
+        w.write_all(&[
+            PlainText::HEADER_SZ as u8, // block size
+            left as u8,
+            (left >> 8) as u8,
+            top as u8,
+            (top >> 8) as u8,
+            width as u8,
+            (width >> 8) as u8,
+            height as u8,
+            (height >> 8) as u8,
+            self.cell_width(),
+            self.cell_height(),
+            self.fg_color_idx(),
+            self.bg_color_idx(),
+        ])?;
+        for b in self.text() {
             debug_assert!(!b.is_empty() && b.len() < 256);
             let len = b.len() as u8;
             w.write_all(&[len])?; // sub-block size
@@ -208,28 +433,69 @@ impl LocalColorTable {
 
 impl ImageData {
     /// Format an image data block
-    fn format<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        // number of entries needed is max value plus one
-        let min_code_bits =
-            next_high_bit(self.data().iter().copied().max().unwrap_or(0) + 1);
-        // minimum code bits must be between 2 and 8
-        let min_code_bits = 2.max(min_code_bits).min(8);
-        w.write_all(&[min_code_bits])?;
-        let mut buffer = Vec::with_capacity(self.data().len());
-        let mut compressor = Compressor::new(min_code_bits);
-        compressor.compress(self.data(), &mut buffer);
-        // split buffer into sub-blocks
-        for chunk in buffer.chunks(255) {
-            let len = chunk.len() as u8;
-            w.write_all(&[len])?; // sub-block size
-            w.write_all(chunk)?;
+    fn format<W: Write>(
+        &self,
+        w: &mut W,
+        compression: Compression,
+        max_bits: u8,
+    ) -> io::Result<()> {
+        match self.min_code_size() {
+            Some(min_code_bits) => {
+                format_raw_image_data(min_code_bits, self.data(), w)
+            }
+            None => format_image_data(self.data(), w, compression, max_bits),
         }
-        w.write_all(&[0]) // final sub-block size
     }
 }
 
+/// Format raw image data from LZW-compressed sub-blocks
+fn format_raw_image_data<W: Write>(
+    min_code_bits: u8,
+    data: &[u8],
+    w: &mut W,
+) -> io::Result<()> {
+    // check sub-block framing
+    let mut pos = 0;
+    while pos < data.len() && data[pos] > 0 {
+        pos += usize::from(data[pos]) + 1;
+    }
+    if pos != data.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Malformed raw image data",
+        ));
+    }
+    w.write_all(&[min_code_bits])?;
+    w.write_all(data)?;
+    w.write_all(&[0]) // final sub-block size
+}
+
+/// Format image data from a slice of color indices
+fn format_image_data<W: Write>(
+    data: &[u8],
+    w: &mut W,
+    compression: Compression,
+    max_bits: u8,
+) -> io::Result<()> {
+    // number of entries needed is max value plus one
+    let max_idx = data.iter().copied().max().unwrap_or(0);
+    let min_code_bits = next_high_bit(u16::from(max_idx) + 1);
+    // minimum code bits must be between 2 and 8
+    let min_code_bits = 2.max(min_code_bits).min(8);
+    w.write_all(&[min_code_bits])?;
+    let mut compressor = Compressor::new(min_code_bits).with_max_bits(max_bits);
+    match compression {
+        Compression::Normal => compressor.compress_to(data, w)?,
+        Compression::Fast => compressor.compress_fast_to(data, w)?,
+        Compression::Compatible => compressor
+            .with_table_reset_interval(Some(COMPATIBLE_RESET_INTERVAL))
+            .compress_to(data, w)?,
+    }
+    w.write_all(&[0]) // final sub-block size
+}
+
 /// Get the high bit of a value
-fn next_high_bit(value: u8) -> u8 {
+fn next_high_bit(value: u16) -> u8 {
     u32::from(value).next_power_of_two().trailing_zeros() as u8
 }
 
@@ -244,31 +510,83 @@ impl<W: Write> FrameEnc<W> {
     /// Create a new GIF frame encoder.
     pub(crate) fn new(block_enc: BlockEnc<W>) -> Self {
         FrameEnc {
-            block_enc,
+            block_enc: block_enc.with_strict(true),
             has_preamble: false,
             has_trailer: false,
         }
     }
 
+    /// Set the compression mode for image data.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.block_enc.compression = compression;
+        self
+    }
+
+    /// Set the maximum LZW code width for image data.
+    ///
+    /// See BlockEnc.[with_max_lzw_bits].
+    ///
+    /// [with_max_lzw_bits]: struct.BlockEnc.html#method.with_max_lzw_bits
+    pub fn with_max_lzw_bits(mut self, bits: u8) -> Self {
+        self.block_enc.max_lzw_bits = bits;
+        self
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.block_enc.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing directly to it may corrupt the GIF file.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.block_enc.get_mut()
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.block_enc.flush()
+    }
+
+    /// Unwrap the encoder, returning the underlying writer.
+    ///
+    /// The [Trailer] is not encoded; use [finish] for that.
+    ///
+    /// [finish]: struct.FrameEnc.html#method.finish
+    /// [Trailer]: ../block/struct.Trailer.html
+    pub fn into_inner(self) -> W {
+        self.block_enc.into_inner()
+    }
+
     /// Encode the GIF preamble blocks.
     ///
     /// Must be called only once, before [encode_frame].
     ///
     /// [encode_frame]: struct.FrameEnc.html#method.encode_frame
     pub fn encode_preamble(&mut self, preamble: &Preamble) -> Result<()> {
+        use crate::block::BlockCode::*;
         if self.has_preamble {
             return Err(Error::InvalidBlockSequence);
         }
         self.block_enc.encode(preamble.header)?;
         self.block_enc.encode(preamble.logical_screen_desc)?;
         if let Some(tbl) = &preamble.global_color_table {
-            self.block_enc.encode(tbl.clone())?;
+            let sz = tbl.colors().len();
+            self.block_enc
+                .format_next(GlobalColorTable_, sz, None, |w| tbl.format(w))?;
         }
         if let Some(cnt) = &preamble.loop_count_ext {
-            self.block_enc.encode(cnt.clone())?;
+            self.block_enc
+                .format_next(Extension_, 0, None, |w| cnt.format(w))?;
+        }
+        for app in &preamble.applications {
+            self.block_enc
+                .format_next(Extension_, 0, None, |w| app.format(w))?;
         }
         for comment in &preamble.comments {
-            self.block_enc.encode(comment.clone())?;
+            self.block_enc
+                .format_next(Extension_, 0, None, |w| comment.format(w))?;
         }
         self.has_preamble = true;
         Ok(())
@@ -276,21 +594,82 @@ impl<W: Write> FrameEnc<W> {
 
     /// Encode one `Frame` of a GIF file.
     ///
-    /// Must be called after [encode_preamble].
+    /// If the frame is interlaced, its image data is reordered into
+    /// interlaced rows.  Raw image data is written without re-compression.
+    ///
+    /// Must be called after [encode_preamble].  Returns
+    /// [Error::ImageDataMismatch] if the image data does not match the size
+    /// of the image descriptor, before writing anything.  Raw image data is
+    /// not checked, since it is passed through still compressed.
     ///
     /// [encode_preamble]: struct.FrameEnc.html#method.encode_preamble
+    /// [Error::ImageDataMismatch]: ../enum.Error.html#variant.ImageDataMismatch
     pub fn encode_frame(&mut self, frame: &Frame) -> Result<()> {
+        use crate::block::BlockCode::*;
+        if frame.is_raw() {
+            self.encode_frame_head(
+                frame.graphic_control_ext,
+                frame.image_desc,
+                frame.local_color_table.as_ref(),
+            )?;
+            let compression = self.block_enc.compression;
+            let max_bits = self.block_enc.max_lzw_bits;
+            return self.block_enc.format_next(ImageData_, 0, None, |w| {
+                frame.image_data.format(w, compression, max_bits)
+            });
+        }
+        self.encode_frame_data(
+            frame.graphic_control_ext,
+            frame.image_desc,
+            frame.local_color_table.as_ref(),
+            frame.image_data.data(),
+        )
+    }
+
+    /// Encode one frame from its parts, with image data in raster order
+    fn encode_frame_data(
+        &mut self,
+        control: Option<GraphicControl>,
+        image_desc: ImageDesc,
+        local_color_table: Option<&LocalColorTable>,
+        data: &[u8],
+    ) -> Result<()> {
+        use crate::block::BlockCode::*;
+        check_image_data(image_desc.image_sz(), data.len())?;
+        self.encode_frame_head(control, image_desc, local_color_table)?;
+        let compression = self.block_enc.compression;
+        let max_bits = self.block_enc.max_lzw_bits;
+        self.block_enc.format_next(ImageData_, 0, None, |w| {
+            if image_desc.interlaced() {
+                let data = interlace(data, &image_desc);
+                format_image_data(&data, w, compression, max_bits)
+            } else {
+                format_image_data(data, w, compression, max_bits)
+            }
+        })
+    }
+
+    /// Encode frame blocks preceding image data
+    fn encode_frame_head(
+        &mut self,
+        control: Option<GraphicControl>,
+        image_desc: ImageDesc,
+        local_color_table: Option<&LocalColorTable>,
+    ) -> Result<()> {
+        use crate::block::BlockCode::*;
         if self.has_trailer || !self.has_preamble {
             return Err(Error::InvalidBlockSequence);
         }
-        if let Some(ctrl) = &frame.graphic_control_ext {
-            self.block_enc.encode(*ctrl)?;
+        if let Some(ctrl) = control {
+            self.block_enc.encode(ctrl)?;
         }
-        self.block_enc.encode(frame.image_desc)?;
-        if let Some(tbl) = &frame.local_color_table {
-            self.block_enc.encode(tbl.clone())?;
+        self.block_enc.encode(image_desc)?;
+        if let Some(tbl) = local_color_table {
+            let sz = tbl.colors().len();
+            let next = Some((ImageData_, ImageData_.size()));
+            self.block_enc
+                .format_next(LocalColorTable_, sz, next, |w| tbl.format(w))?;
         }
-        self.block_enc.encode(frame.image_data.clone())?;
         Ok(())
     }
 
@@ -309,6 +688,39 @@ impl<W: Write> FrameEnc<W> {
         self.has_trailer = true;
         Ok(())
     }
+
+    /// Finish encoding the GIF file.
+    ///
+    /// The [Trailer] is encoded (unless [encode_trailer] was already called),
+    /// and the writer is flushed and returned.
+    ///
+    /// [encode_trailer]: struct.FrameEnc.html#method.encode_trailer
+    /// [Trailer]: ../block/struct.Trailer.html
+    pub fn finish(mut self) -> Result<W> {
+        if !self.has_trailer {
+            self.encode_trailer()?;
+        }
+        self.block_enc.flush()?;
+        Ok(self.block_enc.into_inner())
+    }
+}
+
+/// Check that image data matches the image size
+fn check_image_data(expected: usize, got: usize) -> Result<()> {
+    if expected == got {
+        Ok(())
+    } else {
+        Err(Error::ImageDataMismatch { expected, got })
+    }
+}
+
+/// Background color for the logical screen
+#[derive(Clone, Copy, Debug)]
+enum BackgroundColor {
+    /// Color to find or add in the global color table
+    Color(SRgb8),
+    /// Index into the global color table
+    Index(u8),
 }
 
 /// Encoder for writing [Step]s into a GIF file.
@@ -317,19 +729,39 @@ impl<W: Write> FrameEnc<W> {
 ///
 /// [Step]: ../struct.Step.html
 pub struct StepEnc<W: Write> {
-    /// Frame encoder
-    frame_enc: FrameEnc<W>,
+    /// Frame encoder (taken by `finish`)
+    frame_enc: Option<FrameEnc<W>>,
     /// Global color table
     global_color_table: (ColorTableConfig, Option<GlobalColorTable>),
+    /// GIF version
+    version: Version,
+    /// Interlaced frames
+    interlaced: bool,
+    /// Allow color indices outside of palette
+    allow_out_of_range: bool,
+    /// Minimum delay time (centiseconds)
+    min_delay_cs: u16,
+    /// Quantizer for true color rasters
+    quantizer: Quantizer,
+    /// Background color
+    background_color: Option<BackgroundColor>,
     /// Animation loop count
-    loop_count: Option<Application>,
+    loop_count: Option<u16>,
+    /// Stream position of GIF, for patching loop count placeholder
+    loop_count_start: Option<u64>,
+    /// Other application extension blocks
+    applications: Vec<Application>,
+    /// Comments
+    comments: Vec<String>,
     /// Preamble blocks
     preamble: Option<Preamble>,
 }
 
 impl<W: Write> Drop for StepEnc<W> {
     fn drop(&mut self) {
-        let _ = self.frame_enc.encode_trailer();
+        if let Some(mut frame_enc) = self.frame_enc.take() {
+            let _ = frame_enc.encode_trailer();
+        }
     }
 }
 
@@ -337,141 +769,816 @@ impl<W: Write> StepEnc<W> {
     /// Create a new GIF raster encoder.
     pub(crate) fn new(frame_enc: FrameEnc<W>) -> Self {
         StepEnc {
-            frame_enc,
+            frame_enc: Some(frame_enc),
             global_color_table: (ColorTableConfig::default(), None),
+            version: Version::default(),
+            interlaced: false,
+            allow_out_of_range: false,
+            min_delay_cs: 0,
+            quantizer: Quantizer::default(),
+            background_color: None,
             loop_count: None,
+            loop_count_start: None,
+            applications: Vec::new(),
+            comments: Vec::new(),
             preamble: None,
         }
     }
 
+    /// Get the frame encoder
+    fn frame_enc(&mut self) -> &mut FrameEnc<W> {
+        // only taken when self is consumed
+        self.frame_enc.as_mut().expect("frame encoder")
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        // only taken when self is consumed
+        self.frame_enc.as_ref().expect("frame encoder").get_ref()
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing directly to it may corrupt the GIF file.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.frame_enc().get_mut()
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.frame_enc().flush()
+    }
+
+    /// Unwrap the encoder, returning the underlying writer.
+    ///
+    /// The [Trailer] is not encoded; use [finish] for that.
+    ///
+    /// [finish]: struct.StepEnc.html#method.finish
+    /// [Trailer]: ../block/struct.Trailer.html
+    pub fn into_inner(mut self) -> W {
+        // only taken when self is consumed
+        self.frame_enc.take().expect("frame encoder").into_inner()
+    }
+
+    /// Finish encoding the GIF file.
+    ///
+    /// The [Trailer] is encoded and the writer is flushed and returned.
+    /// Otherwise, the trailer is encoded when the `StepEnc` is dropped, but
+    /// any error is ignored.
+    ///
+    /// [Trailer]: ../block/struct.Trailer.html
+    pub fn finish(mut self) -> Result<W> {
+        match self.frame_enc.take() {
+            Some(frame_enc) => frame_enc.finish(),
+            None => Err(Error::InvalidBlockSequence),
+        }
+    }
+
+    /// Set the GIF version.
+    ///
+    /// Version 87a does not support extension blocks.  Encoding returns
+    /// [UnsupportedExtension] if a loop count, comment, application block or
+    /// graphic control (delay time, disposal method or transparent color) is
+    /// used.
+    ///
+    /// [UnsupportedExtension]: ../enum.Error.html#variant.UnsupportedExtension
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the compression mode for image data.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.frame_enc().block_enc.compression = compression;
+        self
+    }
+
+    /// Set the maximum LZW code width for image data.
+    ///
+    /// See BlockEnc.[with_max_lzw_bits].
+    ///
+    /// [with_max_lzw_bits]: struct.BlockEnc.html#method.with_max_lzw_bits
+    pub fn with_max_lzw_bits(mut self, bits: u8) -> Self {
+        self.frame_enc().block_enc.max_lzw_bits = bits;
+        self
+    }
+
+    /// Set whether frames are interlaced.
+    pub fn with_interlaced(mut self, interlaced: bool) -> Self {
+        self.interlaced = interlaced;
+        self
+    }
+
+    /// Allow rasters with color indices outside of their palette.
+    ///
+    /// By default, encoding such a raster fails with
+    /// [Error::IndexOutOfPalette].  When allowed, the indices are encoded
+    /// as-is, which may be useful with color tables padded to a power of two.
+    ///
+    /// [Error::IndexOutOfPalette]: ../enum.Error.html#variant.IndexOutOfPalette
+    pub fn with_allow_out_of_range(mut self, allow: bool) -> Self {
+        self.allow_out_of_range = allow;
+        self
+    }
+
+    /// Set the minimum delay time, in centiseconds.
+    ///
+    /// Steps with a shorter delay time (including zero) are encoded with the
+    /// minimum instead.  Steps without a graphic control are unchanged.
+    pub fn with_min_delay_cs(mut self, min_delay_cs: u16) -> Self {
+        self.min_delay_cs = min_delay_cs;
+        self
+    }
+
+    /// Normalize short delay times.
+    ///
+    /// Most browsers play frames with a delay time of 0 or 1 centisecond
+    /// at 10 centiseconds.  When enabled, this is the same as
+    /// [with_min_delay_cs] with 2 centiseconds; otherwise, delay times are
+    /// encoded unchanged.
+    ///
+    /// [with_min_delay_cs]: struct.StepEnc.html#method.with_min_delay_cs
+    pub fn with_normalized_delays(self, normalize: bool) -> Self {
+        self.with_min_delay_cs(if normalize { NORMALIZED_DELAY_CS } else { 0 })
+    }
+
+    /// Set alpha threshold for true color rasters.
+    ///
+    /// GIF only supports fully transparent or opaque pixels.  When encoding
+    /// an `SRgba8` raster, pixels with alpha below the threshold are
+    /// transparent, and all others are opaque.  The default threshold is 1,
+    /// so only pixels with zero alpha are transparent.
+    pub fn with_alpha_threshold(mut self, threshold: u8) -> Self {
+        self.quantizer = self.quantizer.with_alpha_threshold(threshold);
+        self
+    }
+
+    /// Set matte color for true color rasters.
+    ///
+    /// Opaque pixels with partial alpha are composited over the matte color.
+    /// By default, their color channels are used unchanged.
+    pub fn with_matte(mut self, matte: SRgb8) -> Self {
+        self.quantizer = self.quantizer.with_matte(Some(matte));
+        self
+    }
+
     /// Set loop count for the animation.
     ///
     /// * `loop_count`: Number of times to loop animation; zero means forever)
     pub fn with_loop_count(mut self, loop_count: u16) -> Self {
-        self.loop_count = Some(Application::with_loop_count(loop_count));
+        self.loop_count = Some(loop_count);
+        self
+    }
+
+    /// Add an application extension block to the animation.
+    ///
+    /// May be called more than once; the blocks are written in the preamble
+    /// following the loop count block.
+    pub fn with_application(mut self, app: Application) -> Self {
+        self.applications.push(app);
+        self
+    }
+
+    /// Add a comment to the animation.
+    ///
+    /// May be called more than once; each comment is written as a separate
+    /// [Comment] block in the preamble.  Text longer than 255 bytes is split
+    /// into multiple sub-blocks.
+    ///
+    /// If the preamble has already been encoded, the next encoded step
+    /// returns [InvalidBlockSequence].
+    ///
+    /// [Comment]: ../block/struct.Comment.html
+    /// [InvalidBlockSequence]: ../enum.Error.html#variant.InvalidBlockSequence
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comments.push(comment.to_string());
         self
     }
 
     /// Set the global color table for an animation.
+    ///
+    /// Steps with colors which are all in the table are encoded using it;
+    /// any others use a local color table.
     pub fn with_global_color_table(mut self, palette: &Palette) -> Self {
-        let (tbl_cfg, pal) = make_color_table(palette);
-        self.global_color_table =
-            (tbl_cfg, Some(GlobalColorTable::with_colors(&pal[..])));
+        // a pix palette never has more than 256 entries
+        let (tbl_cfg, tbl) =
+            GlobalColorTable::from_palette(palette).expect("palette too large");
+        self.global_color_table = (tbl_cfg, Some(tbl));
+        self
+    }
+
+    /// Set the background color.
+    ///
+    /// The color is added to the global color table if it is not already
+    /// present.
+    pub fn with_background_color(mut self, clr: SRgb8) -> Self {
+        self.background_color = Some(BackgroundColor::Color(clr));
+        self
+    }
+
+    /// Set the background color index.
+    ///
+    /// The index must be within the global color table, or encoding will
+    /// fail with [InvalidColorIndex].
+    ///
+    /// [InvalidColorIndex]: ../enum.Error.html#variant.InvalidColorIndex
+    pub fn with_background_color_idx(mut self, idx: u8) -> Self {
+        self.background_color = Some(BackgroundColor::Index(idx));
         self
     }
 
+    /// Make the global colors and background color index
+    fn make_global_colors(
+        &self,
+        palette: &Palette,
+    ) -> Result<(Vec<SRgb8>, u8)> {
+        let mut colors = match &self.global_color_table.1 {
+            Some(tbl) => tbl.iter().collect(),
+            None => palette.colors().to_vec(),
+        };
+        let idx = match self.background_color {
+            Some(BackgroundColor::Color(clr)) => {
+                match colors.iter().position(|c| *c == clr) {
+                    Some(idx) => idx,
+                    None => {
+                        colors.push(clr);
+                        colors.len() - 1
+                    }
+                }
+            }
+            Some(BackgroundColor::Index(idx)) => {
+                let idx = usize::from(idx);
+                if idx >= colors.len() {
+                    return Err(Error::InvalidColorIndex);
+                }
+                idx
+            }
+            None => 0,
+        };
+        if colors.len() > 256 {
+            return Err(Error::PaletteTooLarge(colors.len()));
+        }
+        let idx = idx.try_into().map_err(|_| Error::InvalidColorIndex)?;
+        Ok((colors, idx))
+    }
+
     /// Encode an indexed `Raster` to a GIF file.
+    ///
+    /// The raster covers `region` within a `screen`.
     fn encode_indexed_raster(
         &mut self,
         raster: &Raster<Gray8>,
         palette: &Palette,
         control: Option<GraphicControl>,
+        screen: Region,
+        region: Region,
     ) -> Result<()> {
-        let image_desc = make_image_desc(raster)?;
-        let image_data = raster.into();
-        let (tbl_cfg, pal) = make_color_table(palette);
-        let logical_screen_desc = LogicalScreenDesc::default()
-            .with_screen_width(image_desc.width())
-            .with_screen_height(image_desc.height())
-            .with_color_table_config(tbl_cfg);
-        let global_color_table = Some(GlobalColorTable::with_colors(&pal[..]));
-        let loop_count_ext = self.loop_count.clone();
-        let preamble = Preamble {
-            logical_screen_desc,
-            global_color_table,
-            loop_count_ext,
-            ..Preamble::default()
+        check_raster_size(screen.width, screen.height)?;
+        let image_desc =
+            make_image_desc(raster, region)?.with_interlaced(self.interlaced);
+        let control = control.map(|mut control| {
+            if control.delay_time_cs() < self.min_delay_cs {
+                control.set_delay_time_cs(self.min_delay_cs);
+            }
+            control
+        });
+        let data = raster.as_u8_slice();
+        if !self.allow_out_of_range {
+            check_indices(data, palette)?;
+        }
+        let (colors, bg_idx) = self.make_global_colors(palette)?;
+        let width = screen.width.try_into()?;
+        let height = screen.height.try_into()?;
+        let screen = Preamble::with_screen(width, height)
+            .with_colors(&colors)?
+            .with_background_color_idx(bg_idx);
+        if self.version == Version::Gif87a && control.is_some() {
+            return Err(Error::UnsupportedExtension);
+        }
+        // map colors to the configured global color table
+        let map = match &self.global_color_table.1 {
+            Some(_) if !colors.starts_with(palette.colors()) => {
+                let trans = control.and_then(|c| c.transparent_color());
+                Some(map_palette(data, palette, trans, &colors))
+            }
+            _ => None,
         };
+        let mut use_local = matches!(map, Some(None));
         match &self.preamble {
             Some(pre) => {
+                // comments can only be written in the preamble
+                if pre.comments.len() < self.comments.len() {
+                    return Err(Error::InvalidBlockSequence);
+                }
                 if !pre
                     .logical_screen_desc
-                    .equal_size(preamble.logical_screen_desc)
+                    .equal_size(screen.logical_screen_desc)
                 {
                     return Err(Error::InvalidRasterDimensions);
                 }
-                if pre.global_color_table != preamble.global_color_table {
-                    let frame = Frame::new(
-                        control,
-                        image_desc.with_color_table_config(tbl_cfg),
-                        Some(LocalColorTable::with_colors(&pal[..])),
-                        image_data,
-                    );
-                    return self.frame_enc.encode_frame(&frame);
+                if pre.global_color_table != screen.global_color_table {
+                    use_local = true;
                 }
             }
             None => {
-                self.frame_enc.encode_preamble(&preamble)?;
+                let preamble = self.make_preamble(screen)?;
+                self.frame_enc().encode_preamble(&preamble)?;
                 self.preamble = Some(preamble);
             }
         }
-        let frame = Frame::new(control, image_desc, None, image_data);
-        self.frame_enc.encode_frame(&frame)
+        if use_local {
+            let (tbl_cfg, tbl) = LocalColorTable::from_palette(palette)?;
+            let image_desc = image_desc.with_color_table_config(tbl_cfg);
+            return self.frame_enc().encode_frame_data(
+                control,
+                image_desc,
+                Some(&tbl),
+                data,
+            );
+        }
+        match map {
+            Some(Some(map)) => {
+                let remap = |idx: u8| map[usize::from(idx)];
+                let data: Vec<u8> =
+                    data.iter().map(|idx| remap(*idx)).collect();
+                let control = control.map(|mut control| {
+                    let idx = control.transparent_color().map(remap);
+                    control.set_transparent_color(idx);
+                    control
+                });
+                self.frame_enc()
+                    .encode_frame_data(control, image_desc, None, &data)
+            }
+            _ => self
+                .frame_enc()
+                .encode_frame_data(control, image_desc, None, data),
+        }
+    }
+
+    /// Make the preamble for the first frame
+    fn make_preamble(&self, screen: Preamble) -> Result<Preamble> {
+        let mut preamble = screen.with_version(self.version);
+        if self.version == Version::Gif87a {
+            if self.loop_count.is_some()
+                || !self.applications.is_empty()
+                || !self.comments.is_empty()
+            {
+                return Err(Error::UnsupportedExtension);
+            }
+            return Ok(preamble);
+        }
+        if let Some(loop_count) = self.loop_count {
+            preamble = preamble.with_loop_count(loop_count);
+        }
+        for app in &self.applications {
+            preamble = preamble.with_application(app.clone());
+        }
+        for comment in &self.comments {
+            preamble = preamble.with_comment(comment);
+        }
+        Ok(preamble)
+    }
+
+    /// Encode a quantized true color step
+    fn encode_quantized(
+        &mut self,
+        indexed: Indexed,
+        control: Option<GraphicControl>,
+        screen: Region,
+        region: Region,
+    ) -> Result<()> {
+        let mut control = control.unwrap_or_default();
+        control.set_transparent_color(indexed.transparent);
+        let control = if control != GraphicControl::default() {
+            Some(control)
+        } else {
+            None
+        };
+        self.encode_indexed_raster(
+            &indexed.raster,
+            &indexed.palette,
+            control,
+            screen,
+            region,
+        )
     }
 
     /// Encode one [Step] to a GIF file.
     ///
+    /// True color rasters are quantized to 256 colors or fewer.  Only the
+    /// step [region] is encoded, which must be within the raster.  Returns
+    /// [RasterTooLarge] if the raster is wider or taller than 65535 pixels.
+    ///
+    /// [RasterTooLarge]: ../enum.Error.html#variant.RasterTooLarge
+    /// [region]: ../struct.Step.html#method.region
     /// [Step]: ../struct.Step.html
     pub fn encode_step(&mut self, step: &Step) -> Result<()> {
+        let (width, height) = step.raster.size();
+        check_raster_size(width, height)?;
+        let control = step.graphic_control_ext;
+        let region = step.region();
         match &step.raster {
-            StepRaster::TrueColor(_) => {
-                todo!("convert raster to indexed raster");
+            StepRaster::TrueColor(raster) => {
+                let cropped = crop(raster, region)?;
+                let indexed = self
+                    .quantizer
+                    .quantize_rgba(cropped.as_ref().unwrap_or(raster));
+                self.encode_quantized(indexed, control, raster.region(), region)
+            }
+            StepRaster::Opaque(raster) => {
+                let cropped = crop(raster, region)?;
+                let indexed = self
+                    .quantizer
+                    .quantize_rgb(cropped.as_ref().unwrap_or(raster));
+                self.encode_quantized(indexed, control, raster.region(), region)
             }
             StepRaster::Indexed(raster, palette) => {
+                let cropped = crop(raster, region)?;
                 self.encode_indexed_raster(
-                    raster,
+                    cropped.as_ref().unwrap_or(raster),
                     palette,
-                    step.graphic_control_ext,
-                )?;
+                    control,
+                    raster.region(),
+                    region,
+                )
             }
         }
-        Ok(())
     }
-}
-
-/// Make an image description block
-fn make_image_desc(raster: &Raster<Gray8>) -> Result<ImageDesc> {
-    let width = raster.width().try_into()?;
-    let height = raster.height().try_into()?;
-    Ok(ImageDesc::default().with_width(width).with_height(height))
-}
 
-/// Make a color table from a palette
-fn make_color_table(palette: &Palette) -> (ColorTableConfig, Vec<u8>) {
-    let tbl_cfg = ColorTableConfig::new(
-        ColorTableExistence::Present,
-        ColorTableOrdering::NotSorted,
-        palette.len() as u16,
-    );
-    let mut pal = Vec::with_capacity(palette.len() * 3);
-    for clr in palette.colors() {
-        pal.push(u8::from(Rgb::red(*clr)));
-        pal.push(u8::from(Rgb::green(*clr)));
-        pal.push(u8::from(Rgb::blue(*clr)));
-    }
-    while pal.len() < tbl_cfg.size_bytes() {
-        pal.push(0);
+    /// Encode a true color `Raster` as one step.
+    ///
+    /// The raster is quantized to 256 colors or fewer.  If any pixels are
+    /// transparent, one of those colors is reserved as the transparent color.
+    /// Returns [RasterTooLarge] if the raster is wider or taller than 65535
+    /// pixels.
+    ///
+    /// [RasterTooLarge]: ../enum.Error.html#variant.RasterTooLarge
+    pub fn encode_raster(&mut self, raster: &Raster<SRgba8>) -> Result<()> {
+        check_raster_size(raster.width(), raster.height())?;
+        let indexed = self.quantizer.quantize_rgba(raster);
+        let region = raster.region();
+        self.encode_quantized(indexed, None, region, region)
     }
-    (tbl_cfg, pal)
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::Encoder;
-    use pix::{gray::Gray8, rgb::SRgb8, Palette, Raster};
 
-    #[test]
-    fn high_bits() {
-        assert_eq!(next_high_bit(0), 0);
-        assert_eq!(next_high_bit(2), 1);
-        assert_eq!(next_high_bit(3), 2);
+    /// Encode a sequence of [Step]s sharing one global color table.
+    ///
+    /// The colors used by all steps are merged into a single global color
+    /// table, and each raster is remapped to it.  If the merged table would
+    /// exceed 256 colors, each step is encoded as with [encode_step], using
+    /// local color tables where needed.
+    ///
+    /// [encode_step]: struct.StepEnc.html#method.encode_step
+    /// [Step]: ../struct.Step.html
+    pub fn encode_all<I>(&mut self, steps: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Step>,
+    {
+        let steps: Vec<Step> = steps.into_iter().collect();
+        let merged = if self.preamble.is_none() {
+            merge_palettes(&steps)
+        } else {
+            None
+        };
+        match merged {
+            Some((palette, maps)) => {
+                for (step, map) in steps.iter().zip(&maps) {
+                    self.encode_step(&remap_step(step, &palette, map))?;
+                }
+            }
+            None => {
+                for step in &steps {
+                    self.encode_step(step)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode a sequence of [Step]s, choosing a shared global palette.
+    ///
+    /// Steps are buffered, so that the global color table can be chosen
+    /// from all of them.  Indexed steps are merged as with [encode_all],
+    /// and true color steps are quantized together.  A loop count is only
+    /// encoded if set with [with_loop_count], even for a single step.  If
+    /// the buffered steps exceed an internal memory limit, each step is
+    /// encoded as with [encode_step].
+    ///
+    /// This accepts the output of Decoder.[into_steps] directly:
+    ///
+    /// ```
+    /// # fn transcode(gif: &[u8]) -> gift::Result<Vec<u8>> {
+    /// use gift::{Decoder, Encoder};
+    ///
+    /// let mut out = vec![];
+    /// let mut enc = Encoder::new(&mut out).into_step_enc();
+    /// enc.encode_steps(Decoder::new(gif).into_steps())?;
+    /// # drop(enc);
+    /// # Ok(out)
+    /// # }
+    /// ```
+    ///
+    /// [encode_all]: struct.StepEnc.html#method.encode_all
+    /// [encode_step]: struct.StepEnc.html#method.encode_step
+    /// [into_steps]: ../struct.Decoder.html#method.into_steps
+    /// [Step]: ../struct.Step.html
+    /// [with_loop_count]: struct.StepEnc.html#method.with_loop_count
+    pub fn encode_steps<I>(&mut self, steps: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<Step>>,
+    {
+        let mut steps = steps.into_iter();
+        let mut buffered = vec![];
+        let mut sz = 0;
+        for step in steps.by_ref() {
+            let step = step?;
+            sz += step_size(&step);
+            buffered.push(step);
+            if sz > MAX_BUFFERED_SZ {
+                for step in buffered.drain(..) {
+                    self.encode_step(&step)?;
+                }
+                for step in steps {
+                    self.encode_step(&step?)?;
+                }
+                return Ok(());
+            }
+        }
+        if self.preamble.is_some() {
+            return self.encode_all(buffered);
+        }
+        let indexed = buffered
+            .iter()
+            .all(|step| matches!(step.raster, StepRaster::Indexed(_, _)));
+        if indexed {
+            return self.encode_all(buffered);
+        }
+        let mut frames = vec![];
+        let mut rasters = vec![];
+        for step in buffered {
+            let control = step.graphic_control_ext;
+            let region = step.region();
+            let raster = step.rgba_raster();
+            let screen = raster.region();
+            rasters.push(crop(&raster, region)?.unwrap_or(raster));
+            frames.push((control, screen, region));
+        }
+        let quantized = self.quantizer.quantize_rgba_all(&rasters);
+        for (indexed, (control, screen, region)) in
+            quantized.into_iter().zip(frames)
+        {
+            self.encode_quantized(indexed, control, screen, region)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> StepEnc<W> {
+    /// Encode a placeholder loop count, to be patched by [set_loop_count].
+    ///
+    /// Must be called before any steps are encoded.  The placeholder loops
+    /// forever, unless [with_loop_count] was called.
+    ///
+    /// [set_loop_count]: struct.StepEnc.html#method.set_loop_count
+    /// [with_loop_count]: struct.StepEnc.html#method.with_loop_count
+    pub fn with_loop_count_placeholder(mut self) -> Result<Self> {
+        if self.preamble.is_some() {
+            return Err(Error::InvalidBlockSequence);
+        }
+        self.loop_count_start = Some(self.get_mut().stream_position()?);
+        self.loop_count = Some(self.loop_count.unwrap_or_default());
+        Ok(self)
+    }
+
+    /// Set the loop count, patching it in place if already encoded.
+    ///
+    /// After steps have been encoded, this requires a placeholder from
+    /// [with_loop_count_placeholder], or it returns
+    /// [MissingLoopCountPlaceholder].
+    ///
+    /// [MissingLoopCountPlaceholder]: ../enum.Error.html#variant.MissingLoopCountPlaceholder
+    /// [with_loop_count_placeholder]: struct.StepEnc.html#method.with_loop_count_placeholder
+    pub fn set_loop_count(&mut self, loop_count: u16) -> Result<()> {
+        let preamble = match &self.preamble {
+            Some(preamble) => preamble,
+            None => {
+                self.loop_count = Some(loop_count);
+                return Ok(());
+            }
+        };
+        let start = match (self.loop_count_start, &preamble.loop_count_ext) {
+            (Some(start), Some(_)) => start,
+            _ => return Err(Error::MissingLoopCountPlaceholder),
+        };
+        let tbl_sz = preamble
+            .global_color_table
+            .as_ref()
+            .map_or(0, |tbl| tbl.colors().len());
+        // header, screen desc, color table, extension introducer / label,
+        // app ID sub-block, and loop count sub-block size
+        let pos = 6 + 7 + tbl_sz as u64 + 2 + 12 + 1;
+        let app = Application::with_loop_count(loop_count);
+        let data = app.app_data().nth(1).expect("loop count data");
+        let writer = self.get_mut();
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(start + pos))?;
+        writer.write_all(data)?;
+        writer.seek(SeekFrom::Start(end))?;
+        self.loop_count = Some(loop_count);
+        if let Some(preamble) = &mut self.preamble {
+            preamble.loop_count_ext = Some(app);
+        }
+        Ok(())
+    }
+}
+
+/// Get the size of a step raster (bytes)
+fn step_size(step: &Step) -> usize {
+    match &step.raster {
+        StepRaster::TrueColor(raster) => raster.as_u8_slice().len(),
+        StepRaster::Opaque(raster) => raster.as_u8_slice().len(),
+        StepRaster::Indexed(raster, _) => raster.as_u8_slice().len(),
+    }
+}
+
+/// Crop a raster to a step region.
+///
+/// Returns `None` if the region covers the full raster.
+fn crop<P: Pixel>(
+    raster: &Raster<P>,
+    region: Region,
+) -> Result<Option<Raster<P>>> {
+    if raster.intersection(region) != region {
+        return Err(Error::InvalidFrameDimensions);
+    }
+    if region == raster.region() {
+        return Ok(None);
+    }
+    let mut cropped = Raster::with_clear(region.width, region.height);
+    cropped.copy_raster(cropped.region(), raster, region);
+    Ok(Some(cropped))
+}
+
+/// Merge the colors used by indexed steps into one palette.
+///
+/// Each step's transparent color gets its own entry, even if an opaque
+/// color is the same.  Returns the merged palette, with a map of indices
+/// for each step, or `None` if any step is not indexed, or there are too
+/// many colors.
+fn merge_palettes(steps: &[Step]) -> Option<(Palette, Vec<[u8; 256]>)> {
+    let mut merged = Palette::new(256);
+    let mut maps = Vec::with_capacity(steps.len());
+    for step in steps {
+        match &step.raster {
+            StepRaster::Indexed(raster, palette) => {
+                let trans = step.transparent_color().map(usize::from);
+                let mut used = [false; 256];
+                for idx in raster.as_u8_slice() {
+                    used[usize::from(*idx)] = true;
+                }
+                let mut map = [0; 256];
+                for idx in (0..256).filter(|i| used[*i] && Some(*i) != trans) {
+                    map[idx] = merged.set_entry(palette.entry(idx)?)? as u8;
+                }
+                if let Some(idx) = trans {
+                    let clr = palette.entry(idx)?;
+                    if merged.len() >= 256 {
+                        return None;
+                    }
+                    // reserve an entry, so opaque pixels of the same color are not
+                    // transparent
+                    let entry = merged.set_entry(unused_color(&merged))?;
+                    merged.replace_entry(entry, clr);
+                    map[idx] = entry as u8;
+                }
+                maps.push(map);
+            }
+            StepRaster::TrueColor(_) | StepRaster::Opaque(_) => return None,
+        }
+    }
+    Some((merged, maps))
+}
+
+/// Remap an indexed step to a merged palette
+fn remap_step(step: &Step, merged: &Palette, map: &[u8; 256]) -> Step {
+    match &step.raster {
+        StepRaster::Indexed(raster, _) => {
+            let pixels: Vec<Gray8> = raster
+                .as_u8_slice()
+                .iter()
+                .map(|idx| Gray8::new(map[usize::from(*idx)]))
+                .collect();
+            let raster =
+                Raster::with_pixels(raster.width(), raster.height(), pixels);
+            let mut remapped = Step::with_indexed(raster, merged.clone());
+            remapped.region = step.region;
+            remapped.graphic_control_ext =
+                step.graphic_control_ext.map(|mut control| {
+                    if let Some(idx) = control.transparent_color() {
+                        let idx = map[usize::from(idx)];
+                        control.set_transparent_color(Some(idx));
+                    }
+                    control
+                });
+            remapped
+        }
+        StepRaster::TrueColor(_) | StepRaster::Opaque(_) => step.clone(),
+    }
+}
+
+/// Map palette indices used by a raster to a color table.
+///
+/// The transparent index is mapped to an entry which is not used by any
+/// opaque pixels.  Returns `None` if a used color is not in the table.
+fn map_palette(
+    data: &[u8],
+    palette: &Palette,
+    transparent: Option<u8>,
+    colors: &[SRgb8],
+) -> Option<[u8; 256]> {
+    let trans = transparent.map(usize::from);
+    let mut used = [false; 256];
+    for idx in data {
+        used[usize::from(*idx)] = true;
+    }
+    let mut map = [0; 256];
+    let mut taken = [false; 256];
+    for idx in (0..256).filter(|i| used[*i] && Some(*i) != trans) {
+        let clr = palette.entry(idx)?;
+        let entry = colors.iter().position(|c| *c == clr)?;
+        map[idx] = u8::try_from(entry).ok()?;
+        taken[entry] = true;
+    }
+    if let Some(idx) = trans {
+        let clr = palette.entry(idx);
+        let free = || (0..colors.len()).filter(|i| !taken[*i]);
+        let entry = free()
+            .find(|i| Some(colors[*i]) == clr)
+            .or_else(|| free().next())?;
+        map[idx] = u8::try_from(entry).ok()?;
+    }
+    Some(map)
+}
+
+/// Check that all color indices are within a palette
+fn check_indices(data: &[u8], palette: &Palette) -> Result<()> {
+    let palette_len = palette.len();
+    match data.iter().copied().max() {
+        Some(index) if usize::from(index) >= palette_len => {
+            Err(Error::IndexOutOfPalette { index, palette_len })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that a raster is within GIF dimension limits
+fn check_raster_size(width: u32, height: u32) -> Result<()> {
+    let max = u32::from(u16::MAX);
+    if width > max || height > max {
+        Err(Error::RasterTooLarge { width, height })
+    } else {
+        Ok(())
+    }
+}
+
+/// Make an image description block
+fn make_image_desc(
+    raster: &Raster<Gray8>,
+    region: Region,
+) -> Result<ImageDesc> {
+    let left = region.x.try_into()?;
+    let top = region.y.try_into()?;
+    let width = raster.width().try_into()?;
+    let height = raster.height().try_into()?;
+    Ok(ImageDesc::default()
+        .with_left(left)
+        .with_top(top)
+        .with_width(width)
+        .with_height(height))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Decoder, Encoder};
+    use pix::{
+        gray::Gray8,
+        rgb::{Rgb, SRgb8},
+        Palette, Raster,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn high_bits() {
+        assert_eq!(next_high_bit(0), 0);
+        assert_eq!(next_high_bit(2), 1);
+        assert_eq!(next_high_bit(3), 2);
         assert_eq!(next_high_bit(4), 2);
         assert_eq!(next_high_bit(5), 3);
         assert_eq!(next_high_bit(7), 3);
         assert_eq!(next_high_bit(8), 3);
         assert_eq!(next_high_bit(9), 4);
         assert_eq!(next_high_bit(16), 4);
+        assert_eq!(next_high_bit(256), 8);
     }
 
     /// Check a raster encode
@@ -487,7 +1594,7 @@ mod test {
     /// Encoded 2x2 gif data
     const GIF_2X2: &[u8] = &[
         71, 73, 70, 56, 57, 97, 2, 0, 2, 0, 128, 0, 0, 0, 255, 0, 0, 255, 255,
-        44, 0, 0, 0, 0, 2, 0, 2, 0, 0, 2, 2, 12, 16, 0, 59,
+        44, 0, 0, 0, 0, 2, 0, 2, 0, 0, 2, 3, 12, 16, 5, 0, 59,
     ];
 
     #[test]
@@ -505,7 +1612,7 @@ mod test {
     const GIF_3X3: &[u8] = &[
         71, 73, 70, 56, 57, 97, 3, 0, 3, 0, 162, 0, 0, 255, 0, 0, 0, 255, 0, 0,
         0, 255, 255, 255, 0, 255, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 44, 0, 0,
-        0, 0, 3, 0, 3, 0, 0, 3, 5, 24, 176, 2, 4, 35, 0, 59,
+        0, 0, 3, 0, 3, 0, 0, 3, 6, 24, 176, 2, 4, 35, 1, 0, 59,
     ];
 
     #[test]
@@ -542,4 +1649,1169 @@ mod test {
         palette.set_entry(SRgb8::new(0xFF, 0xFF, 0));
         check_encode(palette, raster, GIF_4X4);
     }
+
+    #[test]
+    fn enc_comment() {
+        let long = "x".repeat(300);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_comment("first")
+            .with_comment(&long);
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(2, 2), palette);
+        enc.encode_step(&step).unwrap();
+        let mut enc = enc.with_comment("late");
+        assert!(matches!(
+            enc.encode_step(&step),
+            Err(Error::InvalidBlockSequence)
+        ));
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.comments.len(), 2);
+        let sub_blocks: Vec<_> = preamble.comments[0].comments().collect();
+        assert_eq!(sub_blocks, [b"first"]);
+        let sub_blocks: Vec<_> = preamble.comments[1].comments().collect();
+        assert_eq!(sub_blocks.len(), 2);
+        assert_eq!(sub_blocks[0].len(), 255);
+        assert_eq!(sub_blocks[1].len(), 45);
+        assert_eq!(sub_blocks.concat(), long.as_bytes());
+    }
+
+    #[test]
+    fn enc_global_color_table() {
+        let mut global = Palette::new(3);
+        global.set_entry(SRgb8::new(0, 0, 0xFF));
+        global.set_entry(SRgb8::new(0xFF, 0, 0));
+        global.set_entry(SRgb8::new(0, 0xFF, 0));
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_global_color_table(&global);
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0xFF, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0, 0));
+        let mut raster = Raster::with_clear(2, 1);
+        *raster.pixel_mut(1, 0) = Gray8::new(1);
+        enc.encode_step(&Step::with_indexed(raster.clone(), palette))
+            .unwrap();
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0xFF));
+        palette.set_entry(SRgb8::new(0xFF, 0, 0));
+        enc.encode_step(&Step::with_indexed(raster, palette))
+            .unwrap();
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let tbl = preamble.global_color_table.unwrap();
+        // padded with black
+        assert_eq!(Palette::from(&tbl).colors()[..3], *global.colors());
+        // colors remapped to the global color table
+        let frame = frames.next().unwrap().unwrap();
+        assert!(frame.local_color_table.is_none());
+        assert_eq!(frame.image_data.data(), [2, 1]);
+        // white is not in the global color table
+        let frame = frames.next().unwrap().unwrap();
+        assert!(frame.local_color_table.is_some());
+        assert_eq!(frame.image_data.data(), [0, 1]);
+    }
+
+    #[test]
+    fn enc_global_color_table_duplicates() {
+        let red = SRgb8::new(0xFF, 0, 0);
+        let green = SRgb8::new(0, 0xFF, 0);
+        // global table with a duplicate entry
+        let mut global = Palette::new(3);
+        global.set_entry(green);
+        global.set_entry(red);
+        global.set_entry(SRgb8::default());
+        global.replace_entry(2, green);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_global_color_table(&global);
+        // transparent entry duplicates an opaque color
+        let mut palette = Palette::new(3);
+        palette.set_entry(red);
+        palette.set_entry(green);
+        palette.set_entry(SRgb8::default());
+        palette.replace_entry(2, red);
+        let pixels: Vec<Gray8> =
+            [0, 1, 2, 0].iter().map(|i| Gray8::new(*i)).collect();
+        let step =
+            Step::with_indexed(Raster::with_pixels(4, 1, pixels), palette)
+                .with_transparent_color(Some(2));
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let tbl = preamble.global_color_table.unwrap();
+        let colors: Vec<_> = tbl.iter().collect();
+        assert_eq!(colors, [green, red, green, SRgb8::default()]);
+        let frame = frames.next().unwrap().unwrap();
+        assert!(frame.local_color_table.is_none());
+        assert_eq!(frame.image_data.data(), [1, 0, 2, 1]);
+        assert_eq!(frame.transparent_color(), Some(2));
+    }
+
+    #[test]
+    fn enc_comment_utf8() {
+        let text: String = "αβγ→✓x".chars().cycle().take(1000).collect();
+        let mut bytes = vec![];
+        let mut enc =
+            Encoder::new(&mut bytes).into_step_enc().with_comment(&text);
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(2, 2), palette);
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let sub_blocks: Vec<_> = preamble.comments[0].comments().collect();
+        for b in &sub_blocks {
+            assert!(std::str::from_utf8(b).is_ok());
+        }
+        assert_eq!(sub_blocks.concat(), text.as_bytes());
+    }
+
+    #[test]
+    fn enc_application() {
+        let data: Vec<u8> = (0..400).map(|v| v as u8).collect();
+        let mut app = Application::new(*b"GIFTTEST", *b"1.0");
+        app.add_data(&data);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_loop_count(0)
+            .with_application(app.clone());
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        let blocks: Vec<Block> = crate::Decoder::new(&bytes[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[3] {
+            Block::Application(b) => assert_eq!(b.loop_count(), Some(0)),
+            _ => panic!(),
+        }
+        match &blocks[4] {
+            Block::Application(b) => {
+                assert_eq!(b, &app);
+                let app_data: Vec<_> = b.app_data().collect();
+                assert_eq!(app_data[0], b"GIFTTEST1.0");
+                assert_eq!(app_data[1..].concat(), data);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn enc_application_1k() {
+        let data: Vec<u8> = (0..1024).map(|v| (v % 251) as u8).collect();
+        let mut app = Application::new(*b"GIFTTEST", *b"1.0");
+        app.add_data(&data);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        enc.encode(LogicalScreenDesc::default()).unwrap();
+        enc.encode(app.clone()).unwrap();
+        enc.encode(Trailer::default()).unwrap();
+        // 11 byte ID + 5 data sub-blocks (4 full) + terminator
+        assert_eq!(bytes.len(), 6 + 7 + 2 + 12 + 1024 + 5 + 1 + 1);
+        let blocks: Vec<Block> = crate::Decoder::new(&bytes[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[2] {
+            Block::Application(b) => {
+                assert_eq!(b, &app);
+                let app_data: Vec<_> = b.app_data().collect();
+                assert_eq!(app_data.len(), 6);
+                assert_eq!(app_data[0], b"GIFTTEST1.0");
+                assert_eq!(app_data[5].len(), 4);
+                assert_eq!(app_data[1..].concat(), data);
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Encode a step with a background color, returning the preamble
+    fn background_preamble<F>(f: F) -> Result<Preamble>
+    where
+        F: FnOnce(StepEnc<&mut Vec<u8>>) -> StepEnc<&mut Vec<u8>>,
+    {
+        let mut bytes = vec![];
+        let mut enc = f(Encoder::new(&mut bytes).into_step_enc());
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0xFF, 0, 0));
+        palette.set_entry(SRgb8::new(0, 0xFF, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        enc.encode_step(&step)?;
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        Ok(frames.preamble()?.unwrap())
+    }
+
+    #[test]
+    fn enc_background_color() {
+        let pre = background_preamble(|e| e).unwrap();
+        assert_eq!(pre.logical_screen_desc.background_color_idx(), 0);
+        let pre = background_preamble(|e| {
+            e.with_background_color(SRgb8::new(0, 0xFF, 0))
+        })
+        .unwrap();
+        assert_eq!(pre.logical_screen_desc.background_color_idx(), 1);
+        assert_eq!(pre.global_color_table.unwrap().len(), 2);
+        let pre = background_preamble(|e| {
+            e.with_background_color(SRgb8::new(0, 0, 0xFF))
+        })
+        .unwrap();
+        assert_eq!(pre.logical_screen_desc.background_color_idx(), 2);
+        let tbl = pre.global_color_table.unwrap();
+        assert_eq!(tbl.len(), 4);
+        assert_eq!(&tbl.colors()[6..9], &[0, 0, 0xFF]);
+        let pre =
+            background_preamble(|e| e.with_background_color_idx(1)).unwrap();
+        assert_eq!(pre.logical_screen_desc.background_color_idx(), 1);
+        assert!(matches!(
+            background_preamble(|e| e.with_background_color_idx(2)),
+            Err(Error::InvalidColorIndex)
+        ));
+    }
+
+    #[test]
+    fn enc_palette_too_large() {
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_background_color(SRgb8::new(0xFF, 0xFF, 0xFF));
+        let mut palette = Palette::new(256);
+        for v in 0..=255 {
+            palette.set_entry(SRgb8::new(v, 0, 0));
+        }
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        assert!(matches!(
+            enc.encode_step(&step),
+            Err(Error::PaletteTooLarge(257))
+        ));
+        drop(enc);
+        assert!(bytes.is_empty());
+    }
+
+    /// Make an indexed step from pixel indices and colors
+    fn indexed_step(indices: &[u8], colors: &[SRgb8]) -> Step {
+        let pixels: Vec<Gray8> =
+            indices.iter().map(|i| Gray8::new(*i)).collect();
+        let raster = Raster::with_pixels(4, 4, pixels);
+        let mut palette = Palette::new(256);
+        for clr in colors {
+            palette.set_entry(*clr);
+        }
+        Step::with_indexed(raster, palette)
+    }
+
+    #[test]
+    fn enc_all_global() {
+        let red = SRgb8::new(0xFF, 0, 0);
+        let green = SRgb8::new(0, 0xFF, 0);
+        let blue = SRgb8::new(0, 0, 0xFF);
+        #[rustfmt::skip]
+        let indices = [
+            0, 0, 1, 1,
+            0, 0, 1, 1,
+            1, 1, 0, 0,
+            1, 1, 0, 0,
+        ];
+        let step0 = indexed_step(&indices, &[red, green]);
+        let step1 = indexed_step(&indices, &[blue, red]);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        enc.encode_all(vec![step0, step1]).unwrap();
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert_eq!(preamble.global_color_table.unwrap().len(), 4);
+        for frame in frames {
+            assert!(frame.unwrap().local_color_table.is_none());
+        }
+        let rasters: Vec<_> = crate::Decoder::new(&bytes[..])
+            .into_steps()
+            .map(|s| s.unwrap().raster().into_owned())
+            .collect();
+        assert_eq!(rasters.len(), 2);
+        let pix = |clr: SRgb8| {
+            pix::rgb::SRgba8::new(
+                u8::from(Rgb::red(clr)),
+                u8::from(Rgb::green(clr)),
+                u8::from(Rgb::blue(clr)),
+                255,
+            )
+        };
+        for (raster, clrs) in rasters.iter().zip([[red, green], [blue, red]]) {
+            for (p, i) in raster.pixels().iter().zip(indices.iter()) {
+                assert_eq!(*p, pix(clrs[usize::from(*i)]));
+            }
+        }
+    }
+
+    #[test]
+    fn enc_all_transparent_duplicate() {
+        let red = SRgb8::new(0xFF, 0, 0);
+        let green = SRgb8::new(0, 0xFF, 0);
+        #[rustfmt::skip]
+        let indices = [
+            0, 0, 1, 1,
+            0, 0, 1, 1,
+            2, 2, 0, 0,
+            2, 2, 0, 0,
+        ];
+        let pixels: Vec<Gray8> =
+            indices.iter().map(|i| Gray8::new(*i)).collect();
+        let mut palette = Palette::new(3);
+        palette.set_entry(red);
+        palette.set_entry(green);
+        palette.set_entry(SRgb8::default());
+        // transparent entry duplicates an opaque color
+        palette.replace_entry(2, red);
+        let step =
+            Step::with_indexed(Raster::with_pixels(4, 4, pixels), palette);
+        let step = step.with_transparent_color(Some(2));
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        enc.encode_all(vec![step.clone(), step]).unwrap();
+        drop(enc);
+        let rasters: Vec<_> = crate::Decoder::new(&bytes[..])
+            .into_steps()
+            .map(|s| s.unwrap().raster().into_owned())
+            .collect();
+        assert_eq!(rasters.len(), 2);
+        let red = pix::rgb::SRgba8::new(0xFF, 0, 0, 0xFF);
+        let green = pix::rgb::SRgba8::new(0, 0xFF, 0, 0xFF);
+        let clear = pix::rgb::SRgba8::default();
+        for raster in rasters {
+            for (p, i) in raster.pixels().iter().zip(indices.iter()) {
+                assert_eq!(*p, [red, green, clear][usize::from(*i)]);
+            }
+        }
+    }
+
+    #[test]
+    fn enc_all_local() {
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        let steps = (0..2).map(|s| {
+            let pixels: Vec<Gray8> = (0..=255).map(Gray8::new).collect();
+            let raster = Raster::with_pixels(16, 16, pixels);
+            let mut palette = Palette::new(256);
+            for v in 0..=255 {
+                palette.set_entry(SRgb8::new(s, v, 0));
+            }
+            Step::with_indexed(raster, palette)
+        });
+        enc.encode_all(steps).unwrap();
+        drop(enc);
+        let mut frames = crate::Decoder::new(&bytes[..]).into_frames();
+        frames.preamble().unwrap().unwrap();
+        let frames: Vec<_> = frames.map(|f| f.unwrap()).collect();
+        assert!(frames[0].local_color_table.is_none());
+        assert!(frames[1].local_color_table.is_some());
+    }
+
+    #[test]
+    fn enc_87a() {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        let mut enc = Encoder::new(vec![])
+            .into_step_enc()
+            .with_version(Version::Gif87a);
+        enc.encode_step(&step).unwrap();
+        let bytes = enc.finish().unwrap();
+        assert_eq!(&bytes[..6], b"GIF87a");
+        assert!(!bytes.contains(&0x21), "extension introducer");
+        for block in crate::Decoder::new(&bytes[..]).into_blocks() {
+            match block.unwrap() {
+                Block::GraphicControl(_)
+                | Block::Comment(_)
+                | Block::Application(_)
+                | Block::PlainText(_)
+                | Block::Unknown(_) => panic!("extension block"),
+                _ => (),
+            }
+        }
+        let enc87a = || {
+            Encoder::new(vec![])
+                .into_step_enc()
+                .with_version(Version::Gif87a)
+        };
+        let delayed = step.clone().with_delay_time_cs(Some(10));
+        let transparent = step.clone().with_transparent_color(Some(0));
+        for step in [delayed, transparent] {
+            match enc87a().encode_step(&step) {
+                Err(Error::UnsupportedExtension) => (),
+                _ => panic!("expected UnsupportedExtension"),
+            }
+        }
+        let encs = [
+            enc87a().with_loop_count(0),
+            enc87a().with_comment("comment"),
+        ];
+        for mut enc in encs {
+            match enc.encode_step(&step) {
+                Err(Error::UnsupportedExtension) => (),
+                _ => panic!("expected UnsupportedExtension"),
+            }
+        }
+    }
+
+    #[test]
+    fn enc_89a() {
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette)
+            .with_delay_time_cs(Some(10));
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        assert_eq!(&bytes[..6], b"GIF89a");
+    }
+
+    #[test]
+    fn enc_min_delay() {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        for (normalize, delay, expected) in [
+            (true, 0, 2),
+            (true, 1, 2),
+            (true, 5, 5),
+            (false, 0, 0),
+            (false, 1, 1),
+        ] {
+            let mut bytes = vec![];
+            let mut enc = Encoder::new(&mut bytes)
+                .into_step_enc()
+                .with_normalized_delays(normalize);
+            let step = step
+                .clone()
+                .with_disposal_method(DisposalMethod::Keep)
+                .with_delay_time_cs(Some(delay));
+            enc.encode_step(&step).unwrap();
+            assert_eq!(step.delay_time_cs(), Some(delay));
+            drop(enc);
+            // graphic control: introducer, label, size, flags, delay
+            let pos = bytes.windows(3).position(|w| w == [0x21, 0xF9, 0x04]);
+            let pos = pos.unwrap() + 4;
+            assert_eq!(bytes[pos..pos + 2], [expected, 0]);
+        }
+        let mut enc = Encoder::new(vec![]).into_step_enc().with_min_delay_cs(5);
+        enc.encode_step(&step).unwrap();
+        let bytes = enc.finish().unwrap();
+        assert!(!bytes.windows(2).any(|w| w == [0x21, 0xF9]));
+    }
+
+    #[test]
+    fn enc_finish() {
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        enc.encode_step(&step).unwrap();
+        let bytes = enc.finish().unwrap();
+        assert_eq!(bytes.last(), Some(&0x3B));
+        assert_eq!(bytes[bytes.len() - 2], 0);
+        let mut bytes = vec![];
+        let enc = Encoder::new(&mut bytes).into_step_enc();
+        assert!(matches!(enc.finish(), Err(Error::InvalidBlockSequence)));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn enc_frame_data_size() {
+        let desc = ImageDesc::default().with_width(2).with_height(2);
+        for (len, ok) in [(3, false), (5, false), (4, true)] {
+            let mut data = ImageData::new(len);
+            data.data_mut().resize(len, 0);
+            let frame = Frame::new(None, desc, None, data.clone());
+            let mut bytes = vec![];
+            let mut enc = Encoder::new(&mut bytes).into_frame_enc();
+            enc.encode_preamble(&Preamble::default()).unwrap();
+            let sz = enc.get_ref().len();
+            match enc.encode_frame(&frame) {
+                Ok(()) => assert!(ok),
+                Err(Error::ImageDataMismatch { expected, got }) => {
+                    assert!(!ok);
+                    assert_eq!((expected, got), (4, len));
+                    assert_eq!(enc.get_ref().len(), sz);
+                }
+                Err(e) => panic!("{e}"),
+            }
+            let mut enc =
+                Encoder::new(vec![]).into_block_enc().with_strict(true);
+            enc.encode(Header::default()).unwrap();
+            enc.encode(LogicalScreenDesc::default()).unwrap();
+            enc.encode(desc).unwrap();
+            match enc.encode(data.clone()) {
+                Ok(()) => assert!(ok),
+                Err(Error::ImageDataMismatch { expected, got }) => {
+                    assert!(!ok);
+                    assert_eq!((expected, got), (4, len));
+                }
+                Err(e) => panic!("{e}"),
+            }
+            // not checked unless strict
+            let mut enc = Encoder::new(vec![]).into_block_enc();
+            enc.encode(desc).unwrap();
+            enc.encode(data).unwrap();
+        }
+        // raw data is passed through without checking
+        let mut data = ImageData::new_raw(2);
+        data.data_mut().extend_from_slice(&[2, 0x4C, 0x01]);
+        let frame = Frame::new(None, desc, None, data);
+        let mut enc = Encoder::new(vec![]).into_frame_enc();
+        enc.encode_preamble(&Preamble::default()).unwrap();
+        enc.encode_frame(&frame).unwrap();
+    }
+
+    #[test]
+    fn enc_frame_finish() {
+        let mut enc = Encoder::new(vec![]).into_frame_enc();
+        enc.encode_preamble(&Preamble::default()).unwrap();
+        enc.encode_trailer().unwrap();
+        let bytes = enc.finish().unwrap();
+        assert_eq!(bytes, b"GIF89a\0\0\0\0\0\0\0;");
+    }
+
+    #[test]
+    fn enc_header_version() {
+        for (version, bytes) in [
+            (Version::Gif87a, b"GIF87a"),
+            (Version::Gif89a, b"GIF89a"),
+            (Version::Other(*b"90a"), b"GIF90a"),
+        ] {
+            let mut enc = Encoder::new(vec![]).into_block_enc();
+            enc.encode(Header::with_version_enum(version)).unwrap();
+            assert_eq!(enc.get_ref(), bytes);
+        }
+    }
+
+    /// Encode indexed steps with a loop count placeholder, then patch it
+    fn patch_loop_count(n_steps: u8, global: bool) -> Vec<u8> {
+        let mut palette = Palette::new(4);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0xFF));
+        let mut writer = Cursor::new(b"junk".to_vec());
+        writer.set_position(4);
+        let mut enc = Encoder::new(writer).into_step_enc();
+        if global {
+            enc = enc.with_global_color_table(&palette);
+        }
+        let mut enc = enc.with_loop_count_placeholder().unwrap();
+        let steps = (0..n_steps).map(|i| {
+            let raster = Raster::with_color(4, 4, Gray8::new(i % 2));
+            Ok(Step::with_indexed(raster, palette.clone()))
+        });
+        enc.encode_steps(steps).unwrap();
+        enc.set_loop_count(u16::from(n_steps) * 100).unwrap();
+        let bytes = enc.finish().unwrap().into_inner();
+        assert_eq!(&bytes[..4], b"junk");
+        bytes[4..].to_vec()
+    }
+
+    #[test]
+    fn enc_loop_count_patch() {
+        for (n_steps, global) in [(1, false), (3, false), (3, true)] {
+            let bytes = patch_loop_count(n_steps, global);
+            let mut frames = Decoder::new(&bytes[..]).into_frames();
+            let preamble = frames.preamble().unwrap().unwrap();
+            let loop_count = preamble.loop_count_ext.unwrap().loop_count();
+            assert_eq!(loop_count, Some(u16::from(n_steps) * 100));
+            assert_eq!(frames.count(), usize::from(n_steps));
+        }
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        let mut enc = Encoder::new(Cursor::new(vec![])).into_step_enc();
+        enc.encode_step(&step).unwrap();
+        match enc.set_loop_count(1) {
+            Err(Error::MissingLoopCountPlaceholder) => (),
+            _ => panic!("expected MissingLoopCountPlaceholder"),
+        }
+        match enc.with_loop_count_placeholder() {
+            Err(Error::InvalidBlockSequence) => (),
+            _ => panic!("expected InvalidBlockSequence"),
+        }
+    }
+
+    #[test]
+    fn enc_writer() {
+        let mut enc = Encoder::new(Cursor::new(vec![])).into_block_enc();
+        enc.encode(Header::default()).unwrap();
+        assert_eq!(enc.get_ref().get_ref(), b"GIF89a");
+        assert_eq!(enc.get_mut().position(), 6);
+        enc.flush().unwrap();
+        assert_eq!(enc.into_inner().into_inner(), b"GIF89a");
+        let mut enc = Encoder::new(Cursor::new(vec![])).into_step_enc();
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(4, 4), palette);
+        enc.encode_step(&step).unwrap();
+        let len = enc.get_ref().get_ref().len();
+        assert_eq!(&enc.get_ref().get_ref()[..6], b"GIF89a");
+        enc.encode_step(&step).unwrap();
+        enc.flush().unwrap();
+        assert!(enc.get_ref().get_ref().len() > len);
+        let bytes = enc.into_inner().into_inner();
+        assert_ne!(bytes.last(), Some(&0x3B));
+    }
+
+    #[test]
+    fn enc_ref() {
+        let blocks: Vec<Block> = Decoder::new(GIF_4X4)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        for block in &blocks {
+            enc.encode_ref(block).unwrap();
+        }
+        assert_eq!(enc.into_inner(), GIF_4X4);
+    }
+
+    #[test]
+    fn enc_ref_same_bytes() {
+        const LOGO: &[u8] = include_bytes!("../res/gift_logo.gif");
+        let blocks: Vec<Block> = Decoder::new(LOGO)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        let mut by_val = Encoder::new(vec![]).into_block_enc();
+        let mut by_ref = Encoder::new(vec![]).into_block_enc();
+        for block in &blocks {
+            by_val.encode(block.clone()).unwrap();
+            by_ref.encode_ref(block).unwrap();
+        }
+        let bytes = by_ref.into_inner();
+        assert_eq!(by_val.into_inner(), bytes);
+        let mut frames = Decoder::new(LOGO).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let mut enc = Encoder::new(vec![]).into_frame_enc();
+        enc.encode_preamble(&preamble).unwrap();
+        for frame in frames {
+            enc.encode_frame(&frame.unwrap()).unwrap();
+        }
+        assert_eq!(enc.finish().unwrap(), bytes);
+    }
+
+    #[test]
+    fn enc_interlaced() {
+        let pixels: Vec<Gray8> = (0..30).map(|i| Gray8::new(i / 3)).collect();
+        let raster = Raster::with_pixels(3, 10, pixels);
+        let mut palette = Palette::new(16);
+        for v in 0..10 {
+            palette.set_entry(SRgb8::new(v, v, v));
+        }
+        let step = Step::with_indexed(raster, palette);
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_interlaced(true);
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        for block in crate::Decoder::new(&bytes[..]).into_blocks() {
+            match block.unwrap() {
+                Block::ImageDesc(b) => assert!(b.interlaced()),
+                Block::ImageData(b) => {
+                    let rows: Vec<u8> =
+                        b.data().chunks(3).map(|r| r[0]).collect();
+                    assert_eq!(rows, [0, 8, 4, 2, 6, 1, 3, 5, 7, 9]);
+                }
+                _ => (),
+            }
+        }
+        for frame in crate::Decoder::new(&bytes[..]).into_frames() {
+            let frame = frame.unwrap();
+            let rows: Vec<u8> =
+                frame.image_data.data().chunks(3).map(|r| r[0]).collect();
+            assert_eq!(rows, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        }
+        let step = crate::Decoder::new(&bytes[..]).into_steps().next();
+        let raster = step.unwrap().unwrap().raster().into_owned();
+        for (i, p) in raster.pixels().iter().enumerate() {
+            let v = (i / 3) as u8;
+            assert_eq!(*p, pix::rgb::SRgba8::new(v, v, v, 255));
+        }
+    }
+
+    #[test]
+    fn enc_fast() {
+        let pixels: Vec<Gray8> =
+            (0..400).map(|i| Gray8::new((i % 7) as u8)).collect();
+        let raster = Raster::with_pixels(20, 20, pixels);
+        let mut palette = Palette::new(8);
+        for v in 0..7 {
+            palette.set_entry(SRgb8::new(v, v, v));
+        }
+        let step = Step::with_indexed(raster.clone(), palette);
+        let mut fast = vec![];
+        let mut enc = Encoder::new(&mut fast)
+            .into_step_enc()
+            .with_compression(Compression::Fast);
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        let mut normal = vec![];
+        let mut enc = Encoder::new(&mut normal).into_step_enc();
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        assert!(fast.len() > normal.len());
+        let frame = Decoder::new(&fast[..]).into_frames().next();
+        let frame = frame.unwrap().unwrap();
+        assert_eq!(frame.image_data.data(), raster.as_u8_slice());
+    }
+
+    #[test]
+    fn enc_max_lzw_bits() {
+        let pixels: Vec<Gray8> = (0..128 * 128_u32)
+            .map(|i| Gray8::new((i.wrapping_mul(2_654_435_761) >> 24) as u8))
+            .collect();
+        let raster = Raster::with_pixels(128, 128, pixels);
+        let mut palette = Palette::new(256);
+        for v in 0..=255 {
+            palette.set_entry(SRgb8::new(v, v, v));
+        }
+        let step = Step::with_indexed(raster.clone(), palette);
+        let mut sizes = vec![];
+        for bits in [9, 12] {
+            let mut enc =
+                Encoder::new(vec![]).into_step_enc().with_max_lzw_bits(bits);
+            enc.encode_step(&step).unwrap();
+            let bytes = enc.finish().unwrap();
+            let frame = Decoder::new(&bytes[..]).into_frames().next();
+            let frame = frame.unwrap().unwrap();
+            assert_eq!(frame.image_data.data(), raster.as_u8_slice());
+            sizes.push(bytes.len());
+        }
+        assert_ne!(sizes[0], sizes[1]);
+    }
+
+    #[test]
+    fn enc_compatible() {
+        let pixels: Vec<Gray8> = (0..128 * 128_u32)
+            .map(|i| Gray8::new((i.wrapping_mul(2_654_435_761) >> 26) as u8))
+            .collect();
+        let raster = Raster::with_pixels(128, 128, pixels);
+        let mut palette = Palette::new(64);
+        for v in 0..64 {
+            palette.set_entry(SRgb8::new(v, v, v));
+        }
+        let step = Step::with_indexed(raster.clone(), palette);
+        let mut sizes = vec![];
+        for compression in [Compression::Normal, Compression::Compatible] {
+            let mut enc = Encoder::new(vec![])
+                .into_step_enc()
+                .with_compression(compression);
+            enc.encode_step(&step).unwrap();
+            let bytes = enc.finish().unwrap();
+            let frame = Decoder::new(&bytes[..]).into_frames().next();
+            let frame = frame.unwrap().unwrap();
+            assert_eq!(frame.image_data.data(), raster.as_u8_slice());
+            sizes.push(bytes.len());
+        }
+        assert!(sizes[1] > sizes[0]);
+    }
+
+    #[test]
+    fn enc_out_of_palette() {
+        let mut raster = Raster::with_clear(4, 4);
+        *raster.pixel_mut(1, 2) = Gray8::new(5);
+        let mut palette = Palette::new(4);
+        for v in 0..4 {
+            palette.set_entry(SRgb8::new(v, v, v));
+        }
+        let step = Step::with_indexed(raster, palette);
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        match enc.encode_step(&step) {
+            Err(Error::IndexOutOfPalette { index, palette_len }) => {
+                assert_eq!(index, 5);
+                assert_eq!(palette_len, 4);
+            }
+            _ => panic!("index out of palette"),
+        }
+        let mut enc = Encoder::new(vec![])
+            .into_step_enc()
+            .with_allow_out_of_range(true);
+        enc.encode_step(&step).unwrap();
+    }
+
+    /// Encode blocks in strict mode
+    fn strict_blocks(blocks: Vec<Block>) -> Result<()> {
+        let mut enc = Encoder::new(vec![]).into_block_enc().with_strict(true);
+        for block in blocks {
+            enc.encode(block)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn enc_strict() {
+        let blocks: Vec<Block> = Decoder::new(GIF_4X4)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        strict_blocks(blocks).unwrap();
+        let tbl_cfg = ColorTableConfig::new(
+            ColorTableExistence::Present,
+            ColorTableOrdering::NotSorted,
+            2,
+        );
+        let lsd = LogicalScreenDesc::default().with_color_table_config(tbl_cfg);
+        let tbl = GlobalColorTable::with_colors(&[0; 6]);
+        let desc = ImageDesc::default().with_width(1).with_height(1);
+        let data = ImageData::new(1);
+        let strict_err = |blocks: Vec<Block>| match strict_blocks(blocks) {
+            Err(Error::InvalidBlockSequence) => (),
+            _ => panic!("invalid block sequence"),
+        };
+        strict_err(vec![data.clone().into()]);
+        strict_err(vec![Header::default().into(), desc.into()]);
+        strict_err(vec![
+            Header::default().into(),
+            LogicalScreenDesc::default().into(),
+            tbl.clone().into(),
+        ]);
+        strict_err(vec![
+            Header::default().into(),
+            lsd.into(),
+            GlobalColorTable::with_colors(&[0; 12]).into(),
+        ]);
+        strict_err(vec![
+            Header::default().into(),
+            lsd.into(),
+            tbl.clone().into(),
+            data.clone().into(),
+        ]);
+        strict_err(vec![
+            Header::default().into(),
+            lsd.into(),
+            tbl.clone().into(),
+            desc.with_color_table_config(tbl_cfg).into(),
+            data.clone().into(),
+        ]);
+        strict_err(vec![
+            Header::default().into(),
+            lsd.into(),
+            tbl.into(),
+            Trailer::default().into(),
+            desc.into(),
+        ]);
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        enc.encode(data).unwrap();
+    }
+
+    const GIF_PLAIN_TEXT: &[u8] = &[
+        71, 73, 70, 56, 57, 97, 2, 0, 2, 0, 128, 0, 0, 0, 255, 0, 0, 255, 255,
+        33, 1, 12, 0, 0, 0, 0, 2, 0, 2, 0, 1, 1, 1, 0, 2, 72, 105, 0, 44, 0, 0,
+        0, 0, 2, 0, 2, 0, 0, 2, 3, 12, 16, 5, 0, 59,
+    ];
+
+    #[test]
+    fn enc_plain_text() {
+        let blocks: Vec<Block> = Decoder::new(GIF_PLAIN_TEXT)
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[3] {
+            Block::PlainText(b) => {
+                assert_eq!(b.grid_width(), 2);
+                assert_eq!(b.cell_height(), 1);
+                assert_eq!(b.fg_color_idx(), 1);
+                assert_eq!(b.text(), [b"Hi"]);
+            }
+            _ => panic!("plain text block"),
+        }
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        for block in &blocks {
+            enc.encode_ref(block).unwrap();
+        }
+        let bytes = enc.into_inner();
+        assert_eq!(bytes, GIF_PLAIN_TEXT);
+        let decoded: Vec<Block> = Decoder::new(&bytes[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(decoded, blocks);
+        let mut b = PlainText::default();
+        b.add_sub_block(b"short");
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        match enc.encode(b) {
+            Err(Error::MalformedPlainTextExtension) => (),
+            _ => panic!("malformed plain text"),
+        }
+    }
+
+    #[test]
+    fn preamble_builder() {
+        let mut palette = Palette::new(3);
+        palette.set_entry(SRgb8::new(0xFF, 0, 0));
+        palette.set_entry(SRgb8::new(0, 0xFF, 0));
+        palette.set_entry(SRgb8::new(0, 0, 0xFF));
+        let app = Application::new(*b"GIFTTEST", *b"1.0");
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes)
+            .into_step_enc()
+            .with_loop_count(0)
+            .with_application(app.clone())
+            .with_comment("builder")
+            .with_background_color_idx(2);
+        let step =
+            Step::with_indexed(Raster::with_clear(4, 4), palette.clone());
+        enc.encode_step(&step).unwrap();
+        drop(enc);
+        let mut frames = Decoder::new(&bytes[..]).into_frames();
+        let preamble = Preamble::with_screen(4, 4)
+            .with_palette(&palette)
+            .with_background_color_idx(2)
+            .with_loop_count(0)
+            .with_application(app)
+            .with_comment("builder");
+        assert_eq!(preamble.global_color_table.as_ref().unwrap().len(), 4);
+        assert_eq!(frames.preamble().unwrap().unwrap(), preamble);
+        let mut enc = Encoder::new(vec![]).into_frame_enc();
+        enc.encode_preamble(&preamble).unwrap();
+        let pre_bytes = enc.into_inner();
+        assert_eq!(&bytes[..pre_bytes.len()], &pre_bytes[..]);
+    }
+
+    #[test]
+    fn enc_srgb() {
+        let colors = [
+            SRgb8::new(0xFF, 0, 0),
+            SRgb8::new(0, 0xFF, 0),
+            SRgb8::new(0, 0, 0xFF),
+        ];
+        let pixels: Vec<SRgb8> = (0..16).map(|i| colors[i % 3]).collect();
+        let raster = Raster::with_pixels(4, 4, pixels);
+        let mut opaque = vec![];
+        let mut enc = Encoder::new(&mut opaque).into_step_enc();
+        enc.encode_step(&Step::with_srgb(raster.clone())).unwrap();
+        drop(enc);
+        let mut alpha = vec![];
+        let mut enc = Encoder::new(&mut alpha).into_step_enc();
+        let raster = Raster::<pix::rgb::SRgba8>::with_raster(&raster);
+        enc.encode_step(&Step::with_true_color(raster)).unwrap();
+        drop(enc);
+        assert_eq!(opaque, alpha);
+    }
+
+    #[test]
+    fn enc_raster() {
+        let red = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let blue = SRgba8::new(0, 0, 0xFF, 0xFF);
+        let clear = SRgba8::default();
+        let pixels: Vec<SRgba8> =
+            (0..16).map(|i| [red, blue, clear][i % 3]).collect();
+        let raster = Raster::with_pixels(4, 4, pixels);
+        let mut bytes = vec![];
+        crate::encode_raster(&mut bytes, &raster).unwrap();
+        let blocks: Vec<Block> = Decoder::new(&bytes[..])
+            .into_blocks()
+            .map(|b| b.unwrap())
+            .collect();
+        assert!(!blocks.iter().any(|b| matches!(b, Block::Application(_))));
+        assert!(matches!(blocks.last(), Some(Block::Trailer(_))));
+        let steps: Vec<Step> = Decoder::new(&bytes[..])
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].transparent_color().is_some());
+        assert_eq!(steps[0].raster().pixels(), raster.pixels());
+        let wide = Raster::with_clear(65536, 1);
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        assert!(matches!(
+            enc.encode_raster(&wide),
+            Err(Error::RasterTooLarge {
+                width: 65536,
+                height: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn enc_raster_too_large() {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0xFF));
+        let raster = Raster::<Gray8>::with_clear(70000, 1);
+        let step = Step::with_indexed(raster, palette);
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        match enc.encode_step(&step) {
+            Err(Error::RasterTooLarge { width, height }) => {
+                assert_eq!((width, height), (70000, 1));
+            }
+            _ => panic!("expected RasterTooLarge"),
+        }
+        // nothing was written
+        assert!(enc.get_ref().is_empty());
+    }
+
+    #[test]
+    fn enc_steps_loop_count() {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        let step = Step::with_indexed(Raster::with_clear(2, 2), palette);
+        let mut bytes = vec![];
+        let mut enc =
+            Encoder::new(&mut bytes).into_step_enc().with_loop_count(3);
+        enc.encode_steps([Ok(step)]).unwrap();
+        drop(enc);
+        let mut frames = Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let loop_count = preamble.loop_count_ext.unwrap().loop_count();
+        assert_eq!(loop_count, Some(3));
+    }
+
+    #[test]
+    fn enc_steps_transcode() {
+        use pix::el::Pixel;
+        const LOGO: &[u8] = include_bytes!("../res/gift_logo.gif");
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        enc.encode_steps(Decoder::new(LOGO).into_steps()).unwrap();
+        drop(enc);
+        let mut n_steps = 0;
+        let decoded = Decoder::new(&bytes[..]).into_steps();
+        for (a, b) in Decoder::new(LOGO).into_steps().zip(decoded) {
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.delay_time_cs(), b.delay_time_cs());
+            for (pa, pb) in a.raster().pixels().iter().zip(b.raster().pixels())
+            {
+                let alpha = u8::from(pa.alpha());
+                assert_eq!(alpha, u8::from(pb.alpha()));
+                if alpha > 0 {
+                    assert_eq!(pa, pb);
+                }
+            }
+            n_steps += 1;
+        }
+        assert_eq!(n_steps, Decoder::new(LOGO).into_steps().count());
+        let mut frames = Decoder::new(&bytes[..]).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        assert!(preamble.global_color_table.is_some());
+        for frame in frames {
+            assert!(frame.unwrap().local_color_table.is_none());
+        }
+    }
+
+    /// Get raw image data blocks from a GIF
+    fn raw_image_data(gif: &[u8]) -> Vec<ImageData> {
+        Decoder::new(gif)
+            .raw_image_data(true)
+            .into_blocks()
+            .filter_map(|b| match b.unwrap() {
+                Block::ImageData(b) => Some(b),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enc_raw_passthrough() {
+        const LOGO: &[u8] = include_bytes!("../res/gift_logo.gif");
+        let mut frames = Decoder::new(LOGO).raw_image_data(true).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let mut enc = Encoder::new(vec![]).into_frame_enc();
+        enc.encode_preamble(&preamble).unwrap();
+        for frame in frames {
+            let frame = frame.unwrap();
+            assert!(frame.is_raw());
+            enc.encode_frame(&frame).unwrap();
+        }
+        let bytes = enc.finish().unwrap();
+        let raw = raw_image_data(LOGO);
+        assert!(raw.iter().all(|b| b.is_raw()));
+        assert_eq!(raw, raw_image_data(&bytes));
+        let frames = Decoder::new(&bytes[..]).into_frames();
+        for (a, b) in Decoder::new(LOGO).into_frames().zip(frames) {
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert!(!b.is_raw());
+            assert_eq!(a.image_data, b.image_data);
+        }
+    }
+
+    #[test]
+    fn enc_raw_malformed() {
+        let mut data = ImageData::new_raw(2);
+        data.data_mut().extend_from_slice(&[3, 12, 16]);
+        let mut enc = Encoder::new(vec![]).into_block_enc();
+        match enc.encode(data) {
+            Err(Error::Io(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData)
+            }
+            r => panic!("unexpected result: {r:?}"),
+        }
+    }
+
+    #[test]
+    fn enc_step_region() {
+        let colors = [SRgb8::new(0, 0, 0), SRgb8::new(0xFF, 0xFF, 0xFF)];
+        let region = Region::new(1, 2, 2, 1);
+        let steps = [
+            indexed_step(&[0; 16], &colors),
+            indexed_step(
+                &[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0],
+                &colors,
+            )
+            .with_region(region),
+        ];
+        let mut bytes = vec![];
+        let mut enc = Encoder::new(&mut bytes).into_step_enc();
+        for step in &steps {
+            enc.encode_step(step).unwrap();
+        }
+        drop(enc);
+        let frames: Vec<Frame> = Decoder::new(&bytes[..])
+            .into_frames()
+            .map(|f| f.unwrap())
+            .collect();
+        assert_eq!(frames[1].region(), region);
+        assert_eq!(frames[1].image_data.data(), [1, 1]);
+        let decoded: Vec<Step> = Decoder::new(&bytes[..])
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(decoded[0].region(), Region::new(0, 0, 4, 4));
+        assert_eq!(decoded[1].region(), region);
+        // re-encode decoded steps
+        let mut again = vec![];
+        let mut enc = Encoder::new(&mut again).into_step_enc();
+        for step in &decoded {
+            enc.encode_step(step).unwrap();
+        }
+        drop(enc);
+        let steps = Decoder::new(&again[..]).into_steps();
+        for (a, b) in decoded.iter().zip(steps) {
+            let b = b.unwrap();
+            assert_eq!(a.region(), b.region());
+            assert_eq!(a.raster().pixels(), b.raster().pixels());
+        }
+    }
+
+    #[test]
+    fn enc_step_region_bounds() {
+        let colors = [SRgb8::new(0, 0, 0)];
+        let step = indexed_step(&[0; 16], &colors)
+            .with_region(Region::new(3, 3, 2, 2));
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        assert!(matches!(
+            enc.encode_step(&step),
+            Err(Error::InvalidFrameDimensions)
+        ));
+    }
 }
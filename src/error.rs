@@ -2,12 +2,19 @@
 //
 // Copyright (c) 2019-2023  Douglas Lau
 //
+use crate::block::Version;
 use std::fmt;
 use std::io;
 use std::num::TryFromIntError;
 
-/// Errors encountered while decoding or encoding
+/// Errors encountered while decoding or encoding.
+///
+/// New variants may be added in minor releases; use [kind] to handle
+/// categories of errors.
+///
+/// [kind]: #method.kind
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// A wrapped I/O error.
     Io(io::Error),
@@ -16,7 +23,7 @@ pub enum Error {
     /// [Header](block/struct.Header.html) block malformed or missing.
     MalformedHeader,
     /// GIF version not supported (87a or 89a only).
-    UnsupportedVersion([u8; 3]),
+    UnsupportedVersion(Version),
     /// Invalid [Block](block/enum.Block.html) code (signature).
     InvalidBlockCode,
     /// [Block](block/enum.Block.html)s arranged in invalid sequence.
@@ -24,15 +31,32 @@ pub enum Error {
     /// [GraphicControl](block/struct.GraphicControl.html) block has invalid
     /// length.
     MalformedGraphicControlExtension,
+    /// [PlainText](block/struct.PlainText.html) block has invalid header.
+    MalformedPlainTextExtension,
     /// File ends with incomplete block.
     UnexpectedEndOfFile,
     /// Compressed LZW data invalid or corrupt
     InvalidLzwData,
+    /// LZW minimum code size in [ImageData](block/struct.ImageData.html)
+    /// not between 2 and 8.
+    InvalidLzwCodeSize(u8),
     /// Image larger than specified by
-    /// [max_image_sz](struct.Decoder.html#method.max_image_sz).
+    /// [max_image_sz](struct.Decoder.html#method.max_image_sz) or
+    /// [max_screen_sz](struct.Decoder.html#method.max_screen_sz).
     TooLargeImage,
+    /// Extension block larger than specified by
+    /// [max_extension_sz](struct.Decoder.html#method.max_extension_sz).
+    TooLargeExtension,
     /// [ImageData](block/struct.ImageData.html) block is incomplete.
     IncompleteImageData,
+    /// [ImageData](block/struct.ImageData.html) length does not match the
+    /// size of its [ImageDesc](block/struct.ImageDesc.html).
+    ImageDataMismatch {
+        /// Image size from descriptor
+        expected: usize,
+        /// Length of image data
+        got: usize,
+    },
     /// Frame location / size larger than sreen size.
     InvalidFrameDimensions,
     /// Missing color table for a frame.
@@ -41,16 +65,166 @@ pub enum Error {
     InvalidColorIndex,
     /// Invalid Raster dimensions
     InvalidRasterDimensions,
+    /// Raster larger than a GIF allows (65535 x 65535 pixels).
+    RasterTooLarge {
+        /// Raster width
+        width: u32,
+        /// Raster height
+        height: u32,
+    },
+    /// Raster contains a color index outside of its palette.
+    IndexOutOfPalette {
+        /// Largest color index in the raster
+        index: u8,
+        /// Number of entries in the palette
+        palette_len: usize,
+    },
+    /// Color count not between 2 and 256.
+    InvalidColorCount,
+    /// Color table length (entries) not between 1 and 256.
+    InvalidColorTableLen(usize),
+    /// Palette has more colors than a color table allows (256).
+    PaletteTooLarge(usize),
+    /// Color table data size (bytes) not a multiple of 3.
+    InvalidColorTableSize(usize),
+    /// File ends within a color table.
+    TruncatedColorTable {
+        /// Declared size of color table (bytes)
+        expected: usize,
+        /// Number of bytes before end of file
+        actual: usize,
+    },
+    /// Extension block (graphic control, comment, application or loop count)
+    /// not supported by GIF version 87a.
+    UnsupportedExtension,
+    /// Data found after [Trailer](block/struct.Trailer.html) block.
+    TrailingData {
+        /// Number of bytes after trailer
+        bytes: usize,
+    },
+    /// Loop count cannot be patched without a placeholder
+    /// [Application](block/struct.Application.html) block.
+    MissingLoopCountPlaceholder,
+    /// Error from one of several sources, such as animations chained by
+    /// [concat](tools/fn.concat.html).
+    Source {
+        /// Index of the source
+        index: usize,
+        /// Error from the source
+        error: Box<Error>,
+    },
+}
+
+/// Category of an [Error](enum.Error.html).
+///
+/// Get with Error.[kind](enum.Error.html#method.kind).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// I/O error from the reader or writer
+    Io,
+    /// GIF data malformed, truncated or corrupt
+    Malformed,
+    /// GIF feature or version not supported
+    Unsupported,
+    /// Size limit exceeded, either configured or from the GIF format
+    LimitExceeded,
+    /// Invalid arguments or data passed to the API
+    Api,
 }
 
 /// Gift result type
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Get the category of an error.
+    ///
+    /// For [Source] errors, this is the kind of the inner error.
+    ///
+    /// ## Example
+    /// ```
+    /// use gift::{Decoder, ErrorKind};
+    ///
+    /// let gif = b"GIF89a";
+    /// let err = Decoder::new(&gif[..]).into_frames().next().unwrap();
+    /// assert_eq!(err.unwrap_err().kind(), ErrorKind::Malformed);
+    /// ```
+    ///
+    /// [Source]: #variant.Source
+    pub fn kind(&self) -> ErrorKind {
+        use Error::*;
+        match self {
+            Io(_) => ErrorKind::Io,
+            MalformedHeader
+            | InvalidBlockCode
+            | InvalidBlockSequence
+            | MalformedGraphicControlExtension
+            | MalformedPlainTextExtension
+            | UnexpectedEndOfFile
+            | InvalidLzwData
+            | InvalidLzwCodeSize(_)
+            | IncompleteImageData
+            | InvalidFrameDimensions
+            | MissingColorTable
+            | InvalidColorIndex
+            | TruncatedColorTable { .. }
+            | TrailingData { .. } => ErrorKind::Malformed,
+            UnsupportedVersion(_) | UnsupportedExtension => {
+                ErrorKind::Unsupported
+            }
+            TryFromInt(_)
+            | TooLargeImage
+            | TooLargeExtension
+            | RasterTooLarge { .. } => ErrorKind::LimitExceeded,
+            ImageDataMismatch { .. }
+            | InvalidRasterDimensions
+            | IndexOutOfPalette { .. }
+            | InvalidColorCount
+            | InvalidColorTableLen(_)
+            | PaletteTooLarge(_)
+            | InvalidColorTableSize(_)
+            | MissingLoopCountPlaceholder => ErrorKind::Api,
+            Source { error, .. } => error.kind(),
+        }
+    }
+
+    /// Check if an error is from I/O
+    pub fn is_io(&self) -> bool {
+        self.kind() == ErrorKind::Io
+    }
+
+    /// Check if an error is from malformed GIF data
+    pub fn is_malformed(&self) -> bool {
+        self.kind() == ErrorKind::Malformed
+    }
+
+    /// Convert into an I/O error.
+    ///
+    /// An `Io` error is unwrapped; all others become `InvalidData` I/O
+    /// errors, with the original error as their inner error.
+    pub fn into_io(self) -> io::Error {
+        match self {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Io(err) => err.fmt(fmt),
             Error::TryFromInt(err) => err.fmt(fmt),
+            Error::RasterTooLarge { width, height } => {
+                let max = u16::MAX;
+                write!(
+                    fmt,
+                    "raster too large: {width}x{height} (max {max}x{max})"
+                )
+            }
+            Error::Source { index, error } => {
+                write!(fmt, "source {index}: {error}")
+            }
             _ => fmt::Debug::fmt(self, fmt),
         }
     }
@@ -61,6 +235,7 @@ impl std::error::Error for Error {
         match *self {
             Error::Io(ref err) => Some(err),
             Error::TryFromInt(ref err) => Some(err),
+            Error::Source { ref error, .. } => Some(error.as_ref()),
             _ => None,
         }
     }
@@ -72,8 +247,136 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        err.into_io()
+    }
+}
+
 impl From<TryFromIntError> for Error {
     fn from(err: TryFromIntError) -> Self {
         Error::TryFromInt(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        use ErrorKind::*;
+        let errors = [
+            (Error::Io(io::ErrorKind::Other.into()), Io),
+            (u8::try_from(256_u16).unwrap_err().into(), LimitExceeded),
+            (Error::MalformedHeader, Malformed),
+            (
+                Error::UnsupportedVersion(Version::Other(*b"90a")),
+                Unsupported,
+            ),
+            (Error::InvalidBlockCode, Malformed),
+            (Error::InvalidBlockSequence, Malformed),
+            (Error::MalformedGraphicControlExtension, Malformed),
+            (Error::MalformedPlainTextExtension, Malformed),
+            (Error::UnexpectedEndOfFile, Malformed),
+            (Error::InvalidLzwData, Malformed),
+            (Error::InvalidLzwCodeSize(9), Malformed),
+            (Error::TooLargeImage, LimitExceeded),
+            (Error::TooLargeExtension, LimitExceeded),
+            (Error::IncompleteImageData, Malformed),
+            (
+                Error::ImageDataMismatch {
+                    expected: 4,
+                    got: 3,
+                },
+                Api,
+            ),
+            (Error::InvalidFrameDimensions, Malformed),
+            (Error::MissingColorTable, Malformed),
+            (Error::InvalidColorIndex, Malformed),
+            (Error::InvalidRasterDimensions, Api),
+            (
+                Error::RasterTooLarge {
+                    width: 70000,
+                    height: 1,
+                },
+                LimitExceeded,
+            ),
+            (
+                Error::IndexOutOfPalette {
+                    index: 4,
+                    palette_len: 2,
+                },
+                Api,
+            ),
+            (Error::InvalidColorCount, Api),
+            (Error::InvalidColorTableLen(0), Api),
+            (Error::PaletteTooLarge(300), Api),
+            (Error::InvalidColorTableSize(4), Api),
+            (
+                Error::TruncatedColorTable {
+                    expected: 6,
+                    actual: 3,
+                },
+                Malformed,
+            ),
+            (Error::UnsupportedExtension, Unsupported),
+            (Error::TrailingData { bytes: 1 }, Malformed),
+            (Error::MissingLoopCountPlaceholder, Api),
+            (
+                Error::Source {
+                    index: 1,
+                    error: Box::new(Error::TooLargeImage),
+                },
+                LimitExceeded,
+            ),
+        ];
+        for (err, kind) in errors {
+            assert_eq!(err.kind(), kind, "{err:?}");
+            assert_eq!(err.is_io(), kind == Io);
+            assert_eq!(err.is_malformed(), kind == Malformed);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let err = Error::RasterTooLarge {
+            width: 70000,
+            height: 1,
+        };
+        assert_eq!(
+            err.to_string(),
+            "raster too large: 70000x1 (max 65535x65535)"
+        );
+    }
+
+    #[test]
+    fn into_io() {
+        let err = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "x"));
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(err.get_ref().unwrap().downcast_ref::<Error>().is_none());
+        let err = io::Error::from(Error::TrailingData { bytes: 3 });
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::TrailingData { bytes: 3 })
+        ));
+    }
+
+    #[test]
+    fn io_round_trip() {
+        use std::error::Error as _;
+        let err = Error::from(Error::InvalidLzwData.into_io());
+        assert!(err.is_io());
+        let source = err.source().unwrap();
+        let io_err = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        let inner = io_err.get_ref().unwrap().downcast_ref::<Error>();
+        assert!(matches!(inner, Some(Error::InvalidLzwData)));
+        // unwrapped again without another layer of boxing
+        let io_err = err.into_io();
+        let inner = io_err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(*inner, Error::InvalidLzwData));
+    }
+}
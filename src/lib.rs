@@ -29,6 +29,12 @@
 //! # }
 //! ```
 //!
+//! ## Thread Safety
+//!
+//! All decoder and encoder types are `Send` when their reader or writer is
+//! `Send`, and `Sync` when it is `Sync`.  [Step]s (including shared rasters)
+//! and [Block](block/enum.Block.html)s are always `Send` and `Sync`.
+//!
 #![doc(
     html_logo_url = "https://raw.githubusercontent.com/DougLau/gift/master/res/gift_logo.gif"
 )]
@@ -37,12 +43,75 @@
 #[macro_use]
 extern crate log;
 
+pub mod analysis;
+mod animation;
 pub mod block;
 pub mod decode;
+pub mod diff;
 pub mod encode;
 mod error;
 mod lzw;
+pub mod optimize;
 mod private;
+mod quantize;
+pub mod tools;
+pub mod validate;
+
+pub use crate::animation::Animation;
+pub use crate::error::{Error, ErrorKind, Result};
+pub use crate::private::{
+    encode_raster, from_bytes, open, save, ByteOrder, Decoder, Encoder, Step,
+};
+pub use crate::validate::validate;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    fn assert_send<T: Send>() {}
+
+    fn assert_sync<T: Sync>() {}
 
-pub use crate::error::{Error, Result};
-pub use crate::private::{Decoder, Encoder, Step};
+    #[test]
+    fn send_sync() {
+        assert_send::<Decoder<File>>();
+        assert_sync::<Decoder<File>>();
+        assert_send::<decode::Blocks<File>>();
+        assert_sync::<decode::Blocks<File>>();
+        assert_send::<decode::BlockRefs<'_>>();
+        assert_sync::<decode::BlockRefs<'_>>();
+        assert_send::<decode::Comments<File>>();
+        assert_sync::<decode::Comments<File>>();
+        assert_send::<decode::Metadata>();
+        assert_sync::<decode::Metadata>();
+        assert_send::<decode::Frames<File>>();
+        assert_sync::<decode::Frames<File>>();
+        assert_send::<decode::Steps<File>>();
+        assert_sync::<decode::Steps<File>>();
+        assert_send::<diff::DirtyRegions<File>>();
+        assert_sync::<diff::DirtyRegions<File>>();
+        assert_send::<Step>();
+        assert_sync::<Step>();
+        assert_send::<Animation>();
+        assert_sync::<Animation>();
+        assert_send::<block::Block>();
+        assert_sync::<block::Block>();
+        assert_send::<block::Frame>();
+        assert_sync::<block::Frame>();
+        assert_send::<block::Preamble>();
+        assert_sync::<block::Preamble>();
+        assert_send::<Encoder<File>>();
+        assert_sync::<Encoder<File>>();
+        assert_send::<encode::BlockEnc<File>>();
+        assert_sync::<encode::BlockEnc<File>>();
+        assert_send::<encode::FrameEnc<File>>();
+        assert_sync::<encode::FrameEnc<File>>();
+        assert_send::<encode::StepEnc<File>>();
+        assert_sync::<encode::StepEnc<File>>();
+        assert_send::<validate::ValidationReport>();
+        assert_sync::<validate::ValidationReport>();
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    }
+}
@@ -4,7 +4,7 @@
 //
 //! Lempel-Ziv-Welch compression for GIF
 use crate::error::{Error, Result};
-use std::cmp::Ordering;
+use std::io::{self, Write};
 use std::ops::AddAssign;
 
 /// Code Bits
@@ -47,27 +47,41 @@ impl Bits {
 /// Code type
 type Code = u16;
 
-/// Node for compressor table
-#[derive(Clone, Copy, Debug)]
-struct CNode {
-    /// Next node code
-    next: Option<Code>,
-    /// Left node code
-    left: Option<Code>,
-    /// Right node code
-    right: Option<Code>,
-    /// Data value
-    data: u8,
+/// Size of compressor hash table (prime number larger than 4096 * 1.2)
+const HASH_SIZE: usize = 5003;
+
+/// Empty hash table entry
+///
+/// Codes in the table are always greater than the end code, so a valid entry
+/// can never be zero.
+const EMPTY_ENTRY: u32 = 0;
+
+/// Sub-block writer for compressed data
+struct SubBlocks<'a, W: Write> {
+    /// Underlying writer
+    writer: &'a mut W,
+    /// Staging buffer (sub-block size followed by data)
+    block: [u8; 256],
+    /// Number of data bytes in staging buffer
+    len: usize,
 }
 
 /// LZW Data Compressor
 pub struct Compressor {
-    /// Code table
-    table: Vec<CNode>,
+    /// Hash table entries (prefix code, data value and code)
+    table: Vec<u32>,
     /// Minimum code bits
     min_code_bits: u8,
     /// Current code bits
     code_bits: Bits,
+    /// Maximum code bits
+    max_bits: Bits,
+    /// Number of codes between table resets
+    reset_interval: Option<usize>,
+    /// Number of codes since last table reset
+    n_codes: usize,
+    /// Next available code
+    next_code: Code,
     /// Current code
     code: u32,
     /// Number of bits in current code
@@ -81,6 +95,10 @@ struct DNode {
     parent: Option<Code>,
     /// Data value
     data: u8,
+    /// First data value of code (from root node)
+    first: u8,
+    /// Length of code data
+    len: u16,
 }
 
 /// LZW Data Decompressor
@@ -98,55 +116,58 @@ pub struct Decompressor {
     code: u32,
     /// Number of bits in current code
     n_bits: u8,
-}
-
-impl CNode {
-    /// Create a new compressor node
-    fn new(next: Option<Code>, data: u8) -> Self {
-        CNode {
-            next,
-            left: None,
-            right: None,
-            data,
-        }
-    }
-
-    /// Get a link code
-    fn link(&self, ordering: Ordering) -> Option<Code> {
-        match ordering {
-            Ordering::Less => self.left,
-            Ordering::Equal => self.next,
-            Ordering::Greater => self.right,
-        }
-    }
-
-    /// Set a link code
-    fn set_link(&mut self, ordering: Ordering, code: Code) {
-        match ordering {
-            Ordering::Less => self.left = Some(code),
-            Ordering::Equal => self.next = Some(code),
-            Ordering::Greater => self.right = Some(code),
-        }
-    }
+    /// Maximum decompressed length
+    max_len: usize,
+    /// Decompressed data truncated to maximum length
+    truncated: bool,
+    /// End code received
+    done: bool,
 }
 
 impl Compressor {
     /// Create a new compressor
     pub fn new(min_code_bits: u8) -> Self {
-        let table = Vec::with_capacity(Bits::MAX.entries().into());
         let initial_code_bits = min_code_bits + 1;
         let code_bits = Bits::from(initial_code_bits);
         let mut com = Compressor {
-            table,
+            table: vec![EMPTY_ENTRY; HASH_SIZE],
             min_code_bits,
             code_bits,
+            max_bits: Bits::MAX,
+            reset_interval: None,
+            n_codes: 0,
+            next_code: 0,
             code: 0,
             n_bits: 0,
         };
-        com.reset_table();
+        com.next_code = com.end_code() + 1;
         com
     }
 
+    /// Set the maximum code bits.
+    ///
+    /// The value is clamped between 2 and 12, and to at least one more than
+    /// the minimum code bits.  When the code table is full, a clear code is
+    /// written and the table is reset, so codes never exceed this width.
+    pub fn with_max_bits(mut self, max_bits: u8) -> Self {
+        let max_bits = max_bits.clamp(2, Bits::MAX.0);
+        self.max_bits = Bits::from(max_bits.max(self.min_code_bits + 1));
+        self
+    }
+
+    /// Set the number of codes between table resets.
+    ///
+    /// After writing this many codes, a clear code is written and the table
+    /// is reset, even if it is not full.  This limits the table size, for
+    /// old decoders which mishandle a full table.
+    pub fn with_table_reset_interval(
+        mut self,
+        interval: Option<usize>,
+    ) -> Self {
+        self.reset_interval = interval.map(|i| i.max(1));
+        self
+    }
+
     /// Get the clear code
     fn clear_code(&self) -> Code {
         1 << self.min_code_bits
@@ -157,97 +178,187 @@ impl Compressor {
         self.clear_code() + 1
     }
 
-    /// Get the next available code
-    fn next_code(&self) -> Code {
-        self.table.len() as Code
+    /// Check if the table reset interval has elapsed
+    fn is_interval_done(&self) -> bool {
+        self.reset_interval.is_some_and(|i| self.n_codes >= i)
     }
 
-    /// Reset the table
-    fn reset_table(&mut self) {
-        self.table.clear();
-        for data in 0..self.clear_code() {
-            self.push_node(None, data as u8);
+    /// Check if the code table is full
+    fn is_table_full(&self) -> bool {
+        if self.max_bits == Bits::MAX {
+            // code width never grows past 12 bits, so the last code is used
+            self.next_code > Bits::MAX.entries()
+        } else {
+            // decoder would widen codes after one more table entry
+            self.next_code >= self.max_bits.entries()
         }
-        self.push_node(None, 0); // clear code
-        self.push_node(None, 0); // end code
-    }
-
-    /// Push a node into the table
-    fn push_node(&mut self, next: Option<Code>, data: u8) {
-        self.table.push(CNode::new(next, data))
     }
 
-    /// Get a mutable node
-    fn node_mut(&mut self, code: Code) -> &mut CNode {
-        &mut self.table[code as usize]
+    /// Reset the table
+    fn reset_table(&mut self) {
+        self.table.fill(EMPTY_ENTRY);
+        self.next_code = self.end_code() + 1;
+        self.n_codes = 0;
     }
 
-    /// Pack a code into a buffer
-    fn pack(&mut self, code: Code, buffer: &mut Vec<u8>) {
+    /// Pack a code into sub-blocks
+    fn pack<W: Write>(
+        &mut self,
+        code: Code,
+        blocks: &mut SubBlocks<W>,
+    ) -> io::Result<()> {
         self.code |= (code as u32) << self.n_bits;
         self.n_bits += u8::from(self.code_bits);
         while self.n_bits >= 8 {
-            buffer.push(self.code as u8);
+            blocks.push(self.code as u8)?;
             self.code >>= 8;
             self.n_bits -= 8;
         }
+        Ok(())
     }
 
-    /// Compress a byte buffer
-    pub fn compress(&mut self, bytes: &[u8], buffer: &mut Vec<u8>) {
-        self.pack(self.clear_code(), buffer);
+    /// Flush remaining bits into sub-blocks
+    fn flush<W: Write>(&mut self, blocks: &mut SubBlocks<W>) -> io::Result<()> {
+        if self.n_bits > 0 {
+            blocks.push(self.code as u8)?;
+            self.code = 0;
+            self.n_bits = 0;
+        }
+        blocks.flush()
+    }
+
+    /// Compress a byte buffer to a writer.
+    ///
+    /// Compressed data is written as sub-blocks of up to 255 bytes, each
+    /// preceded by its size.  The final (empty) sub-block is not written.
+    pub fn compress_to<W: Write>(
+        &mut self,
+        bytes: &[u8],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let mut blocks = SubBlocks::new(writer);
+        self.pack(self.clear_code(), &mut blocks)?;
         let mut code = None;
         for data in bytes {
-            let prefix = self.search_insert(code, *data);
-            if prefix.is_some() {
-                code = prefix;
+            let prefix = match code {
+                Some(prefix) => prefix,
+                None => {
+                    code = Some(*data as Code);
+                    continue;
+                }
+            };
+            if let Some(c) = self.search_insert(prefix, *data) {
+                code = Some(c);
                 continue;
             }
-            if let Some(code) = code {
-                self.pack(code, buffer);
+            self.pack(prefix, &mut blocks)?;
+            self.n_codes += 1;
+            if !self.is_table_full()
+                && self.next_code > self.code_bits.entries()
+            {
+                self.code_bits += 1;
             }
-            let next_code = self.next_code();
-            if next_code > self.code_bits.entries() {
-                if next_code <= Bits::MAX.entries() {
-                    self.code_bits += 1;
-                } else {
-                    self.pack(self.clear_code(), buffer);
-                    self.reset_table();
-                    let initial_code_bits = self.min_code_bits + 1;
-                    self.code_bits = Bits::from(initial_code_bits);
-                }
+            if self.is_table_full() || self.is_interval_done() {
+                self.pack(self.clear_code(), &mut blocks)?;
+                self.reset_table();
+                let initial_code_bits = self.min_code_bits + 1;
+                self.code_bits = Bits::from(initial_code_bits);
             }
             code = Some(*data as Code);
         }
         if let Some(code) = code {
-            self.pack(code, buffer);
+            self.pack(code, &mut blocks)?;
         }
-        self.pack(self.end_code(), buffer);
+        self.pack(self.end_code(), &mut blocks)?;
+        self.flush(&mut blocks)
     }
 
-    /// Search and insert a node
-    fn search_insert(&mut self, code: Option<Code>, data: u8) -> Option<Code> {
-        match code {
-            Some(code) => self.insert_node(code, data),
-            None => Some(data as Code),
+    /// Compress a byte buffer to a writer, without using the dictionary.
+    ///
+    /// Every byte is written as a literal code, with a clear code inserted
+    /// before the decoder's table would grow past the initial code bits.
+    /// This is much faster than [compress_to], but the output is larger than
+    /// the input.
+    ///
+    /// [compress_to]: struct.Compressor.html#method.compress_to
+    pub fn compress_fast_to<W: Write>(
+        &mut self,
+        bytes: &[u8],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let mut blocks = SubBlocks::new(writer);
+        // decoder adds one table entry for each code after the first
+        let n_literals = usize::from(self.clear_code()) - 2;
+        for chunk in bytes.chunks(n_literals) {
+            self.pack(self.clear_code(), &mut blocks)?;
+            for data in chunk {
+                self.pack(Code::from(*data), &mut blocks)?;
+            }
         }
+        if bytes.is_empty() {
+            self.pack(self.clear_code(), &mut blocks)?;
+        }
+        self.pack(self.end_code(), &mut blocks)?;
+        self.flush(&mut blocks)
     }
 
-    /// Insert a node
-    fn insert_node(&mut self, code: Code, data: u8) -> Option<Code> {
-        let next_code = self.next_code();
-        let mut node = self.node_mut(code);
-        let mut ordering = Ordering::Equal;
-        while let Some(code) = node.link(ordering) {
-            node = self.node_mut(code);
-            ordering = data.cmp(&node.data);
-            if ordering == Ordering::Equal {
-                return Some(code);
+    /// Search for a prefix code / data value, inserting it if not found
+    fn search_insert(&mut self, prefix: Code, data: u8) -> Option<Code> {
+        let key = (u32::from(prefix) << 8 | u32::from(data)) << Bits::MAX.0;
+        // hash function from GIFLIB
+        let mut i = (usize::from(data) << 4) ^ usize::from(prefix);
+        let disp = if i == 0 { 1 } else { HASH_SIZE - i };
+        loop {
+            let entry = self.table[i];
+            if entry == EMPTY_ENTRY {
+                // the last code is never used, since the table is reset
+                if self.next_code < Bits::MAX.entries() {
+                    self.table[i] = key | u32::from(self.next_code);
+                }
+                self.next_code += 1;
+                return None;
+            }
+            if entry & !Bits::MAX.mask() == key {
+                return Some((entry & Bits::MAX.mask()) as Code);
             }
+            i = if i >= disp {
+                i - disp
+            } else {
+                i + HASH_SIZE - disp
+            };
         }
-        node.set_link(ordering, next_code);
-        self.push_node(None, data);
-        None
+    }
+}
+
+impl<'a, W: Write> SubBlocks<'a, W> {
+    /// Create a new sub-block writer
+    fn new(writer: &'a mut W) -> Self {
+        SubBlocks {
+            writer,
+            block: [0; 256],
+            len: 0,
+        }
+    }
+
+    /// Push one byte, writing the sub-block when full
+    fn push(&mut self, data: u8) -> io::Result<()> {
+        self.len += 1;
+        self.block[self.len] = data;
+        if self.len == 255 {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write staged data as a sub-block
+    fn flush(&mut self) -> io::Result<()> {
+        if self.len > 0 {
+            self.block[0] = self.len as u8; // sub-block size
+            self.writer.write_all(&self.block[..=self.len])?;
+            self.len = 0;
+        }
+        Ok(())
     }
 }
 
@@ -264,11 +375,27 @@ impl Decompressor {
             prefix: None,
             code: 0,
             n_bits: 0,
+            max_len: usize::MAX,
+            truncated: false,
+            done: false,
         };
         dec.reset_table();
         dec
     }
 
+    /// Set the maximum decompressed length.
+    ///
+    /// Once reached, decompression stops and any remaining codes are ignored.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Check if decompressed data was truncated to the maximum length
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
     /// Get the clear code
     fn clear_code(&self) -> Code {
         1 << self.min_code_bits
@@ -296,16 +423,24 @@ impl Decompressor {
 
     /// Push a node into the table
     fn push_node(&mut self, parent: Option<Code>, data: u8) {
-        self.table.push(DNode { parent, data });
+        let (first, len) = match parent {
+            Some(code) => {
+                let node = self.table[code as usize];
+                (node.first, node.len + 1)
+            }
+            None => (data, 1),
+        };
+        self.table.push(DNode {
+            parent,
+            data,
+            first,
+            len,
+        });
     }
 
-    /// Lookup data value of a code
+    /// Lookup first data value of a code
     fn lookup(&self, code: Code) -> u8 {
-        let mut node = self.table[code as usize];
-        while let Some(code) = node.parent {
-            node = self.table[code as usize];
-        }
-        node.data
+        self.table[code as usize].first
     }
 
     /// Unpack one code from a buffer
@@ -338,6 +473,9 @@ impl Decompressor {
     ) -> Result<()> {
         let mut bytes = bytes;
         while let (Some(code), n_consumed) = self.unpack(bytes) {
+            if self.done {
+                break;
+            }
             self.decompress_code(code, buffer)?;
             bytes = &bytes[n_consumed..];
         }
@@ -356,8 +494,9 @@ impl Decompressor {
             self.code_bits = Bits::from(initial_code_bits);
             self.prefix = None;
         } else if code == self.end_code() {
-            // FIXME: end decompress loop here
+            // ignore any padding bits after end code
             self.prefix = None;
+            self.done = true;
         } else {
             self.decompress_data(code, buffer)?;
             self.prefix = Some(code);
@@ -386,7 +525,8 @@ impl Decompressor {
                 self.push_node(Some(prefix), data);
                 self.decompress_buffer(code, buffer);
             }
-            None => buffer.push(code as u8),
+            None if code < next_code => self.decompress_buffer(code, buffer),
+            None => return Err(Error::InvalidLzwData),
         }
         if next_code + 1 == self.code_bits.entries() {
             self.code_bits += 1;
@@ -396,16 +536,202 @@ impl Decompressor {
 
     /// Decompress a code into a buffer
     ///
-    /// Starts from the final code data and works backward to the start, then
-    /// finally reverses the fully decompressed code.
-    fn decompress_buffer(&self, code: Code, buffer: &mut Vec<u8>) {
-        let start = buffer.len();
+    /// Starts from the final code data and works backward to the start.  The
+    /// table has at most 4096 entries, which limits the expansion of one code.
+    /// Data past the maximum length is never written to the buffer.
+    fn decompress_buffer(&mut self, code: Code, buffer: &mut Vec<u8>) {
         let mut node = self.table[code as usize];
-        buffer.push(node.data);
-        while let Some(code) = node.parent {
-            node = self.table[code as usize];
-            buffer.push(node.data);
+        let start = buffer.len();
+        let len = usize::from(node.len);
+        let keep = len.min(self.max_len.saturating_sub(start));
+        if keep < len {
+            // skip data past the maximum length
+            for _ in keep..len {
+                if let Some(code) = node.parent {
+                    node = self.table[code as usize];
+                }
+            }
+            self.truncated = true;
+            self.done = true;
+        }
+        buffer.resize(start + keep, 0);
+        for val in buffer[start..].iter_mut().rev() {
+            *val = node.data;
+            if let Some(code) = node.parent {
+                node = self.table[code as usize];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Compress data into sub-blocks
+    fn compress(min_code_bits: u8, data: &[u8]) -> Vec<u8> {
+        let mut blocks = vec![];
+        Compressor::new(min_code_bits)
+            .compress_to(data, &mut blocks)
+            .unwrap();
+        blocks
+    }
+
+    /// Compress data into sub-blocks without the dictionary
+    fn compress_fast(min_code_bits: u8, data: &[u8]) -> Vec<u8> {
+        let mut blocks = vec![];
+        Compressor::new(min_code_bits)
+            .compress_fast_to(data, &mut blocks)
+            .unwrap();
+        blocks
+    }
+
+    /// Compress and decompress data
+    fn round_trip(min_code_bits: u8, data: &[u8]) {
+        check_blocks(min_code_bits, data, &compress(min_code_bits, data));
+        check_blocks(min_code_bits, data, &compress_fast(min_code_bits, data));
+    }
+
+    /// Decompress sub-blocks and check data
+    fn check_blocks(min_code_bits: u8, data: &[u8], blocks: &[u8]) {
+        let mut blocks = blocks;
+        let mut dec = Decompressor::new(min_code_bits);
+        let mut out = vec![];
+        while let Some((len, rest)) = blocks.split_first() {
+            let len = usize::from(*len);
+            assert!(len > 0 && len <= rest.len());
+            dec.decompress(&rest[..len], &mut out).unwrap();
+            blocks = &rest[len..];
+        }
+        assert_eq!(data, &out[..]);
+    }
+
+    #[test]
+    fn compress_2x2() {
+        assert_eq!(compress(2, &[1, 0, 0, 1]), [3, 12, 16, 5]);
+    }
+
+    #[test]
+    fn compress_fast_2x2() {
+        // clear, 1, 0, clear, 0, 1, end
+        assert_eq!(compress_fast(2, &[1, 0, 0, 1]), [3, 12, 136, 20]);
+    }
+
+    #[test]
+    fn max_len() {
+        // long runs build deep prefix chains, expanding each code further
+        let blocks = compress(2, &vec![0; 1 << 22]);
+        assert!(blocks.len() < 1 << 14);
+        let mut blocks = &blocks[..];
+        let mut dec = Decompressor::new(2).with_max_len(16);
+        let mut out = vec![];
+        while let Some((len, rest)) = blocks.split_first() {
+            let len = usize::from(*len);
+            dec.decompress(&rest[..len], &mut out).unwrap();
+            blocks = &rest[len..];
+        }
+        assert!(dec.is_truncated());
+        assert_eq!(out, [0; 16]);
+        assert!(out.capacity() <= 1 << 13);
+        let mut dec = Decompressor::new(2).with_max_len(4);
+        let mut out = vec![];
+        dec.decompress(&[12, 16, 5], &mut out).unwrap();
+        assert!(!dec.is_truncated());
+        assert_eq!(out, [1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn sub_blocks() {
+        let data: Vec<u8> = (0..4000_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 & 0xF)
+            .collect();
+        let blocks = compress(4, &data);
+        let mut sizes = vec![];
+        let mut i = 0;
+        while i < blocks.len() {
+            sizes.push(blocks[i]);
+            i += usize::from(blocks[i]) + 1;
+        }
+        assert_eq!(i, blocks.len());
+        assert!(sizes.len() > 2);
+        let (last, full) = sizes.split_last().unwrap();
+        assert!(full.iter().all(|s| *s == 255));
+        assert!(*last > 0);
+    }
+
+    /// Compress with maximum code bits, then check widths and data
+    fn check_max_bits(min_code_bits: u8, max_bits: u8, data: &[u8]) {
+        let mut blocks = vec![];
+        Compressor::new(min_code_bits)
+            .with_max_bits(max_bits)
+            .compress_to(data, &mut blocks)
+            .unwrap();
+        let mut blocks = &blocks[..];
+        let mut dec = Decompressor::new(min_code_bits);
+        let mut out = vec![];
+        let mut widest = 0;
+        while let Some((len, rest)) = blocks.split_first() {
+            let len = usize::from(*len);
+            for byte in &rest[..len] {
+                dec.decompress(&[*byte], &mut out).unwrap();
+                widest = widest.max(u8::from(dec.code_bits));
+            }
+            blocks = &rest[len..];
         }
-        buffer[start..].reverse();
+        assert_eq!(widest, max_bits);
+        assert_eq!(data, &out[..]);
+    }
+
+    #[test]
+    fn max_bits() {
+        let data: Vec<u8> = (0..100_000_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        check_max_bits(8, 9, &data);
+        check_max_bits(8, 12, &data);
+        let data: Vec<u8> = data.iter().map(|d| d & 0xF).collect();
+        check_max_bits(4, 5, &data);
+        check_max_bits(4, 10, &data);
+        assert_eq!(Compressor::new(8).max_bits, Bits::MAX);
+        assert_eq!(Compressor::new(8).with_max_bits(2).max_bits, Bits(9));
+        assert_eq!(Compressor::new(2).with_max_bits(20).max_bits, Bits::MAX);
+    }
+
+    #[test]
+    fn reset_interval() {
+        let data: Vec<u8> = (0..100_000_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 & 0x3F)
+            .collect();
+        for interval in [1, 2, 100, 1000, 5000] {
+            let mut blocks = vec![];
+            Compressor::new(6)
+                .with_table_reset_interval(Some(interval))
+                .compress_to(&data, &mut blocks)
+                .unwrap();
+            check_blocks(6, &data, &blocks);
+            assert!(blocks.len() >= compress(6, &data).len());
+        }
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 7) as u8).collect();
+        let mut blocks = vec![];
+        Compressor::new(3)
+            .with_table_reset_interval(Some(10))
+            .with_max_bits(5)
+            .compress_to(&data, &mut blocks)
+            .unwrap();
+        check_blocks(3, &data, &blocks);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(2, &[]);
+        round_trip(2, &[0]);
+        round_trip(2, &[0, 0, 0, 0]);
+        round_trip(2, &[3, 2, 1, 0, 1, 2, 3]);
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 7) as u8).collect();
+        round_trip(3, &data);
+        let data: Vec<u8> = (0..100_000_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        round_trip(8, &data);
     }
 }
@@ -6,13 +6,21 @@
 
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use gift::block::{DisposalMethod, Frame};
-use gift::Decoder;
+use gift::decode::{BlockRef, FrameStats};
+use gift::{Decoder, Encoder, Step};
+use pix::{rgb::SRgba8, Raster, Region};
+use serde::Deserialize;
 use std::error::Error;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{BufReader, Write};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+/// Block name and byte span within a file
+type BlockSpan = (&'static str, Range<usize>);
+
 /// Crate version
 const VERSION: &'static str = std::env!("CARGO_PKG_VERSION");
 
@@ -21,10 +29,16 @@ fn main() -> Result<(), Box<dyn Error>> {
     env_logger::builder().format_timestamp(None).init();
     let mut out = StandardStream::stdout(ColorChoice::Always);
     match create_app().get_matches().subcommand() {
-        ("show", Some(matches)) => show(&mut out, matches)?,
-        ("unwrap", Some(_matches)) => todo!(),
-        ("wrap", Some(_matches)) => todo!(),
-        ("peek", Some(_matches)) => todo!(),
+        ("show", Some(matches)) => {
+            if !show(&mut out, matches)? {
+                out.reset()?;
+                std::process::exit(2);
+            }
+        }
+        ("unwrap", Some(matches)) => unwrap(matches)?,
+        ("wrap", Some(matches)) => wrap(matches)?,
+        // no color, so output can be piped
+        ("peek", Some(matches)) => return peek(matches),
         _ => panic!(),
     }
     out.reset()?;
@@ -46,42 +60,115 @@ fn create_app() -> App<'static, 'static> {
                         .required(true)
                         .min_values(1)
                         .help("input file(s)"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .short("l")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("N")
+                        .help("stop after N frames"),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .short("f")
+                        .long("frames")
+                        .takes_value(true)
+                        .value_name("A..B")
+                        .help("show frames A to B (exclusive)"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("unwrap")
                 .about("Unwrap frames from a GIF")
-                .arg(Arg::with_name("file").required(true).help("input file")),
+                .arg(Arg::with_name("file").required(true).help("input file"))
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help("export color indices and palettes"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .short("d")
+                        .long("dir")
+                        .takes_value(true)
+                        .help("output directory"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("wrap")
                 .about("Wrap frames into a GIF")
-                .arg(Arg::with_name("file").required(true).help("input file")),
+                .arg(
+                    Arg::with_name("manifest")
+                        .short("m")
+                        .long("manifest")
+                        .takes_value(true)
+                        .required(true)
+                        .help("manifest file (TOML)"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("output file (overrides manifest)"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("peek")
                 .about("Peek into a GIF")
-                .arg(Arg::with_name("file").required(true).help("input file")),
+                .arg(Arg::with_name("file").required(true).help("input file"))
+                .arg(
+                    Arg::with_name("block")
+                        .short("b")
+                        .long("block")
+                        .takes_value(true)
+                        .value_name("N")
+                        .help("hex dump of block N"),
+                )
+                .arg(
+                    Arg::with_name("range")
+                        .short("r")
+                        .long("range")
+                        .takes_value(true)
+                        .value_name("START..END")
+                        .conflicts_with("block")
+                        .help("hex dump of blocks START to END (exclusive)"),
+                ),
         )
 }
 
 /// Handle show subcommand
+///
+/// Returns `false` if a decode error occurred in any file.
 fn show(
     out: &mut StandardStream,
     matches: &ArgMatches,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<bool, Box<dyn Error>> {
+    let mut shown = match matches.value_of("frames") {
+        Some(range) => parse_range(range)?,
+        None => 0..usize::MAX,
+    };
+    if let Some(limit) = matches.value_of("limit") {
+        shown.end = shown.end.min(limit.parse()?);
+    }
+    let mut ok = true;
     let values = matches.values_of_os("files").unwrap();
     for path in values {
-        show_file(out, path)?;
+        ok &= show_file(out, path, shown.clone())?;
     }
-    Ok(())
+    Ok(ok)
 }
 
 /// Show one GIF file
+///
+/// Decoding stops after the end of the `shown` frame range.  Returns `false`
+/// if a decode error occurred.
 fn show_file(
     out: &mut StandardStream,
     path: &OsStr,
-) -> Result<(), Box<dyn Error>> {
+    shown: Range<usize>,
+) -> Result<bool, Box<dyn Error>> {
     let mut magenta = ColorSpec::new();
     magenta.set_fg(Some(Color::Magenta));
     let mut red = ColorSpec::new();
@@ -96,22 +183,44 @@ fn show_file(
         .set_bold(true);
     let f = BufReader::new(File::open(&path)?);
     let mut frame_dec = Decoder::new(f).into_frames();
-    let preamble = if let Some(p) = frame_dec.preamble()? {
-        p
-    } else {
-        out.set_color(&red)?;
-        writeln!(out, "no preamble!")?;
-        return Ok(());
+    let preamble = match frame_dec.preamble() {
+        Ok(Some(p)) => p,
+        Ok(None) => {
+            out.set_color(&red)?;
+            writeln!(out, "no preamble!")?;
+            return Ok(true);
+        }
+        Err(e) => {
+            out.set_color(&red)?;
+            writeln!(out, "{:?}: {}", path, e)?;
+            return Ok(false);
+        }
     };
     let mut frames = vec![];
-    for f in frame_dec {
-        frames.push(f?);
+    let mut count = 0;
+    let mut error = None;
+    while count < shown.end {
+        match frame_dec.next() {
+            Some(Ok(f)) => {
+                if shown.contains(&count) {
+                    frames.push((count, f, frame_dec.frame_stats()));
+                }
+                count += 1;
+            }
+            Some(Err(e)) => {
+                error = Some(e);
+                break;
+            }
+            None => break,
+        }
     }
-    let frame_digits = digits(frames.len()).max(3);
+    // stopped before the end of the file?
+    let partial = count == shown.end || error.is_some();
+    let frame_digits = digits(count).max(3);
     let width = preamble.screen_width();
     let height = preamble.screen_height();
     let size_digits = 4.max(1 + digits(width) + digits(height));
-    let gif = String::from_utf8_lossy(&preamble.header.version()).to_string();
+    let gif = preamble.header.version_enum().to_string();
     let mut comments = vec![];
     for cmt in preamble.comments {
         for c in cmt.comments() {
@@ -126,7 +235,11 @@ fn show_file(
     out.set_color(&magenta)?;
     writeln!(out, "{:?}", path)?;
     out.set_color(&bold)?;
-    write!(out, "GIF{}, frames: {}", gif, frames.len())?;
+    if partial {
+        write!(out, "GIF{}, frames: ≥{}", gif, count)?;
+    } else {
+        write!(out, "GIF{}, frames: {}", gif, count)?;
+    }
     if let Some(ap) = preamble.loop_count_ext {
         if let Some(c) = ap.loop_count() {
             write!(out, ", repeat: ")?;
@@ -150,11 +263,12 @@ fn show_file(
     write!(out, "  Delay Disp")?;
     write!(out, " {:>w$}", "Size", w = size_digits)?;
     write!(out, " {:>w$}", "X,Y", w = size_digits)?;
-    writeln!(out, " Clrs Trn")?;
+    writeln!(out, " Clrs Trn   Bytes")?;
     let global_clr = preamble.logical_screen_desc.color_table_config().len();
-    for (n, f) in frames.into_iter().enumerate() {
+    for (n, f, stats) in frames {
         show_frame(
             &f,
+            stats,
             out,
             width,
             height,
@@ -164,12 +278,18 @@ fn show_file(
             size_digits,
         )?;
     }
-    Ok(())
+    if let Some(e) = error {
+        out.set_color(&red)?;
+        writeln!(out, "error in frame {}: {}", count, e)?;
+        return Ok(false);
+    }
+    Ok(true)
 }
 
 /// Show one frame of a GIF file
 fn show_frame(
     frame: &Frame,
+    stats: FrameStats,
     out: &mut StandardStream,
     width: u16,
     height: u16,
@@ -266,10 +386,380 @@ fn show_frame(
     } else {
         out.set_color(&bold)?;
     }
-    writeln!(out, " {:>3}", tc)?;
+    write!(out, " {:>3}", tc)?;
+    out.set_color(&dflt)?;
+    writeln!(out, " {:>7}", stats.compressed_len)?;
     Ok(())
 }
 
+/// Handle unwrap subcommand
+fn unwrap(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(matches.value_of_os("file").unwrap());
+    let dir = Path::new(matches.value_of_os("dir").unwrap_or(OsStr::new(".")));
+    fs::create_dir_all(dir)?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "frame".to_string());
+    let f = BufReader::new(File::open(path)?);
+    if matches.is_present("raw") {
+        unwrap_raw(f, dir, &stem)
+    } else {
+        unwrap_steps(f, dir, &stem)
+    }
+}
+
+/// Unwrap composited frames, each into a still GIF
+fn unwrap_steps(
+    f: BufReader<File>,
+    dir: &Path,
+    stem: &str,
+) -> Result<(), Box<dyn Error>> {
+    for (n, step) in Decoder::new(f).into_steps().enumerate() {
+        let path = dir.join(format!("{stem}_{n:03}.gif"));
+        gift::save(&path, [step?], None)?;
+    }
+    Ok(())
+}
+
+/// Unwrap frames without compositing, as color indices and palettes
+fn unwrap_raw(
+    f: BufReader<File>,
+    dir: &Path,
+    stem: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut frame_dec = Decoder::new(f).into_frames();
+    let global = match frame_dec.preamble()? {
+        Some(preamble) => match preamble.global_color_table {
+            Some(tbl) => {
+                let path = dir.join(format!("{stem}.pal"));
+                write_palette(&path, tbl.colors())?;
+                Some(path)
+            }
+            None => None,
+        },
+        None => return Err("no preamble".into()),
+    };
+    for (n, frame) in frame_dec.enumerate() {
+        let frame = frame?;
+        let name = format!("{stem}_{n:03}");
+        fs::write(dir.join(format!("{name}.idx")), frame.image_data.data())?;
+        let palette = match &frame.local_color_table {
+            Some(tbl) => {
+                let path = dir.join(format!("{name}.pal"));
+                write_palette(&path, tbl.colors())?;
+                Some(path)
+            }
+            None => global.clone(),
+        };
+        let info = frame_info(&frame, palette);
+        fs::write(dir.join(format!("{name}.txt")), info)?;
+    }
+    Ok(())
+}
+
+/// Write a color table as a 256-entry RGB palette file (768 bytes)
+fn write_palette(path: &Path, colors: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut pal = colors.to_vec();
+    pal.resize(768, 0);
+    fs::write(path, pal)?;
+    Ok(())
+}
+
+/// Describe a raw frame for its sidecar file
+fn frame_info(frame: &Frame, palette: Option<PathBuf>) -> String {
+    let desc = &frame.image_desc;
+    let gc = frame.graphic_control_ext.unwrap_or_default();
+    let disposal = match gc.disposal_method() {
+        DisposalMethod::NoAction => "none",
+        DisposalMethod::Keep => "keep",
+        DisposalMethod::Background => "bg",
+        DisposalMethod::Previous => "prev",
+        _ => "res",
+    };
+    let transparent = match gc.transparent_color() {
+        Some(tc) => tc.to_string(),
+        None => "-".to_string(),
+    };
+    let palette = match palette.as_deref().and_then(Path::file_name) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "-".to_string(),
+    };
+    format!(
+        "left: {}\ntop: {}\nwidth: {}\nheight: {}\ndelay: {}\n\
+         disposal: {}\ntransparent: {}\ninterlaced: {}\npalette: {}\n",
+        desc.left(),
+        desc.top(),
+        desc.width(),
+        desc.height(),
+        gc.delay_time_cs(),
+        disposal,
+        transparent,
+        desc.interlaced(),
+        palette,
+    )
+}
+
+/// Handle peek subcommand
+fn peek(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(matches.value_of_os("file").unwrap())?;
+    let blocks = block_spans(&data)?;
+    let range = if let Some(n) = matches.value_of("block") {
+        let n: usize = n.parse()?;
+        n..n + 1
+    } else if let Some(range) = matches.value_of("range") {
+        parse_range(range)?
+    } else {
+        return peek_table(&blocks);
+    };
+    if range.is_empty() || range.end > blocks.len() {
+        let which = match range.len() {
+            1 => format!("block {}", range.start),
+            _ => format!("blocks {}..{}", range.start, range.end),
+        };
+        let len = blocks.len();
+        return Err(format!("{which} out of range ({len} blocks)").into());
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for n in range {
+        let (name, span) = &blocks[n];
+        writeln!(out, "block {n}: {name}, {} bytes", span.len())?;
+        hex_dump(&mut out, &data, span.clone())?;
+    }
+    Ok(())
+}
+
+/// Get the name and byte span of each block in a GIF
+fn block_spans(data: &[u8]) -> Result<Vec<BlockSpan>, Box<dyn Error>> {
+    let mut blocks = Decoder::new(data).raw_image_data(true).into_block_refs();
+    let mut spans = vec![];
+    let mut start = blocks.position();
+    while let Some(block) = blocks.next() {
+        let end = blocks.position();
+        spans.push((block_name(&block?), start..end));
+        start = end;
+    }
+    Ok(spans)
+}
+
+/// Get the name of a block
+fn block_name(block: &BlockRef) -> &'static str {
+    match block {
+        BlockRef::Header(_) => "Header",
+        BlockRef::LogicalScreenDesc(_) => "LogicalScreenDesc",
+        BlockRef::GlobalColorTable(_) => "GlobalColorTable",
+        BlockRef::PlainText(_) => "PlainText",
+        BlockRef::GraphicControl(_) => "GraphicControl",
+        BlockRef::Comment(_) => "Comment",
+        BlockRef::Application(_) => "Application",
+        BlockRef::Unknown(_, _) => "Unknown",
+        BlockRef::ImageDesc(_) => "ImageDesc",
+        BlockRef::LocalColorTable(_) => "LocalColorTable",
+        BlockRef::ImageData(_) => "ImageData",
+        BlockRef::Trailer(_) => "Trailer",
+    }
+}
+
+/// Parse a range (START..END)
+fn parse_range(range: &str) -> Result<Range<usize>, Box<dyn Error>> {
+    match range.split_once("..") {
+        Some((start, end)) => Ok(start.parse()?..end.parse()?),
+        None => Err(format!("invalid range: {range}").into()),
+    }
+}
+
+/// Print a table of blocks
+fn peek_table(blocks: &[BlockSpan]) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{:>5} {:>8} {:>7}  Block", "Blk#", "Offset", "Bytes")?;
+    for (n, (name, span)) in blocks.iter().enumerate() {
+        writeln!(out, "{n:>5} {:>8} {:>7}  {name}", span.start, span.len())?;
+    }
+    Ok(())
+}
+
+/// Write a hex + ASCII dump of a span of bytes
+fn hex_dump<W: Write>(
+    out: &mut W,
+    data: &[u8],
+    span: Range<usize>,
+) -> io::Result<()> {
+    let start = span.start;
+    for (i, line) in data[span].chunks(16).enumerate() {
+        write!(out, "{:08x} ", start + i * 16)?;
+        for col in 0..16 {
+            if col == 8 {
+                write!(out, " ")?;
+            }
+            match line.get(col) {
+                Some(b) => write!(out, " {b:02x}")?,
+                None => write!(out, "   ")?,
+            }
+        }
+        let ascii: String = line
+            .iter()
+            .map(|b| match b {
+                0x20..=0x7E => char::from(*b),
+                _ => '.',
+            })
+            .collect();
+        writeln!(out, "  |{ascii}|")?;
+    }
+    Ok(())
+}
+
+/// Animation manifest for wrap subcommand
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    /// Output file, relative to the manifest
+    output: PathBuf,
+    /// Screen width (default: width of first frame)
+    width: Option<u16>,
+    /// Screen height (default: height of first frame)
+    height: Option<u16>,
+    /// Loop count (0 means forever)
+    loop_count: Option<u16>,
+    /// Default delay time (centiseconds)
+    delay: Option<u16>,
+    /// Frames in order
+    frames: Vec<ManifestFrame>,
+}
+
+/// One frame of a manifest
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFrame {
+    /// Image file (single-frame GIF), relative to the manifest
+    file: PathBuf,
+    /// Delay time (centiseconds)
+    delay: Option<u16>,
+    /// Disposal method: none, keep, bg or prev
+    disposal: Option<String>,
+    /// Left position on screen
+    #[serde(default)]
+    left: u16,
+    /// Top position on screen
+    #[serde(default)]
+    top: u16,
+    /// Color to make transparent (#rrggbb)
+    transparent: Option<String>,
+}
+
+/// Handle wrap subcommand
+fn wrap(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(matches.value_of_os("manifest").unwrap());
+    let manifest: Manifest = toml::from_str(&fs::read_to_string(path)?)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let output = match matches.value_of_os("output") {
+        Some(output) => PathBuf::from(output),
+        None => base.join(&manifest.output),
+    };
+    let mut screen = manifest.width.zip(manifest.height);
+    let mut steps = vec![];
+    for (i, frame) in manifest.frames.iter().enumerate() {
+        let step = manifest_step(&manifest, frame, base, &mut screen)
+            .map_err(|e| format!("frame {i}: {e}"))?;
+        steps.push(step);
+    }
+    if steps.is_empty() {
+        return Err("no frames in manifest".into());
+    }
+    let writer = BufWriter::new(File::create(output)?);
+    let mut enc = Encoder::new(writer).into_step_enc();
+    if let Some(loop_count) = manifest.loop_count {
+        enc = enc.with_loop_count(loop_count);
+    }
+    for (i, step) in steps.iter().enumerate() {
+        enc.encode_step(step)
+            .map_err(|e| format!("frame {i}: {e}"))?;
+    }
+    enc.finish()?.flush()?;
+    Ok(())
+}
+
+/// Make a step from one manifest frame
+fn manifest_step(
+    manifest: &Manifest,
+    frame: &ManifestFrame,
+    base: &Path,
+    screen: &mut Option<(u16, u16)>,
+) -> Result<Step, Box<dyn Error>> {
+    let image = load_image(&base.join(&frame.file))?;
+    let (width, height) = match screen {
+        Some(size) => *size,
+        None => {
+            let w = u16::try_from(image.width())?;
+            let h = u16::try_from(image.height())?;
+            *screen.insert((w, h))
+        }
+    };
+    let region = Region::new(
+        frame.left.into(),
+        frame.top.into(),
+        image.width(),
+        image.height(),
+    );
+    let (width, height) = (u32::from(width), u32::from(height));
+    if region.x as u32 + region.width > width
+        || region.y as u32 + region.height > height
+    {
+        return Err(format!(
+            "{}x{} image at {},{} outside {}x{} screen",
+            region.width, region.height, region.x, region.y, width, height,
+        )
+        .into());
+    }
+    let mut raster = Raster::with_clear(width, height);
+    raster.copy_raster(region, &image, image.region());
+    if let Some(clr) = &frame.transparent {
+        let key = parse_color(clr)?;
+        for px in raster.pixels_mut() {
+            if *px == key {
+                *px = SRgba8::default();
+            }
+        }
+    }
+    let disposal = match frame.disposal.as_deref() {
+        None | Some("none") => DisposalMethod::NoAction,
+        Some("keep") => DisposalMethod::Keep,
+        Some("bg") => DisposalMethod::Background,
+        Some("prev") => DisposalMethod::Previous,
+        Some(d) => return Err(format!("invalid disposal: {d}").into()),
+    };
+    Ok(Step::with_true_color(raster)
+        .with_region(region)
+        .with_delay_time_cs(frame.delay.or(manifest.delay))
+        .with_disposal_method(disposal))
+}
+
+/// Load an image from a single-frame GIF file
+fn load_image(path: &Path) -> Result<Raster<SRgba8>, Box<dyn Error>> {
+    let f = BufReader::new(File::open(path)?);
+    let mut steps = Decoder::new(f).into_steps();
+    let step = match steps.next() {
+        Some(step) => step?,
+        None => return Err(format!("no frames in {path:?}").into()),
+    };
+    if steps.next().is_some() {
+        return Err(format!("more than one frame in {path:?}").into());
+    }
+    Ok(step.raster().into_owned())
+}
+
+/// Parse a color (#rrggbb)
+fn parse_color(clr: &str) -> Result<SRgba8, Box<dyn Error>> {
+    let hex = clr.strip_prefix('#').unwrap_or(clr);
+    if hex.len() != 6 {
+        return Err(format!("invalid color: {clr}").into());
+    }
+    let rgb = u32::from_str_radix(hex, 16)?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Ok(SRgba8::new(r, g, b, 0xFF))
+}
+
 /// Calculate digits in a number
 fn digits<T: Into<usize>>(v: T) -> usize {
     let v = v.into();
@@ -0,0 +1,392 @@
+// optimize.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! Animation optimization
+use crate::block::DisposalMethod;
+use crate::diff::dirty_region;
+use crate::private::Step;
+use pix::{el::Pixel, rgb::SRgba8, Raster, Region};
+
+/// Options for [optimize_steps]
+///
+/// [optimize_steps]: fn.optimize_steps.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OptimizeOpts {
+    /// Crop each step to the region changed from the previous step
+    pub crop_frames: bool,
+    /// Make unchanged pixels within a step region transparent.
+    ///
+    /// This can improve compression, but relies on standard GIF
+    /// compositing, where transparent pixels leave the screen unchanged.
+    pub transparent_deltas: bool,
+    /// Merge consecutive identical steps, summing their delays
+    pub merge_identical: bool,
+    /// Drop unchanged steps with a zero-area region, adding their delay to
+    /// the previous step
+    pub drop_empty: bool,
+}
+
+impl Default for OptimizeOpts {
+    fn default() -> Self {
+        OptimizeOpts {
+            crop_frames: true,
+            transparent_deltas: false,
+            merge_identical: true,
+            drop_empty: true,
+        }
+    }
+}
+
+/// Composited step, with its screen before drawing
+struct Composite {
+    /// Composited raster
+    raster: Raster<SRgba8>,
+    /// Delay time (centiseconds)
+    delay: Option<u16>,
+    /// Screen before drawing the step
+    base: Option<Raster<SRgba8>>,
+    /// Region to draw
+    region: Region,
+    /// Disposal method
+    disposal: DisposalMethod,
+}
+
+/// Optimize animation steps for encoding.
+///
+/// The steps must be fully composited (each raster is the entire screen),
+/// as produced by Decoder.[into_steps].  The optimized steps have regions
+/// and disposal methods set, so that [StepEnc] writes minimal frames, while
+/// compositing them reproduces the original sequence.  Delays of merged
+/// steps are summed, saturating at `u16::MAX` centiseconds.
+///
+/// [into_steps]: ../struct.Decoder.html#method.into_steps
+/// [StepEnc]: ../encode/struct.StepEnc.html
+pub fn optimize_steps(steps: Vec<Step>, opts: OptimizeOpts) -> Vec<Step> {
+    let mut composites = merge_steps(steps, opts);
+    plan_frames(&mut composites, opts);
+    composites
+        .into_iter()
+        .map(|c| {
+            let region = c.region;
+            let mut raster = c.raster;
+            if let (true, Some(base)) = (opts.transparent_deltas, &c.base) {
+                make_delta(&mut raster, base, region);
+            }
+            Step::with_true_color(raster)
+                .with_region(region)
+                .with_disposal_method(c.disposal)
+                .with_delay_time_cs(c.delay)
+        })
+        .collect()
+}
+
+/// Merge identical and empty steps
+fn merge_steps(steps: Vec<Step>, opts: OptimizeOpts) -> Vec<Composite> {
+    let mut composites: Vec<Composite> = Vec::with_capacity(steps.len());
+    for step in steps {
+        let delay = step.delay_time_cs();
+        let region = step.region();
+        let empty = region.width == 0 || region.height == 0;
+        let raster = step.rgba_raster();
+        if let Some(prev) = composites.last_mut() {
+            let merge = opts.merge_identical || (opts.drop_empty && empty);
+            if merge && dirty_region(&prev.raster, &raster).is_none() {
+                prev.delay = add_delay(prev.delay, delay);
+                continue;
+            }
+        }
+        composites.push(Composite {
+            region: raster.region(),
+            raster,
+            delay,
+            base: None,
+            disposal: DisposalMethod::Keep,
+        });
+    }
+    composites
+}
+
+/// Add two delay times
+fn add_delay(a: Option<u16>, b: Option<u16>) -> Option<u16> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Plan frame regions and disposal methods
+fn plan_frames(composites: &mut [Composite], opts: OptimizeOpts) {
+    let mut base = match composites.first() {
+        Some(c) => Raster::with_clear(c.raster.width(), c.raster.height()),
+        None => return,
+    };
+    for i in 0..composites.len() {
+        if i > 0 {
+            let (done, rest) = composites.split_at_mut(i);
+            let prev = &mut done[i - 1];
+            let next = &rest[0].raster;
+            // pixels becoming transparent must be cleared by disposal
+            if let Some(clear) = cleared_region(&prev.raster, next) {
+                prev.region = union(prev.region, clear);
+                prev.disposal = DisposalMethod::Background;
+            }
+            base = Raster::with_raster(&prev.raster);
+            if prev.disposal == DisposalMethod::Background {
+                base.copy_color(prev.region, SRgba8::default());
+            }
+        }
+        let c = &mut composites[i];
+        c.region = if opts.crop_frames {
+            dirty_region(&base, &c.raster)
+                .unwrap_or_else(|| Region::new(0, 0, 1, 1))
+        } else {
+            c.raster.region()
+        };
+        c.region = c.raster.intersection(c.region);
+        c.base = Some(base.clone());
+    }
+}
+
+/// Get region of opaque pixels which become transparent
+fn cleared_region(
+    prev: &Raster<SRgba8>,
+    next: &Raster<SRgba8>,
+) -> Option<Region> {
+    if prev.width() != next.width() || prev.height() != next.height() {
+        return Some(prev.region());
+    }
+    let width = next.width() as usize;
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    for (i, (p, n)) in prev.pixels().iter().zip(next.pixels()).enumerate() {
+        if is_transparent(*n) && !is_transparent(*p) {
+            let (x, y) = (i % width, i / width);
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, _)) => (x0.min(x), y0, x1.max(x), y),
+                None => (x, y, x, y),
+            });
+        }
+    }
+    let (x0, y0, x1, y1) = bounds?;
+    Some(Region::new(
+        x0 as i32,
+        y0 as i32,
+        (x1 - x0 + 1) as u32,
+        (y1 - y0 + 1) as u32,
+    ))
+}
+
+/// Check if a pixel is transparent
+fn is_transparent(p: SRgba8) -> bool {
+    u8::from(p.alpha()) == 0
+}
+
+/// Get union (bounding region) of two regions
+fn union(a: Region, b: Region) -> Region {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    let right = (a.x + a.width as i32).max(b.x + b.width as i32);
+    let bottom = (a.y + a.height as i32).max(b.y + b.height as i32);
+    Region::new(x, y, (right - x) as u32, (bottom - y) as u32)
+}
+
+/// Make pixels unchanged from the base transparent
+fn make_delta(raster: &mut Raster<SRgba8>, base: &Raster<SRgba8>, reg: Region) {
+    let width = raster.width() as usize;
+    let x0 = reg.x as usize;
+    let x1 = x0 + reg.width as usize;
+    let brows = base.pixels().chunks_exact(width).skip(reg.y as usize);
+    for (row, brow) in raster.rows_mut(reg).zip(brows) {
+        for (p, b) in row.iter_mut().zip(&brow[x0..x1]) {
+            if p == b {
+                *p = SRgba8::default();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    /// Composite steps, with standard GIF semantics
+    fn composite(steps: &[Step]) -> Vec<(Raster<SRgba8>, Option<u16>)> {
+        let mut out = vec![];
+        let Some(first) = steps.first() else {
+            return out;
+        };
+        let raster = first.raster();
+        let mut screen = Raster::with_clear(raster.width(), raster.height());
+        for step in steps {
+            let raster = step.raster();
+            let reg = step.region();
+            let mut next = Raster::with_raster(&screen);
+            for y in reg.y..reg.y + reg.height as i32 {
+                for x in reg.x..reg.x + reg.width as i32 {
+                    let p = raster.pixel(x, y);
+                    if !is_transparent(p) {
+                        *next.pixel_mut(x, y) = p;
+                    }
+                }
+            }
+            match step.disposal_method() {
+                DisposalMethod::Background => {
+                    screen = Raster::with_raster(&next);
+                    screen.copy_color(reg, SRgba8::default());
+                }
+                DisposalMethod::Previous => (),
+                _ => screen = Raster::with_raster(&next),
+            }
+            out.push((next, step.delay_time_cs()));
+        }
+        out
+    }
+
+    /// Get rasters and delays of composited steps
+    fn rasters(steps: &[Step]) -> Vec<(Raster<SRgba8>, Option<u16>)> {
+        steps
+            .iter()
+            .map(|s| (s.raster().into_owned(), s.delay_time_cs()))
+            .collect()
+    }
+
+    /// Collapse identical consecutive rasters, summing delays
+    fn timeline(
+        frames: Vec<(Raster<SRgba8>, Option<u16>)>,
+    ) -> Vec<(Vec<SRgba8>, u16)> {
+        let mut out: Vec<(Vec<SRgba8>, u16)> = vec![];
+        for (raster, delay) in frames {
+            let pixels: Vec<SRgba8> = raster
+                .pixels()
+                .iter()
+                .map(|p| {
+                    if is_transparent(*p) {
+                        SRgba8::default()
+                    } else {
+                        *p
+                    }
+                })
+                .collect();
+            let delay = delay.unwrap_or_default();
+            match out.last_mut() {
+                Some((prev, d)) if *prev == pixels => {
+                    *d = d.saturating_add(delay)
+                }
+                _ => out.push((pixels, delay)),
+            }
+        }
+        out
+    }
+
+    /// Simple pseudo-random number generator (xorshift)
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self, n: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 % n
+        }
+    }
+
+    /// Make a random animation of composited steps
+    fn random_steps(rng: &mut Rng) -> Vec<Step> {
+        let colors = [
+            SRgba8::default(),
+            SRgba8::new(255, 0, 0, 255),
+            SRgba8::new(0, 255, 0, 255),
+            SRgba8::new(0, 0, 255, 255),
+        ];
+        let width = 1 + rng.next(6);
+        let height = 1 + rng.next(5);
+        let mut raster = Raster::with_clear(width, height);
+        let mut steps = vec![];
+        for _ in 0..1 + rng.next(6) {
+            // change a random rectangle, or nothing
+            if rng.next(4) > 0 {
+                let x = rng.next(width);
+                let y = rng.next(height);
+                let w = 1 + rng.next(width - x);
+                let h = 1 + rng.next(height - y);
+                let reg = Region::new(x as i32, y as i32, w, h);
+                let clr = colors[rng.next(4) as usize];
+                raster.copy_color(reg, clr);
+                if rng.next(2) == 0 {
+                    let (x, y) = (rng.next(width), rng.next(height));
+                    *raster.pixel_mut(x as i32, y as i32) =
+                        colors[rng.next(4) as usize];
+                }
+            }
+            let delay = [None, Some(0), Some(5), Some(u16::MAX)];
+            let step = Step::with_true_color(Raster::with_raster(&raster))
+                .with_delay_time_cs(delay[rng.next(4) as usize]);
+            steps.push(step);
+        }
+        steps
+    }
+
+    #[test]
+    fn optimize_random() {
+        let mut rng = Rng(0x1234_5678);
+        for _ in 0..500 {
+            let steps = random_steps(&mut rng);
+            let expected = timeline(rasters(&steps));
+            for bits in 0..16 {
+                let opts = OptimizeOpts {
+                    crop_frames: bits & 1 != 0,
+                    transparent_deltas: bits & 2 != 0,
+                    merge_identical: bits & 4 != 0,
+                    drop_empty: bits & 8 != 0,
+                };
+                let optimized = optimize_steps(steps.clone(), opts);
+                assert_eq!(timeline(composite(&optimized)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn optimize_merge() {
+        let raster =
+            Raster::<SRgba8>::with_color(4, 4, SRgba8::new(1, 2, 3, 255));
+        let step = Step::with_true_color(raster).with_delay_time_cs(Some(10));
+        let steps = vec![step.clone(), step.clone(), step];
+        let opts = OptimizeOpts::default();
+        let optimized = optimize_steps(steps.clone(), opts);
+        assert_eq!(optimized.len(), 1);
+        assert_eq!(optimized[0].delay_time_cs(), Some(30));
+        let opts = OptimizeOpts {
+            merge_identical: false,
+            ..opts
+        };
+        let optimized = optimize_steps(steps, opts);
+        assert_eq!(optimized.len(), 3);
+        assert_eq!(optimized[1].region(), Region::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn optimize_logo() {
+        let logo = include_bytes!("../res/gift_logo.gif");
+        let steps: Vec<Step> = Decoder::new(&logo[..])
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        let expected = rasters(&steps);
+        let optimized = optimize_steps(steps, OptimizeOpts::default());
+        assert!(optimized.iter().any(|s| s.region().width < 64));
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        for step in &optimized {
+            enc.encode_step(step).unwrap();
+        }
+        let gif = enc.finish().unwrap();
+        let decoded: Vec<_> = Decoder::new(&gif[..])
+            .into_steps()
+            .map(|s| {
+                let s = s.unwrap();
+                (s.raster().into_owned(), s.delay_time_cs())
+            })
+            .collect();
+        assert_eq!(timeline(decoded), timeline(expected));
+    }
+}
@@ -3,19 +3,49 @@
 // Copyright (c) 2019-2023  Douglas Lau
 //
 //! Private module for top-level items
+use crate::quantize::Quantizer;
 use crate::{
-    block::{DisposalMethod, GraphicControl},
-    decode, encode, Result,
+    block::{delay_cs, Block, DisposalMethod, GraphicControl},
+    decode::{self, DelayPolicy, Oversize},
+    encode, Error, Result,
 };
-use pix::{gray::Gray8, rgb::SRgba8, Palette, Raster};
-use std::io::{Read, Write};
+use pix::{
+    el::Pixel,
+    gray::Gray8,
+    rgb::{Rgb, SRgb8, SRgba8},
+    Palette, Raster, Region,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Raster for an animation step.
+///
+/// Rasters are shared between clones, and copied on write.
+#[derive(Clone)]
 pub(crate) enum StepRaster {
-    /// True color 24-bit raster
-    TrueColor(Raster<SRgba8>),
+    /// True color 32-bit raster (with alpha)
+    TrueColor(Arc<Raster<SRgba8>>),
+    /// True color 24-bit raster (opaque)
+    Opaque(Arc<Raster<SRgb8>>),
     /// Indexed color 8-bit raster
-    Indexed(Raster<Gray8>, Palette),
+    Indexed(Arc<Raster<Gray8>>, Palette),
+}
+
+/// Channel order of RGBA pixel bytes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ByteOrder {
+    /// Red, green, blue, alpha
+    #[default]
+    Rgba,
+    /// Blue, green, red, alpha
+    Bgra,
+    /// Alpha, red, green, blue
+    Argb,
 }
 
 /// One step of an animation.
@@ -25,6 +55,10 @@ pub struct Step {
     pub(crate) raster: StepRaster,
     /// Graphic control for the step
     pub(crate) graphic_control_ext: Option<GraphicControl>,
+    /// Region of the screen changed by the step
+    pub(crate) region: Option<Region>,
+    /// Policy for reporting delay time
+    pub(crate) delay_policy: DelayPolicy,
 }
 
 /// GIF file decoder
@@ -70,38 +104,365 @@ pub struct Decoder<R: Read> {
     reader: R,
     /// Maximum image size, in bytes
     max_image_sz: Option<usize>,
+    /// Maximum screen size, in bytes
+    max_screen_sz: Option<usize>,
+    /// Maximum extension size, in bytes
+    max_extension_sz: Option<usize>,
+    /// Keep image data compressed
+    raw_image_data: bool,
+    /// Report recoverable errors instead of aborting
+    lenient: bool,
+    /// Check for data after the trailer
+    deny_trailing_data: bool,
+    /// Policy for frames extending beyond the screen
+    oversize: Oversize,
+}
+
+/// Copy a region of a raster
+fn crop_raster<P: Pixel>(raster: &Raster<P>, region: Region) -> Raster<P> {
+    let mut cropped = Raster::with_clear(region.width, region.height);
+    cropped.copy_raster(cropped.region(), raster, region);
+    cropped
+}
+
+/// Scale a raster, using nearest-neighbor sampling
+fn scale_raster<P: Pixel>(
+    raster: &Raster<P>,
+    width: u32,
+    height: u32,
+) -> Raster<P> {
+    let (sw, sh) = (u64::from(raster.width()), u64::from(raster.height()));
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..u64::from(height) {
+        let sy = (y * sh / u64::from(height)) as i32;
+        for x in 0..u64::from(width) {
+            let sx = (x * sw / u64::from(width)) as i32;
+            pixels.push(raster.pixel(sx, sy));
+        }
+    }
+    Raster::with_pixels(width, height, pixels)
+}
+
+impl fmt::Debug for StepRaster {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.size();
+        let size = format_args!("{width}x{height}");
+        match self {
+            StepRaster::TrueColor(_) => {
+                f.debug_tuple("TrueColor").field(&size).finish()
+            }
+            StepRaster::Opaque(_) => {
+                f.debug_tuple("Opaque").field(&size).finish()
+            }
+            StepRaster::Indexed(_, palette) => f
+                .debug_tuple("Indexed")
+                .field(&size)
+                .field(&format_args!("palette: {}", palette.len()))
+                .finish(),
+        }
+    }
 }
 
-impl Clone for StepRaster {
-    fn clone(&self) -> Self {
+impl StepRaster {
+    /// Get the raster size (width and height)
+    pub(crate) fn size(&self) -> (u32, u32) {
+        match self {
+            StepRaster::TrueColor(r) => (r.width(), r.height()),
+            StepRaster::Opaque(r) => (r.width(), r.height()),
+            StepRaster::Indexed(r, _) => (r.width(), r.height()),
+        }
+    }
+
+    /// Copy a region into a new raster of the same kind.
+    ///
+    /// The region must be within the raster.
+    pub(crate) fn crop(&self, region: Region) -> Self {
+        match self {
+            StepRaster::TrueColor(r) => {
+                StepRaster::TrueColor(Arc::new(crop_raster(r, region)))
+            }
+            StepRaster::Opaque(r) => {
+                StepRaster::Opaque(Arc::new(crop_raster(r, region)))
+            }
+            StepRaster::Indexed(r, palette) => StepRaster::Indexed(
+                Arc::new(crop_raster(r, region)),
+                palette.clone(),
+            ),
+        }
+    }
+
+    /// Scale to a new size, using nearest-neighbor sampling
+    pub(crate) fn scale(&self, width: u32, height: u32) -> Self {
         match self {
             StepRaster::TrueColor(r) => {
-                StepRaster::TrueColor(Raster::with_raster(r))
+                StepRaster::TrueColor(Arc::new(scale_raster(r, width, height)))
+            }
+            StepRaster::Opaque(r) => {
+                StepRaster::Opaque(Arc::new(scale_raster(r, width, height)))
+            }
+            StepRaster::Indexed(r, palette) => StepRaster::Indexed(
+                Arc::new(scale_raster(r, width, height)),
+                palette.clone(),
+            ),
+        }
+    }
+
+    /// Convert into an RGBA raster
+    fn into_rgba(self, transparent: Option<u8>) -> Raster<SRgba8> {
+        match self {
+            StepRaster::TrueColor(raster) => unshare(raster),
+            StepRaster::Opaque(raster) => Raster::with_raster(&*raster),
+            StepRaster::Indexed(raster, palette) => {
+                let pixels: Vec<SRgba8> = raster
+                    .as_u8_slice()
+                    .iter()
+                    .map(|idx| match palette.entry(usize::from(*idx)) {
+                        Some(clr) if Some(*idx) != transparent => SRgba8::new(
+                            u8::from(Rgb::red(clr)),
+                            u8::from(Rgb::green(clr)),
+                            u8::from(Rgb::blue(clr)),
+                            0xFF,
+                        ),
+                        _ => SRgba8::default(),
+                    })
+                    .collect();
+                Raster::with_pixels(raster.width(), raster.height(), pixels)
+            }
+        }
+    }
+
+    /// Check if a raster is shared with another
+    #[cfg(test)]
+    fn is_shared(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StepRaster::TrueColor(a), StepRaster::TrueColor(b)) => {
+                Arc::ptr_eq(a, b)
             }
-            StepRaster::Indexed(r, p) => {
-                StepRaster::Indexed(Raster::with_raster(r), p.clone())
+            (StepRaster::Opaque(a), StepRaster::Opaque(b)) => Arc::ptr_eq(a, b),
+            (StepRaster::Indexed(a, _), StepRaster::Indexed(b, _)) => {
+                Arc::ptr_eq(a, b)
             }
+            _ => false,
         }
     }
+
+    /// Get a short description of the raster kind
+    fn kind(&self) -> &'static str {
+        match self {
+            StepRaster::TrueColor(_) => "true color",
+            StepRaster::Opaque(_) => "opaque",
+            StepRaster::Indexed(_, _) => "indexed",
+        }
+    }
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let control = self.graphic_control_ext;
+        f.debug_struct("Step")
+            .field("raster", &self.raster)
+            .field("delay_time_cs", &self.delay_time_cs())
+            .field("disposal_method", &control.map(|c| c.disposal_method()))
+            .field("transparent_color", &self.transparent_color())
+            .field("region", &self.region)
+            .finish()
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.raster.size();
+        write!(f, "Step {width}x{height} {}", self.raster.kind())?;
+        if let Some(control) = &self.graphic_control_ext {
+            write!(
+                f,
+                ", delay {}cs, disposal {:?}",
+                control.delay_time_cs(),
+                control.disposal_method()
+            )?;
+        }
+        if let Some(reg) = &self.region {
+            write!(
+                f,
+                ", region {}x{} at {},{}",
+                reg.width, reg.height, reg.x, reg.y
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl Step {
     /// Create an animation step with a true color raster.
     pub fn with_true_color(raster: Raster<SRgba8>) -> Self {
-        let raster = StepRaster::TrueColor(raster);
+        let raster = StepRaster::TrueColor(Arc::new(raster));
+        Step {
+            raster,
+            graphic_control_ext: None,
+            region: None,
+            delay_policy: DelayPolicy::default(),
+        }
+    }
+
+    /// Create an animation step with an opaque true color raster.
+    ///
+    /// This avoids converting an RGB raster to RGBA for encoding.  No
+    /// transparent color is used.
+    pub fn with_srgb(raster: Raster<SRgb8>) -> Self {
+        let raster = StepRaster::Opaque(Arc::new(raster));
         Step {
             raster,
             graphic_control_ext: None,
+            region: None,
+            delay_policy: DelayPolicy::default(),
         }
     }
 
     /// Create an animation step with an indexed raster.
     pub fn with_indexed(raster: Raster<Gray8>, palette: Palette) -> Self {
-        let raster = StepRaster::Indexed(raster, palette);
+        let raster = StepRaster::Indexed(Arc::new(raster), palette);
         Step {
             raster,
             graphic_control_ext: None,
+            region: None,
+            delay_policy: DelayPolicy::default(),
+        }
+    }
+
+    /// Set the region of the screen changed by the step.
+    ///
+    /// The raster still covers the full screen, but only pixels within the
+    /// region are encoded.  When decoding, this is the region of the source
+    /// frame.
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Get the region of the screen changed by the step.
+    ///
+    /// If no region was set, this covers the full raster.
+    pub fn region(&self) -> Region {
+        match self.region {
+            Some(region) => region,
+            None => match &self.raster {
+                StepRaster::TrueColor(r) => r.region(),
+                StepRaster::Opaque(r) => r.region(),
+                StepRaster::Indexed(r, _) => r.region(),
+            },
+        }
+    }
+
+    /// Convert into a step with an indexed raster.
+    ///
+    /// True color rasters are quantized to `max_colors` or fewer, which
+    /// must be between 2 and 256.  If any pixels are transparent, one of
+    /// those colors is reserved as the transparent color.  Indexed steps
+    /// are returned unchanged.
+    pub fn into_indexed(self, max_colors: u16) -> Result<Self> {
+        if !(2..=256).contains(&max_colors) {
+            return Err(Error::InvalidColorCount);
+        }
+        let quantizer =
+            Quantizer::default().with_max_colors(usize::from(max_colors));
+        let indexed = match &self.raster {
+            StepRaster::TrueColor(raster) => quantizer.quantize_rgba(raster),
+            StepRaster::Opaque(raster) => quantizer.quantize_rgb(raster),
+            StepRaster::Indexed(_, _) => return Ok(self),
+        };
+        let raster =
+            StepRaster::Indexed(Arc::new(indexed.raster), indexed.palette);
+        let step = Step { raster, ..self };
+        Ok(step.with_transparent_color(indexed.transparent))
+    }
+
+    /// Convert into a step with a true color raster.
+    ///
+    /// Indexed pixels using the transparent color become fully transparent.
+    pub fn into_true_color(self) -> Self {
+        if let StepRaster::TrueColor(_) = self.raster {
+            return self;
         }
+        let transparent = self.transparent_color();
+        let raster = self.raster.into_rgba(transparent);
+        let raster = StepRaster::TrueColor(Arc::new(raster));
+        Step { raster, ..self }
+    }
+
+    /// Center the step on a larger screen, with a transparent border.
+    ///
+    /// Indexed steps stay indexed if a transparent color is set, or an index
+    /// is unused; otherwise they are converted to true color.
+    pub(crate) fn letterbox(self, width: u32, height: u32) -> Self {
+        let (w, h) = self.raster.size();
+        let x = ((width - w) / 2) as i32;
+        let y = ((height - h) / 2) as i32;
+        let dst = Region::new(x, y, w, h);
+        let region = self.region().intersection(Region::new(0, 0, w, h));
+        let region = Region::new(
+            region.x + x,
+            region.y + y,
+            region.width,
+            region.height,
+        );
+        let step = match &self.raster {
+            StepRaster::Indexed(raster, palette) => {
+                let mut used = [false; 256];
+                for idx in raster.as_u8_slice() {
+                    used[usize::from(*idx)] = true;
+                }
+                let unused = (0..palette.len()).find(|i| !used[*i]);
+                let trans =
+                    self.transparent_color().or(unused.map(|i| i as u8));
+                match trans {
+                    Some(trans) => {
+                        let mut boxed = Raster::with_color(
+                            width,
+                            height,
+                            Gray8::new(trans),
+                        );
+                        boxed.copy_raster(dst, raster, raster.region());
+                        let raster = StepRaster::Indexed(
+                            Arc::new(boxed),
+                            palette.clone(),
+                        );
+                        Step { raster, ..self }
+                            .with_transparent_color(Some(trans))
+                    }
+                    None => {
+                        return self.into_true_color().letterbox(width, height)
+                    }
+                }
+            }
+            _ => {
+                let transparent = self.transparent_color();
+                let src = self.raster.clone().into_rgba(transparent);
+                let mut boxed = Raster::with_clear(width, height);
+                boxed.copy_raster(dst, &src, src.region());
+                let raster = StepRaster::TrueColor(Arc::new(boxed));
+                Step { raster, ..self }
+            }
+        };
+        step.with_region(region)
+    }
+
+    /// Take the raster, converted to RGBA
+    pub(crate) fn rgba_raster(self) -> Raster<SRgba8> {
+        let transparent = self.transparent_color();
+        self.raster.into_rgba(transparent)
+    }
+
+    /// Set the graphic control block.
+    ///
+    /// This replaces the disposal method, transparent color and delay time.
+    pub fn with_graphic_control(mut self, control: GraphicControl) -> Self {
+        self.graphic_control_ext = Some(control);
+        self
+    }
+
+    /// Get the graphic control block
+    pub fn graphic_control(&self) -> Option<&GraphicControl> {
+        self.graphic_control_ext.as_ref()
     }
 
     /// Adjust the disposal method.
@@ -116,6 +477,15 @@ impl Step {
         self
     }
 
+    /// Get the disposal method.
+    ///
+    /// Without a graphic control, this is the default (`NoAction`).
+    pub fn disposal_method(&self) -> DisposalMethod {
+        self.graphic_control_ext
+            .map(|c| c.disposal_method())
+            .unwrap_or_default()
+    }
+
     /// Adjust the transparent color.
     pub fn with_transparent_color(mut self, clr: Option<u8>) -> Self {
         let mut control = self.graphic_control_ext.unwrap_or_default();
@@ -145,18 +515,115 @@ impl Step {
         self
     }
 
-    /// Get the raster
-    pub fn raster(&self) -> &Raster<SRgba8> {
+    /// Adjust the delay time, rounded to the nearest centisecond.
+    ///
+    /// See [GraphicControl::set_delay] for details.
+    ///
+    /// [GraphicControl::set_delay]: block/struct.GraphicControl.html#method.set_delay
+    pub fn with_delay(self, delay: Option<Duration>) -> Self {
+        self.with_delay_time_cs(delay.map(delay_cs))
+    }
+
+    /// Get the raster.
+    ///
+    /// A true color raster (as decoded) is borrowed; opaque and indexed
+    /// rasters are converted to true color.
+    pub fn raster(&self) -> Cow<'_, Raster<SRgba8>> {
         match &self.raster {
-            StepRaster::TrueColor(ref r) => r,
-            StepRaster::Indexed(_, _) => todo!("convert to true color"),
+            StepRaster::TrueColor(raster) => Cow::Borrowed(raster),
+            StepRaster::Opaque(_) | StepRaster::Indexed(_, _) => {
+                let transparent = self.transparent_color();
+                Cow::Owned(self.raster.clone().into_rgba(transparent))
+            }
+        }
+    }
+
+    /// Get the raster as RGBA bytes, without copying.
+    ///
+    /// Pixels are tightly packed in rows (stride is 4 × width), with straight
+    /// (not premultiplied) alpha.  Returns `None` unless the step has a true
+    /// color raster, as steps from a [Decoder] do.
+    ///
+    /// [Decoder]: struct.Decoder.html
+    pub fn as_rgba8(&self) -> Option<&[u8]> {
+        match &self.raster {
+            StepRaster::TrueColor(raster) => Some(raster.as_u8_slice()),
+            StepRaster::Opaque(_) | StepRaster::Indexed(_, _) => None,
+        }
+    }
+
+    /// Copy the raster to a `Vec` of bytes in a channel order.
+    ///
+    /// Pixels are tightly packed in rows (stride is 4 × width), with straight
+    /// (not premultiplied) alpha.  Indexed pixels using the transparent color
+    /// have zero alpha.
+    pub fn to_rgba8_vec(&self, order: ByteOrder) -> Vec<u8> {
+        let mut buf = match self.as_rgba8() {
+            Some(buf) => buf.to_vec(),
+            None => {
+                let transparent = self.transparent_color();
+                let raster = self.raster.clone().into_rgba(transparent);
+                raster.as_u8_slice().to_vec()
+            }
+        };
+        for px in buf.chunks_exact_mut(4) {
+            match order {
+                ByteOrder::Rgba => (),
+                ByteOrder::Bgra => px.swap(0, 2),
+                ByteOrder::Argb => px.rotate_right(1),
+            }
+        }
+        buf
+    }
+
+    /// Get a mutable raster.
+    ///
+    /// Opaque and indexed rasters are converted to true color first.  If the
+    /// raster is shared with a cloned step, it is copied before mutation.
+    pub fn raster_mut(&mut self) -> &mut Raster<SRgba8> {
+        if !matches!(self.raster, StepRaster::TrueColor(_)) {
+            let transparent = self.transparent_color();
+            let raster = self.raster.clone().into_rgba(transparent);
+            self.raster = StepRaster::TrueColor(Arc::new(raster));
+        }
+        match &mut self.raster {
+            StepRaster::TrueColor(raster) => make_mut(raster),
+            _ => unreachable!(),
         }
     }
 
-    /// Get the delay time in centiseconds
+    /// Get the delay time in centiseconds.
+    ///
+    /// For decoded steps, this is adjusted by the [DelayPolicy] of the
+    /// iterator.  The raw value is available from [graphic_control].
+    ///
+    /// [DelayPolicy]: decode/enum.DelayPolicy.html
+    /// [graphic_control]: struct.Step.html#method.graphic_control
     pub fn delay_time_cs(&self) -> Option<u16> {
-        self.graphic_control_ext.map(|c| c.delay_time_cs())
+        self.graphic_control_ext
+            .map(|c| self.delay_policy.adjust(c.delay_time_cs()))
     }
+
+    /// Get the delay time, adjusted like [delay_time_cs].
+    ///
+    /// [delay_time_cs]: struct.Step.html#method.delay_time_cs
+    pub fn delay(&self) -> Option<Duration> {
+        self.delay_time_cs()
+            .map(|cs| Duration::from_millis(u64::from(cs) * 10))
+    }
+}
+
+/// Take a shared raster, copying it if there are other references
+fn unshare<P: Pixel>(raster: Arc<Raster<P>>) -> Raster<P> {
+    Arc::try_unwrap(raster).unwrap_or_else(|r| Raster::with_raster(&*r))
+}
+
+/// Get a mutable shared raster, copying it if there are other references
+fn make_mut<P: Pixel>(raster: &mut Arc<Raster<P>>) -> &mut Raster<P> {
+    if Arc::get_mut(raster).is_none() {
+        *raster = Arc::new(Raster::with_raster(&**raster));
+    }
+    Arc::get_mut(raster).expect("unique raster")
 }
 
 impl<R: Read> Decoder<R> {
@@ -165,18 +632,183 @@ impl<R: Read> Decoder<R> {
         Decoder {
             reader,
             max_image_sz: Some(1 << 25),
+            max_screen_sz: Some(1 << 27),
+            max_extension_sz: Some(1 << 20),
+            raw_image_data: false,
+            lenient: false,
+            deny_trailing_data: false,
+            oversize: Oversize::default(),
         }
     }
 
     /// Set the maximum image size (in bytes) to allow for decoding.
+    ///
+    /// This applies to each frame.
     pub fn max_image_sz(mut self, max_image_sz: Option<usize>) -> Self {
         self.max_image_sz = max_image_sz;
         self
     }
 
+    /// Set the maximum screen size (in bytes) to allow for decoding.
+    ///
+    /// This is checked against the 4-byte RGBA raster needed to composite
+    /// steps, when the logical screen descriptor is decoded.  Larger
+    /// screens return [TooLargeImage].  The default allows the same number
+    /// of pixels as [max_image_sz].
+    ///
+    /// [max_image_sz]: struct.Decoder.html#method.max_image_sz
+    /// [TooLargeImage]: enum.Error.html#variant.TooLargeImage
+    pub fn max_screen_sz(mut self, max_screen_sz: Option<usize>) -> Self {
+        self.max_screen_sz = max_screen_sz;
+        self
+    }
+
+    /// Set the maximum extension size (in bytes) to allow for decoding.
+    ///
+    /// This limits the total sub-block data of each comment, application,
+    /// plain text or unknown extension.  Larger extensions return
+    /// [TooLargeExtension] (or are truncated with a warning when decoding
+    /// [lenient]ly).  The default is 1 MiB.
+    ///
+    /// [lenient]: struct.Decoder.html#method.lenient
+    /// [TooLargeExtension]: enum.Error.html#variant.TooLargeExtension
+    pub fn max_extension_sz(mut self, max_extension_sz: Option<usize>) -> Self {
+        self.max_extension_sz = max_extension_sz;
+        self
+    }
+
+    /// Keep image data compressed when decoding blocks or frames.
+    ///
+    /// Raw [ImageData] can be encoded without LZW re-compression, for
+    /// copying frames from one file to another.  Steps are always
+    /// decompressed.
+    ///
+    /// [ImageData]: block/struct.ImageData.html
+    pub fn raw_image_data(mut self, raw: bool) -> Self {
+        self.raw_image_data = raw;
+        self
+    }
+
+    /// Decode leniently, logging a warning for recoverable errors.
+    ///
+    /// When enabled, instead of returning an error:
+    /// * An unsupported GIF version in the [Header] is decoded as 89a
+    ///   ([UnsupportedVersion])
+    /// * A color table truncated by the end of file is padded with black
+    ///   ([TruncatedColorTable])
+    /// * An LZW minimum code size outside of 2 to 8 is clamped
+    ///   ([InvalidLzwCodeSize])
+    /// * Image data larger than its frame is truncated ([InvalidLzwData])
+    /// * A frame with no local or global color table is composited into a
+    ///   step using a grayscale palette, where index `i` is `(i, i, i)`
+    ///   ([MissingColorTable])
+    /// * An extension block missing its terminator ends where the next
+    ///   block starts, if a block introducer is found in place of a
+    ///   sub-block size, followed by a complete block
+    ///
+    /// [Header]: block/struct.Header.html
+    /// [InvalidLzwCodeSize]: enum.Error.html#variant.InvalidLzwCodeSize
+    /// [InvalidLzwData]: enum.Error.html#variant.InvalidLzwData
+    /// [MissingColorTable]: enum.Error.html#variant.MissingColorTable
+    /// [TruncatedColorTable]: enum.Error.html#variant.TruncatedColorTable
+    /// [UnsupportedVersion]: enum.Error.html#variant.UnsupportedVersion
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Check for data after the [Trailer] block.
+    ///
+    /// When enabled, any data following the trailer returns
+    /// [TrailingData] instead of the trailer (or logs a warning when
+    /// decoding [lenient]ly).
+    ///
+    /// [lenient]: struct.Decoder.html#method.lenient
+    /// [Trailer]: block/struct.Trailer.html
+    /// [TrailingData]: enum.Error.html#variant.TrailingData
+    pub fn deny_trailing_data(mut self, deny: bool) -> Self {
+        self.deny_trailing_data = deny;
+        self
+    }
+
+    /// Set the policy for frames which extend beyond the logical screen.
+    ///
+    /// This only affects compositing steps.  The default is
+    /// [Oversize::Error].  When expanding, the screen is limited by
+    /// [max_screen_sz].
+    ///
+    /// [max_screen_sz]: struct.Decoder.html#method.max_screen_sz
+    /// [Oversize::Error]: decode/enum.Oversize.html#variant.Error
+    pub fn oversize_frames(mut self, oversize: Oversize) -> Self {
+        self.oversize = oversize;
+        self
+    }
+
     /// Convert into a block `Iterator`.
     pub fn into_blocks(self) -> decode::Blocks<R> {
-        decode::Blocks::new(self.reader, self.max_image_sz)
+        decode::Blocks::new(
+            self.reader,
+            self.max_image_sz,
+            self.max_screen_sz,
+            self.max_extension_sz,
+            self.raw_image_data,
+            self.lenient,
+            self.deny_trailing_data,
+        )
+    }
+
+    /// Convert into a comment `Iterator`.
+    ///
+    /// Only comments before the first frame are included, unless
+    /// [include_interleaved] is set.  Image data is never decompressed.
+    ///
+    /// [include_interleaved]: decode/struct.Comments.html#method.include_interleaved
+    pub fn comments(self) -> decode::Comments<R> {
+        decode::Comments::new(self.into_blocks())
+    }
+
+    /// Get the animation loop count.
+    ///
+    /// Blocks are read until a loop count [Application] extension is found,
+    /// stopping at the first frame.  Returns `Some(0)` for an animation which
+    /// loops forever, or `None` without a loop count extension.
+    ///
+    /// [Application]: block/struct.Application.html
+    pub fn loop_count(self) -> Result<Option<u16>> {
+        self.find_loop_count(false)
+    }
+
+    /// Get the animation loop count, scanning the whole file.
+    ///
+    /// This is like [loop_count], but also finds an extension placed after
+    /// the first frame.  Image data is skipped without decompressing.
+    ///
+    /// [loop_count]: struct.Decoder.html#method.loop_count
+    pub fn scan_loop_count(self) -> Result<Option<u16>> {
+        self.find_loop_count(true)
+    }
+
+    /// Read a summary of the GIF file.
+    ///
+    /// All blocks are read in one pass, without decompressing image data.
+    pub fn metadata(self) -> Result<decode::Metadata> {
+        decode::Metadata::from_blocks(self.into_blocks())
+    }
+
+    /// Find loop count extension
+    fn find_loop_count(self, scan_all: bool) -> Result<Option<u16>> {
+        for block in self.into_blocks().with_skip_image_data() {
+            match block? {
+                Block::Application(b) => {
+                    if let Some(loop_count) = b.loop_count() {
+                        return Ok(Some(loop_count));
+                    }
+                }
+                Block::ImageDesc(_) if !scan_all => break,
+                _ => (),
+            }
+        }
+        Ok(None)
     }
 
     /// Convert into a frame `Iterator`.
@@ -186,7 +818,20 @@ impl<R: Read> Decoder<R> {
 
     /// Convert into a step `Iterator` without looping.
     pub fn into_steps(self) -> decode::Steps<R> {
-        decode::Steps::new_once(self.into_frames())
+        let (oversize, max_screen_sz) = (self.oversize, self.max_screen_sz);
+        let dec = self.raw_image_data(false);
+        decode::Steps::new_once(dec.into_frames())
+            .with_oversize(oversize, max_screen_sz)
+    }
+}
+
+impl<'a> Decoder<&'a [u8]> {
+    /// Convert into a block reference `Iterator`.
+    ///
+    /// Color tables and extension sub-blocks borrow from the GIF data,
+    /// without copying.
+    pub fn into_block_refs(self) -> decode::BlockRefs<'a> {
+        decode::BlockRefs::new(self.into_blocks())
     }
 }
 
@@ -196,7 +841,10 @@ impl<R: Read> IntoIterator for Decoder<R> {
 
     /// Convert into a step `Iterator` with looping
     fn into_iter(self) -> Self::IntoIter {
-        decode::Steps::new_looping(self.into_frames())
+        let (oversize, max_screen_sz) = (self.oversize, self.max_screen_sz);
+        let dec = self.raw_image_data(false);
+        decode::Steps::new_looping(dec.into_frames())
+            .with_oversize(oversize, max_screen_sz)
     }
 }
 
@@ -262,3 +910,295 @@ impl<W: Write> Encoder<W> {
         encode::StepEnc::new(self.into_frame_enc())
     }
 }
+
+/// Open a GIF file for decoding.
+///
+/// The file is buffered, and errors opening it are wrapped in [Error::Io].
+///
+/// ## Example
+/// ```no_run
+/// # fn main() -> Result<(), gift::Error> {
+/// for step in gift::open("example.gif")? {
+///     let raster = step?.raster();
+///     // ... work with raster
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Error::Io]: enum.Error.html#variant.Io
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Decoder<BufReader<File>>> {
+    Ok(Decoder::new(BufReader::new(File::open(path)?)))
+}
+
+/// Create a decoder for a GIF in memory.
+///
+/// This is the same as `Decoder::new(bytes)`, and allows zero-copy
+/// decoding with [into_block_refs].
+///
+/// [into_block_refs]: struct.Decoder.html#method.into_block_refs
+pub fn from_bytes(bytes: &[u8]) -> Decoder<&[u8]> {
+    Decoder::new(bytes)
+}
+
+/// Encode a true color `Raster` as a still GIF image.
+///
+/// A complete file (preamble, one frame and trailer) is written, with no
+/// animation extensions.  See [StepEnc::encode_raster] for details.
+///
+/// ## Example
+/// ```
+/// use pix::{rgb::SRgba8, Raster};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let raster = Raster::with_color(4, 4, SRgba8::new(0xFF, 0, 0, 0xFF));
+/// let mut gif = vec![];
+/// gift::encode_raster(&mut gif, &raster)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [StepEnc::encode_raster]: encode/struct.StepEnc.html#method.encode_raster
+pub fn encode_raster<W: Write>(
+    writer: W,
+    raster: &Raster<SRgba8>,
+) -> Result<()> {
+    let mut enc = Encoder::new(writer).into_step_enc();
+    enc.encode_raster(raster)?;
+    enc.finish()?;
+    Ok(())
+}
+
+/// Save an animation to a GIF file.
+///
+/// The steps are encoded as with [StepEnc::encode_steps], sharing a global
+/// palette where possible.  Indexed and true color steps can be mixed.  A
+/// loop count (zero means forever) is only encoded when there is more than
+/// one step.
+///
+/// ## Example
+/// ```no_run
+/// use gift::Step;
+/// use pix::{rgb::SRgba8, Raster};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let red = Raster::with_color(4, 4, SRgba8::new(0xFF, 0, 0, 0xFF));
+/// let blue = Raster::with_color(4, 4, SRgba8::new(0, 0, 0xFF, 0xFF));
+/// let steps = [red, blue].map(|r| {
+///     Step::with_true_color(r).with_delay_time_cs(Some(50))
+/// });
+/// gift::save("blink.gif", steps, Some(0))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [StepEnc::encode_steps]: encode/struct.StepEnc.html#method.encode_steps
+pub fn save<P, I>(path: P, steps: I, loop_count: Option<u16>) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = Step>,
+{
+    let writer = BufWriter::new(File::create(path)?);
+    let mut enc = Encoder::new(writer).into_step_enc();
+    if let Some(loop_count) = loop_count {
+        enc = enc.with_loop_count(loop_count);
+    }
+    enc.encode_steps(steps.into_iter().map(Ok))?;
+    enc.finish()?.into_inner().map_err(|e| e.into_error())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::DisposalMethod;
+
+    fn indexed_step() -> Step {
+        let raster = Raster::with_clear(64, 32);
+        let mut palette = Palette::new(256);
+        palette.set_entry(SRgb8::new(0, 0, 0));
+        palette.set_entry(SRgb8::new(0xFF, 0xFF, 0xFF));
+        Step::with_indexed(raster, palette)
+            .with_delay_time_cs(Some(10))
+            .with_disposal_method(DisposalMethod::Keep)
+    }
+
+    #[test]
+    fn step_debug() {
+        let step = indexed_step();
+        let dbg = format!("{step:?}");
+        assert!(dbg.contains("Indexed(64x32, palette: 2)"), "{dbg}");
+        assert!(dbg.contains("delay_time_cs: Some(10)"), "{dbg}");
+        assert!(dbg.contains("disposal_method: Some(Keep)"), "{dbg}");
+        let step = Step::with_true_color(Raster::with_clear(8, 8));
+        let dbg = format!("{step:?}");
+        assert!(dbg.contains("TrueColor(8x8)"), "{dbg}");
+        assert!(dbg.contains("delay_time_cs: None"), "{dbg}");
+    }
+
+    #[test]
+    fn step_display() {
+        let step = indexed_step();
+        assert_eq!(
+            step.to_string(),
+            "Step 64x32 indexed, delay 10cs, disposal Keep"
+        );
+        let step = Step::with_srgb(Raster::with_clear(4, 4))
+            .with_region(Region::new(1, 2, 3, 2));
+        assert_eq!(step.to_string(), "Step 4x4 opaque, region 3x2 at 1,2");
+    }
+
+    #[test]
+    fn step_clone_shared() {
+        let step = Step::with_true_color(Raster::with_clear(1024, 1024));
+        let mut cloned = step.clone();
+        assert!(cloned.raster.is_shared(&step.raster));
+        let clr = SRgba8::new(0xFF, 0x80, 0x00, 0xFF);
+        *cloned.raster_mut().pixel_mut(1, 2) = clr;
+        assert!(!cloned.raster.is_shared(&step.raster));
+        assert_eq!(cloned.raster().pixel(1, 2), clr);
+        assert_eq!(step.raster().pixel(1, 2), SRgba8::default());
+        let mut step = indexed_step();
+        let cloned = step.clone();
+        assert!(cloned.raster.is_shared(&step.raster));
+        step.raster_mut();
+        assert!(!cloned.raster.is_shared(&step.raster));
+        assert_eq!(cloned.raster.kind(), "indexed");
+        assert_eq!(step.raster.kind(), "true color");
+    }
+
+    #[test]
+    fn step_rgba8() {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(0x10, 0x20, 0x30));
+        palette.set_entry(SRgb8::new(0x40, 0x50, 0x60));
+        let raster =
+            Raster::with_pixels(2, 1, vec![Gray8::new(0), Gray8::new(1)]);
+        let mut step =
+            Step::with_indexed(raster, palette).with_transparent_color(Some(0));
+        assert_eq!(step.as_rgba8(), None);
+        let rgba = [0, 0, 0, 0, 0x40, 0x50, 0x60, 0xFF];
+        assert_eq!(step.to_rgba8_vec(ByteOrder::Rgba), rgba);
+        let bgra = [0, 0, 0, 0, 0x60, 0x50, 0x40, 0xFF];
+        assert_eq!(step.to_rgba8_vec(ByteOrder::Bgra), bgra);
+        let argb = [0, 0, 0, 0, 0xFF, 0x40, 0x50, 0x60];
+        assert_eq!(step.to_rgba8_vec(ByteOrder::Argb), argb);
+        assert_eq!(step.raster().as_u8_slice(), rgba);
+        step.raster_mut();
+        assert_eq!(step.as_rgba8(), Some(&rgba[..]));
+    }
+
+    #[test]
+    fn step_delay() {
+        let step = indexed_step();
+        assert_eq!(step.delay(), Some(Duration::from_millis(100)));
+        let step = step.with_delay(Some(Duration::from_millis(1234)));
+        assert_eq!(step.delay_time_cs(), Some(123));
+        let step = step.with_delay(Some(Duration::from_micros(1)));
+        assert_eq!(step.delay_time_cs(), Some(1));
+        let step = step.with_delay(None);
+        assert_eq!(step.delay(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn into_indexed_unchanged() {
+        let step = indexed_step().into_indexed(2).unwrap();
+        match &step.raster {
+            StepRaster::Indexed(raster, palette) => {
+                assert_eq!(raster.width(), 64);
+                assert_eq!(palette.len(), 2);
+            }
+            _ => panic!("not indexed"),
+        }
+        assert_eq!(step.delay_time_cs(), Some(10));
+    }
+
+    #[test]
+    fn into_indexed_quantized() {
+        let pixels: Vec<SRgba8> = (0..64)
+            .map(|i| SRgba8::new(i * 4, 0, 0xFF - i * 4, 0xFF))
+            .collect();
+        let step = Step::with_true_color(Raster::with_pixels(8, 8, pixels));
+        let step = step.into_indexed(16).unwrap();
+        match &step.raster {
+            StepRaster::Indexed(_, palette) => assert_eq!(palette.len(), 16),
+            _ => panic!("not indexed"),
+        }
+        assert_eq!(step.transparent_color(), None);
+        assert!(matches!(
+            indexed_step().into_indexed(1),
+            Err(Error::InvalidColorCount)
+        ));
+    }
+
+    #[test]
+    fn into_indexed_transparent() {
+        let red = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let clr = SRgba8::new(0, 0, 0, 0);
+        let raster = Raster::with_pixels(2, 2, vec![red, clr, clr, red]);
+        let step = Step::with_true_color(raster).into_indexed(256).unwrap();
+        assert_eq!(step.transparent_color(), Some(1));
+        let step = step.into_true_color();
+        assert_eq!(step.raster().pixels(), [red, clr, clr, red]);
+    }
+
+    #[test]
+    fn into_indexed_all_transparent() {
+        let raster = Raster::with_clear(4, 4);
+        let step = Step::with_true_color(raster).into_indexed(256).unwrap();
+        match &step.raster {
+            StepRaster::Indexed(raster, palette) => {
+                assert_eq!(palette.len(), 2);
+                assert_eq!(raster.as_u8_slice(), [1; 16]);
+            }
+            _ => panic!("not indexed"),
+        }
+        assert_eq!(step.transparent_color(), Some(1));
+    }
+
+    #[test]
+    fn open_logo() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/res/gift_logo.gif");
+        let bytes = std::fs::read(path).unwrap();
+        let steps = crate::open(path).unwrap().into_steps();
+        assert!(steps.count() > 0);
+        let a = crate::open(path).unwrap().into_frames().count();
+        let b = crate::from_bytes(&bytes).into_frames().count();
+        assert_eq!(a, b);
+        assert!(matches!(crate::open("missing/file.gif"), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn save_steps() {
+        let path = std::env::temp_dir().join("gift_save_steps.gif");
+        let red = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let raster = Raster::with_color(4, 4, red);
+        let steps = vec![
+            indexed_step().with_delay_time_cs(Some(20)),
+            Step::with_true_color(Raster::with_clear(64, 32))
+                .with_delay_time_cs(Some(30)),
+        ];
+        save(&path, steps.clone(), Some(3)).unwrap();
+        let decoded: Vec<Step> = Decoder::new(File::open(&path).unwrap())
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(decoded.len(), 2);
+        for (a, b) in steps.iter().zip(&decoded) {
+            assert_eq!(a.delay_time_cs(), b.delay_time_cs());
+            assert_eq!(a.raster().pixels(), b.raster().pixels());
+        }
+        let mut frames = Decoder::new(File::open(&path).unwrap()).into_frames();
+        let preamble = frames.preamble().unwrap().unwrap();
+        let loop_count = preamble.loop_count_ext.and_then(|a| a.loop_count());
+        assert_eq!(loop_count, Some(3));
+        save(&path, [Step::with_true_color(raster)], None).unwrap();
+        let steps: Vec<Step> = Decoder::new(File::open(&path).unwrap())
+            .into_steps()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].raster().pixels(), [red; 16]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+// quantize.rs
+//
+// Copyright (c) 2023  Douglas Lau
+//
+//! Color quantization for encoding true color rasters
+use pix::{
+    gray::Gray8,
+    rgb::{SRgb8, SRgba8},
+    Palette, Raster,
+};
+use std::collections::HashMap;
+
+/// Maximum number of colors in a palette
+const MAX_COLORS: usize = 256;
+
+/// Color with pixel count
+#[derive(Clone, Copy, Debug)]
+struct ColorCount {
+    /// Red, green and blue channels
+    rgb: [u8; 3],
+    /// Number of pixels with the color
+    count: u32,
+}
+
+/// Indexed raster quantized from a true color raster
+pub(crate) struct Indexed {
+    /// Indexed raster
+    pub raster: Raster<Gray8>,
+    /// Palette for the raster
+    pub palette: Palette,
+    /// Transparent color index
+    pub transparent: Option<u8>,
+}
+
+/// Histogram of colors in one or more rasters
+#[derive(Default)]
+struct Histogram {
+    /// Colors in order of first appearance
+    colors: Vec<ColorCount>,
+    /// Index of each color (by key)
+    index: HashMap<u32, usize>,
+    /// Any transparent pixels?
+    transparent: bool,
+}
+
+/// Palette chosen for a histogram
+struct Quantized {
+    /// Palette of quantized colors
+    palette: Palette,
+    /// Palette index for each histogram color
+    color_idx: Vec<u8>,
+    /// Transparent color index
+    transparent: Option<u8>,
+}
+
+/// Get a hash key for a color
+fn color_key(rgb: &[u8]) -> u32 {
+    u32::from(rgb[0]) << 16 | u32::from(rgb[1]) << 8 | u32::from(rgb[2])
+}
+
+impl Histogram {
+    /// Add colors from a pixel buffer
+    fn add(&mut self, buf: &[u8], n_chan: usize) {
+        for px in buf.chunks_exact(n_chan) {
+            if is_transparent(px) {
+                self.transparent = true;
+                continue;
+            }
+            let colors = &mut self.colors;
+            let i = *self.index.entry(color_key(px)).or_insert_with(|| {
+                colors.push(ColorCount {
+                    rgb: [px[0], px[1], px[2]],
+                    count: 0,
+                });
+                colors.len() - 1
+            });
+            self.colors[i].count += 1;
+        }
+    }
+
+    /// Choose a palette for the histogram
+    fn quantize(&self, max_colors: usize) -> Quantized {
+        let n_colors = max_colors - usize::from(self.transparent);
+        let boxes = if self.colors.len() <= n_colors {
+            self.colors.iter().map(|c| vec![*c]).collect()
+        } else {
+            median_cut(self.colors.clone(), n_colors)
+        };
+        let mut palette = Palette::new(max_colors);
+        if self.colors.is_empty() {
+            // filler color, so palette has at least two entries
+            palette.set_entry(SRgb8::default());
+        }
+        let mut color_idx = vec![0; self.colors.len()];
+        for bx in &boxes {
+            let idx = palette.set_entry(box_mean(bx)).unwrap_or(0) as u8;
+            for c in bx {
+                color_idx[self.index[&color_key(&c.rgb)]] = idx;
+            }
+        }
+        let transparent = if self.transparent {
+            let clr = unused_color(&palette);
+            palette.set_entry(clr).map(|idx| idx as u8)
+        } else {
+            None
+        };
+        Quantized {
+            palette,
+            color_idx,
+            transparent,
+        }
+    }
+
+    /// Map a pixel buffer to an indexed raster
+    fn map(
+        &self,
+        quantized: &Quantized,
+        width: u32,
+        height: u32,
+        buf: &[u8],
+        n_chan: usize,
+    ) -> Indexed {
+        let trans_idx = quantized.transparent.unwrap_or(0);
+        let pixels: Vec<Gray8> = buf
+            .chunks_exact(n_chan)
+            .map(|px| {
+                if is_transparent(px) {
+                    Gray8::new(trans_idx)
+                } else {
+                    let i = self.index[&color_key(px)];
+                    Gray8::new(quantized.color_idx[i])
+                }
+            })
+            .collect();
+        Indexed {
+            raster: Raster::with_pixels(width, height, pixels),
+            palette: quantized.palette.clone(),
+            transparent: quantized.transparent,
+        }
+    }
+}
+
+/// Check if a pixel is transparent
+fn is_transparent(px: &[u8]) -> bool {
+    px.len() == 4 && px[3] == 0
+}
+
+/// Color quantizer settings
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Quantizer {
+    /// Maximum number of colors (including transparent)
+    max_colors: usize,
+    /// Alpha threshold for transparent pixels
+    alpha_threshold: u8,
+    /// Matte color for compositing partially transparent pixels
+    matte: Option<SRgb8>,
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Quantizer {
+            max_colors: MAX_COLORS,
+            alpha_threshold: 1,
+            matte: None,
+        }
+    }
+}
+
+impl Quantizer {
+    /// Set the maximum number of colors (2 to 256)
+    pub fn with_max_colors(mut self, max_colors: usize) -> Self {
+        self.max_colors = max_colors.clamp(2, MAX_COLORS);
+        self
+    }
+
+    /// Set the alpha threshold.
+    ///
+    /// Pixels with alpha below the threshold are transparent; all others
+    /// are opaque.
+    pub fn with_alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// Set the matte color for compositing opaque pixels
+    pub fn with_matte(mut self, matte: Option<SRgb8>) -> Self {
+        self.matte = matte;
+        self
+    }
+
+    /// Quantize an RGBA raster to an indexed raster
+    pub fn quantize_rgba(&self, raster: &Raster<SRgba8>) -> Indexed {
+        let buf = self.prepare_rgba(raster);
+        self.quantize(raster.width(), raster.height(), &buf, 4)
+    }
+
+    /// Quantize RGBA rasters to indexed rasters sharing one palette
+    pub fn quantize_rgba_all(
+        &self,
+        rasters: &[Raster<SRgba8>],
+    ) -> Vec<Indexed> {
+        let bufs: Vec<Vec<u8>> =
+            rasters.iter().map(|r| self.prepare_rgba(r)).collect();
+        let mut hist = Histogram::default();
+        for buf in &bufs {
+            hist.add(buf, 4);
+        }
+        let quantized = hist.quantize(self.max_colors);
+        rasters
+            .iter()
+            .zip(&bufs)
+            .map(|(r, buf)| hist.map(&quantized, r.width(), r.height(), buf, 4))
+            .collect()
+    }
+
+    /// Quantize an RGB raster to an indexed raster
+    pub fn quantize_rgb(&self, raster: &Raster<SRgb8>) -> Indexed {
+        let buf = raster.as_u8_slice();
+        self.quantize(raster.width(), raster.height(), buf, 3)
+    }
+
+    /// Quantize a pixel buffer to an indexed raster
+    fn quantize(
+        &self,
+        width: u32,
+        height: u32,
+        buf: &[u8],
+        n_chan: usize,
+    ) -> Indexed {
+        let mut hist = Histogram::default();
+        hist.add(buf, n_chan);
+        let quantized = hist.quantize(self.max_colors);
+        hist.map(&quantized, width, height, buf, n_chan)
+    }
+
+    /// Prepare an RGBA buffer, with alpha either zero or fully opaque
+    fn prepare_rgba(&self, raster: &Raster<SRgba8>) -> Vec<u8> {
+        let mut buf = raster.as_u8_slice().to_vec();
+        let matte = self.matte.map(|m| {
+            let m = Raster::with_pixels(1, 1, vec![m]);
+            let m = m.as_u8_slice();
+            [m[0], m[1], m[2]]
+        });
+        for px in buf.chunks_exact_mut(4) {
+            let alpha = px[3];
+            if alpha < self.alpha_threshold {
+                px[3] = 0;
+                continue;
+            }
+            if let Some(m) = matte {
+                for (c, m) in px.iter_mut().zip(m) {
+                    *c = composite(*c, m, alpha);
+                }
+            }
+            px[3] = 0xFF;
+        }
+        buf
+    }
+}
+
+/// Composite a color channel over a matte channel
+fn composite(c: u8, m: u8, alpha: u8) -> u8 {
+    let a = u32::from(alpha);
+    let v = u32::from(c) * a + u32::from(m) * (255 - a);
+    ((v + 127) / 255) as u8
+}
+
+/// Split colors into boxes using the median cut algorithm
+fn median_cut(colors: Vec<ColorCount>, n_boxes: usize) -> Vec<Vec<ColorCount>> {
+    let mut boxes = vec![colors];
+    while boxes.len() < n_boxes {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, bx)| bx.len() > 1)
+            .map(|(i, bx)| (i, widest_channel(bx)))
+            .max_by_key(|(_, (_, range))| *range);
+        let (i, chan) = match widest {
+            Some((i, (chan, _))) => (i, chan),
+            None => break,
+        };
+        let mut bx = boxes.swap_remove(i);
+        bx.sort_by_key(|c| c.rgb[chan]);
+        let total: u64 = bx.iter().map(|c| u64::from(c.count)).sum();
+        let mut sum = 0;
+        let mut split = bx.len() / 2;
+        for (j, c) in bx.iter().enumerate() {
+            sum += u64::from(c.count);
+            if sum * 2 >= total {
+                split = j + 1;
+                break;
+            }
+        }
+        let split = split.clamp(1, bx.len() - 1);
+        let hi = bx.split_off(split);
+        boxes.push(bx);
+        boxes.push(hi);
+    }
+    boxes
+}
+
+/// Find the channel with the widest range of values in a box
+fn widest_channel(bx: &[ColorCount]) -> (usize, u8) {
+    (0..3)
+        .map(|chan| {
+            let min = bx.iter().map(|c| c.rgb[chan]).min().unwrap_or(0);
+            let max = bx.iter().map(|c| c.rgb[chan]).max().unwrap_or(0);
+            (chan, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+/// Get the mean color of a box, weighted by pixel count
+fn box_mean(bx: &[ColorCount]) -> SRgb8 {
+    let total: u64 = bx.iter().map(|c| u64::from(c.count)).sum();
+    let total = total.max(1);
+    let mut sums = [0u64; 3];
+    for c in bx {
+        for (s, v) in sums.iter_mut().zip(c.rgb) {
+            *s += u64::from(v) * u64::from(c.count);
+        }
+    }
+    let [r, g, b] = sums.map(|s| ((s + total / 2) / total) as u8);
+    SRgb8::new(r, g, b)
+}
+
+/// Find a color which is not in a palette
+pub(crate) fn unused_color(palette: &Palette) -> SRgb8 {
+    (0..=u8::MAX)
+        .map(|v| SRgb8::new(0, 0, v))
+        .find(|clr| !palette.colors().contains(clr))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exact_colors() {
+        let red = SRgb8::new(0xFF, 0, 0);
+        let blu = SRgb8::new(0, 0, 0xFF);
+        let raster = Raster::with_pixels(3, 1, vec![red, blu, red]);
+        let indexed = Quantizer::default().quantize_rgb(&raster);
+        assert_eq!(indexed.palette.colors(), [red, blu]);
+        assert_eq!(indexed.raster.as_u8_slice(), [0, 1, 0]);
+        assert_eq!(indexed.transparent, None);
+    }
+
+    #[test]
+    fn transparent() {
+        let blk = SRgba8::new(0, 0, 0, 0xFF);
+        let clr = SRgba8::new(0xFF, 0xFF, 0xFF, 0);
+        let raster = Raster::with_pixels(3, 1, vec![clr, blk, blk]);
+        let indexed = Quantizer::default()
+            .with_alpha_threshold(1)
+            .with_matte(None)
+            .quantize_rgba(&raster);
+        assert_eq!(indexed.palette.len(), 2);
+        assert_eq!(indexed.transparent, Some(1));
+        assert_eq!(indexed.raster.as_u8_slice(), [1, 0, 0]);
+        assert_ne!(indexed.palette.entry(1), indexed.palette.entry(0));
+    }
+
+    #[test]
+    fn alpha_threshold() {
+        let pixels = vec![
+            SRgba8::new(0xFF, 0, 0, 0),
+            SRgba8::new(0xFF, 0, 0, 0x40),
+            SRgba8::new(0xFF, 0, 0, 0xC0),
+            SRgba8::new(0xFF, 0, 0, 0xFF),
+        ];
+        let raster = Raster::with_pixels(4, 1, pixels);
+        let indexed = Quantizer::default()
+            .with_alpha_threshold(0)
+            .with_matte(None)
+            .quantize_rgba(&raster);
+        assert_eq!(indexed.transparent, None);
+        assert_eq!(indexed.raster.as_u8_slice(), [0, 0, 0, 0]);
+        let indexed = Quantizer::default()
+            .with_alpha_threshold(0x80)
+            .with_matte(None)
+            .quantize_rgba(&raster);
+        assert_eq!(indexed.transparent, Some(1));
+        assert_eq!(indexed.raster.as_u8_slice(), [1, 1, 0, 0]);
+        let indexed = Quantizer::default()
+            .with_alpha_threshold(0xFF)
+            .with_matte(None)
+            .quantize_rgba(&raster);
+        assert_eq!(indexed.transparent, Some(1));
+        assert_eq!(indexed.raster.as_u8_slice(), [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn matte() {
+        let pixels =
+            vec![SRgba8::new(0xFF, 0, 0, 0x80), SRgba8::new(0xFF, 0, 0, 0xFF)];
+        let raster = Raster::with_pixels(2, 1, pixels);
+        let white = SRgb8::new(0xFF, 0xFF, 0xFF);
+        let indexed = Quantizer::default()
+            .with_alpha_threshold(0)
+            .with_matte(Some(white))
+            .quantize_rgba(&raster);
+        assert_eq!(
+            indexed.palette.colors(),
+            [SRgb8::new(0xFF, 0x7F, 0x7F), SRgb8::new(0xFF, 0, 0)]
+        );
+        assert_eq!(indexed.raster.as_u8_slice(), [0, 1]);
+    }
+
+    #[test]
+    fn shared_palette() {
+        let red = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let grn = SRgba8::new(0, 0xFF, 0, 0xFF);
+        let clr = SRgba8::new(0, 0, 0, 0);
+        let a = Raster::with_pixels(2, 1, vec![red, red]);
+        let b = Raster::with_pixels(2, 1, vec![grn, clr]);
+        let indexed = Quantizer::default().quantize_rgba_all(&[a, b]);
+        assert_eq!(indexed.len(), 2);
+        assert_eq!(indexed[0].palette.len(), 3);
+        assert_eq!(indexed[0].palette.colors(), indexed[1].palette.colors());
+        assert_eq!(indexed[0].raster.as_u8_slice(), [0, 0]);
+        assert_eq!(indexed[1].raster.as_u8_slice(), [1, 2]);
+        assert_eq!(indexed[1].transparent, Some(2));
+    }
+
+    #[test]
+    fn many_colors() {
+        let pixels: Vec<SRgb8> = (0..4096_u32)
+            .map(|i| SRgb8::new((i & 0xF) as u8 * 17, (i >> 4) as u8, 0x80))
+            .collect();
+        let raster = Raster::with_pixels(64, 64, pixels);
+        let indexed = Quantizer::default().quantize_rgb(&raster);
+        assert_eq!(indexed.palette.len(), MAX_COLORS);
+        // each color should map to a nearby palette entry
+        let colors = indexed.palette.colors();
+        for (px, idx) in
+            raster.pixels().iter().zip(indexed.raster.as_u8_slice())
+        {
+            let px = color_bytes(*px);
+            let clr = color_bytes(colors[usize::from(*idx)]);
+            for (a, b) in px.iter().zip(clr) {
+                assert!(a.abs_diff(b) < 32);
+            }
+        }
+    }
+
+    /// Get the bytes of a color
+    fn color_bytes(clr: SRgb8) -> [u8; 3] {
+        let raster = Raster::with_pixels(1, 1, vec![clr]);
+        let buf = raster.as_u8_slice();
+        [buf[0], buf[1], buf[2]]
+    }
+}
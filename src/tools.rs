@@ -0,0 +1,539 @@
+// tools.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! Tools for transforming animations
+use crate::block::DisposalMethod;
+use crate::error::{Error, Result};
+use crate::private::Step;
+use pix::Region;
+use std::iter::Peekable;
+
+/// Reverse the order of animation steps.
+///
+/// The steps must be fully composited (each raster is the entire screen),
+/// as produced by Decoder.[into_steps].  Each reversed step covers the whole
+/// screen, with disposal method `Keep`.
+///
+/// Delays are reassigned to preserve the timing between steps: a step is
+/// shown for the delay of the step preceding it in the original order.  The
+/// first original step gets the delay of the last one.  Reversing twice
+/// restores the original delays.
+///
+/// [into_steps]: ../struct.Decoder.html#method.into_steps
+pub fn reverse(steps: Vec<Step>) -> Vec<Step> {
+    let mut delays: Vec<Option<u16>> =
+        steps.iter().map(|s| s.delay_time_cs()).collect();
+    // delay of each step's predecessor (wrapping around)
+    if !delays.is_empty() {
+        delays.rotate_right(1);
+    }
+    steps
+        .into_iter()
+        .zip(delays)
+        .rev()
+        .map(|(mut step, delay)| {
+            step.region = None;
+            step.with_disposal_method(DisposalMethod::Keep)
+                .with_delay_time_cs(delay)
+        })
+        .collect()
+}
+
+/// Crop animation steps to a region of the screen.
+///
+/// The steps must be fully composited, as produced by Decoder.[into_steps].
+/// Each cropped step has a raster with the dimensions of `region`, clamped
+/// to the step raster, and keeps its delay, disposal method and transparent
+/// color.  An item is [InvalidFrameDimensions] if the region does not
+/// intersect the step raster.
+///
+/// The cropped steps can be encoded directly, since [StepEnc] takes the
+/// screen size from the first step:
+///
+/// ```
+/// # fn crop_gif(gif: &[u8]) -> gift::Result<Vec<u8>> {
+/// use gift::{tools, Decoder, Encoder};
+/// use pix::Region;
+///
+/// let steps = Decoder::new(gif).into_steps().map(|s| s.unwrap());
+/// let mut out = vec![];
+/// let mut enc = Encoder::new(&mut out).into_step_enc();
+/// enc.encode_steps(tools::crop(steps, Region::new(2, 2, 4, 4)))?;
+/// # drop(enc);
+/// # Ok(out)
+/// # }
+/// ```
+///
+/// [into_steps]: ../struct.Decoder.html#method.into_steps
+/// [InvalidFrameDimensions]: ../enum.Error.html#variant.InvalidFrameDimensions
+/// [StepEnc]: ../encode/struct.StepEnc.html
+pub fn crop<I>(steps: I, region: Region) -> impl Iterator<Item = Result<Step>>
+where
+    I: IntoIterator<Item = Step>,
+{
+    steps.into_iter().map(move |step| {
+        let (width, height) = step.raster.size();
+        let reg = Region::new(0, 0, width, height).intersection(region);
+        if reg.width == 0 || reg.height == 0 {
+            return Err(Error::InvalidFrameDimensions);
+        }
+        Ok(Step {
+            raster: step.raster.crop(reg),
+            region: None,
+            ..step
+        })
+    })
+}
+
+/// Scale animation steps to a new screen size.
+///
+/// Each step raster is resized using nearest-neighbor sampling, up or down.
+/// Indexed steps keep their palette and color indices, so they can be
+/// encoded again without loss.  Step regions are scaled proportionally
+/// (rounding outward), and delays are not changed.  An item is
+/// [InvalidRasterDimensions] if the step raster or the new size is empty.
+///
+/// [InvalidRasterDimensions]: ../enum.Error.html#variant.InvalidRasterDimensions
+pub fn scale<I>(
+    steps: I,
+    width: u16,
+    height: u16,
+) -> impl Iterator<Item = Result<Step>>
+where
+    I: IntoIterator<Item = Step>,
+{
+    let (width, height) = (u32::from(width), u32::from(height));
+    steps.into_iter().map(move |step| {
+        let (sw, sh) = step.raster.size();
+        if sw == 0 || sh == 0 || width == 0 || height == 0 {
+            return Err(Error::InvalidRasterDimensions);
+        }
+        let region = step.region.map(|reg| {
+            let (x, w) = scale_span(reg.x, reg.width, sw, width);
+            let (y, h) = scale_span(reg.y, reg.height, sh, height);
+            Region::new(x, y, w, h)
+        });
+        Ok(Step {
+            raster: step.raster.scale(width, height),
+            region,
+            ..step
+        })
+    })
+}
+
+/// Scale a span proportionally, rounding outward
+fn scale_span(pos: i32, len: u32, size: u32, new_size: u32) -> (i32, u32) {
+    let (size, new_size) = (i64::from(size), i64::from(new_size));
+    let start = i64::from(pos) * new_size;
+    let end = (i64::from(pos) + i64::from(len)) * new_size;
+    let start = start.div_euclid(size);
+    let end = (end + size - 1).div_euclid(size);
+    (start as i32, (end - start) as u32)
+}
+
+/// Chain several animations into one.
+///
+/// Each source is an iterator of composited steps, such as
+/// Decoder.[into_steps].  Steps keep their delays, and indexed steps keep
+/// their own palettes (encoded as local color tables where they differ).
+///
+/// All sources must have the same screen size as the first one; a step
+/// with a different size is an [InvalidRasterDimensions] error.  Use
+/// [padded] to letterbox smaller animations onto the largest screen
+/// instead.  Errors are wrapped in [Source], with the index of the
+/// animation which caused them.
+///
+/// ## Example
+/// ```
+/// # fn concat_gifs(a: &[u8], b: &[u8]) -> gift::Result<Vec<u8>> {
+/// use gift::{tools, Decoder, Encoder};
+///
+/// let anims = vec![Decoder::new(a).into_steps(), Decoder::new(b).into_steps()];
+/// let mut enc = Encoder::new(vec![]).into_step_enc();
+/// for step in tools::concat(anims) {
+///     enc.encode_step(&step?)?;
+/// }
+/// enc.finish()
+/// # }
+/// ```
+///
+/// [into_steps]: ../struct.Decoder.html#method.into_steps
+/// [InvalidRasterDimensions]: ../enum.Error.html#variant.InvalidRasterDimensions
+/// [padded]: struct.Concat.html#method.padded
+/// [Source]: ../enum.Error.html#variant.Source
+pub fn concat<I>(animations: Vec<I>) -> Concat<I>
+where
+    I: Iterator<Item = Result<Step>>,
+{
+    Concat {
+        sources: animations.into_iter().map(Iterator::peekable).collect(),
+        index: 0,
+        screen: None,
+        pad: false,
+    }
+}
+
+/// An Iterator for [Step]s of chained animations.
+///
+/// Build with [concat].
+///
+/// [concat]: fn.concat.html
+/// [Step]: ../struct.Step.html
+pub struct Concat<I: Iterator<Item = Result<Step>>> {
+    /// Source animations
+    sources: Vec<Peekable<I>>,
+    /// Index of current source
+    index: usize,
+    /// Screen size (width and height)
+    screen: Option<(u32, u32)>,
+    /// Letterbox smaller animations
+    pad: bool,
+}
+
+impl<I> Iterator for Concat<I>
+where
+    I: Iterator<Item = Result<Step>>,
+{
+    type Item = Result<Step>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (width, height) = self.screen_size();
+        while let Some(source) = self.sources.get_mut(self.index) {
+            let index = self.index;
+            let step = match source.next() {
+                Some(Ok(step)) => step,
+                Some(Err(error)) => {
+                    let error = Box::new(error);
+                    return Some(Err(Error::Source { index, error }));
+                }
+                None => {
+                    self.index += 1;
+                    continue;
+                }
+            };
+            let (w, h) = step.raster.size();
+            if (w, h) == (width, height) {
+                return Some(Ok(step));
+            }
+            if self.pad && w <= width && h <= height {
+                return Some(Ok(step.letterbox(width, height)));
+            }
+            let error = Box::new(Error::InvalidRasterDimensions);
+            return Some(Err(Error::Source { index, error }));
+        }
+        None
+    }
+}
+
+impl<I> Concat<I>
+where
+    I: Iterator<Item = Result<Step>>,
+{
+    /// Letterbox smaller animations onto the largest screen.
+    ///
+    /// Steps are centered, with a transparent border.
+    pub fn padded(mut self) -> Self {
+        self.pad = true;
+        self
+    }
+
+    /// Get the screen size, checking the first step of each source
+    fn screen_size(&mut self) -> (u32, u32) {
+        if let Some(screen) = self.screen {
+            return screen;
+        }
+        let mut sizes = self.sources.iter_mut().filter_map(|source| {
+            source
+                .peek()
+                .and_then(|step| step.as_ref().ok())
+                .map(|step| step.raster.size())
+        });
+        let screen = if self.pad {
+            sizes.fold((0, 0), |(w, h), (sw, sh)| (w.max(sw), h.max(sh)))
+        } else {
+            sizes.next().unwrap_or_default()
+        };
+        self.screen = Some(screen);
+        screen
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Decoder;
+    use pix::{rgb::SRgba8, Raster};
+
+    #[rustfmt::skip]
+    const GIF_1: &[u8] = &[
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x0A, 0x00, 0x0A, 0x00, 0x91, 0x00,
+        0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00,
+        0x00, 0x21, 0xF9, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x00,
+        0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00, 0x00, 0x02, 0x16, 0x8C, 0x2D, 0x99,
+        0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8,
+        0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01, 0x00, 0x3B,
+    ];
+
+    fn make_steps() -> Vec<Step> {
+        let clrs = [
+            SRgba8::new(255, 0, 0, 255),
+            SRgba8::new(0, 255, 0, 255),
+            SRgba8::new(0, 0, 255, 255),
+        ];
+        clrs.iter()
+            .zip([Some(10), Some(0), Some(30)])
+            .map(|(clr, delay)| {
+                let mut raster = Raster::with_clear(4, 4);
+                *raster.pixel_mut(1, 1) = *clr;
+                Step::with_true_color(raster)
+                    .with_disposal_method(DisposalMethod::Background)
+                    .with_delay_time_cs(delay)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reverse_steps() {
+        let steps = make_steps();
+        let reversed = reverse(steps.clone());
+        assert_eq!(reversed.len(), 3);
+        let delays: Vec<_> =
+            reversed.iter().map(|s| s.delay_time_cs()).collect();
+        assert_eq!(delays, [Some(0), Some(10), Some(30)]);
+        for (a, b) in reversed.iter().zip(steps.iter().rev()) {
+            assert_eq!(a.raster().pixels(), b.raster().pixels());
+            assert_eq!(a.disposal_method(), DisposalMethod::Keep);
+        }
+        let twice = reverse(reversed);
+        for (a, b) in twice.iter().zip(&steps) {
+            assert_eq!(a.raster().pixels(), b.raster().pixels());
+            assert_eq!(a.delay_time_cs(), b.delay_time_cs());
+        }
+    }
+
+    #[test]
+    fn reverse_single() {
+        let step = make_steps().pop().unwrap();
+        let reversed = reverse(vec![step.clone()]);
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(reversed[0].delay_time_cs(), Some(30));
+        assert_eq!(reversed[0].raster().pixels(), step.raster().pixels());
+        assert!(reverse(vec![]).is_empty());
+    }
+
+    /// Crop GIF_1 to a 4x4 region
+    fn crop_gif_1(x: i32, y: i32) -> Vec<SRgba8> {
+        let steps = Decoder::new(GIF_1).into_steps().map(|s| s.unwrap());
+        let cropped: Vec<Step> = crop(steps, Region::new(x, y, 4, 4))
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(cropped.len(), 1);
+        let raster = cropped[0].raster();
+        assert_eq!((raster.width(), raster.height()), (4, 4));
+        raster.pixels().to_vec()
+    }
+
+    #[test]
+    fn crop_steps() {
+        let w = SRgba8::new(0xFF, 0xFF, 0xFF, 0xFF);
+        let r = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let b = SRgba8::new(0, 0, 0xFF, 0xFF);
+        assert_eq!(crop_gif_1(3, 3), [w; 16]);
+        #[rustfmt::skip]
+        let expected = [
+            r, r, r, r,
+            r, r, w, w,
+            r, r, w, w,
+            b, b, w, w,
+        ];
+        assert_eq!(crop_gif_1(1, 2), expected);
+    }
+
+    #[test]
+    fn crop_clamped() {
+        let steps = make_steps();
+        let cropped: Vec<Step> = crop(steps.clone(), Region::new(1, 1, 10, 10))
+            .map(|s| s.unwrap())
+            .collect();
+        for (a, b) in cropped.iter().zip(&steps) {
+            let raster = a.raster();
+            assert_eq!((raster.width(), raster.height()), (3, 3));
+            assert_eq!(raster.pixel(0, 0), b.raster().pixel(1, 1));
+            assert_eq!(a.delay_time_cs(), b.delay_time_cs());
+            assert_eq!(a.disposal_method(), b.disposal_method());
+        }
+        let mut outside = crop(steps, Region::new(4, 0, 2, 2));
+        assert!(matches!(
+            outside.next(),
+            Some(Err(Error::InvalidFrameDimensions))
+        ));
+    }
+
+    #[test]
+    fn scale_steps() {
+        let w = SRgba8::new(0xFF, 0xFF, 0xFF, 0xFF);
+        let r = SRgba8::new(0xFF, 0, 0, 0xFF);
+        let b = SRgba8::new(0, 0, 0xFF, 0xFF);
+        let steps = Decoder::new(GIF_1).into_steps().map(|s| s.unwrap());
+        let small: Vec<Step> = scale(steps, 5, 5).map(|s| s.unwrap()).collect();
+        assert_eq!(small[0].region(), Region::new(0, 0, 5, 5));
+        #[rustfmt::skip]
+        let expected = [
+            r, r, r, b, b,
+            r, r, r, b, b,
+            r, r, w, w, b,
+            b, b, w, w, r,
+            b, b, b, r, r,
+        ];
+        assert_eq!(small[0].raster().pixels(), expected);
+        let large: Vec<Step> =
+            scale(small, 10, 10).map(|s| s.unwrap()).collect();
+        let raster = large[0].raster();
+        assert_eq!((raster.width(), raster.height()), (10, 10));
+        for y in 0..10 {
+            for x in 0..10 {
+                let p = expected[(y / 2 * 5 + x / 2) as usize];
+                assert_eq!(raster.pixel(x, y), p);
+            }
+        }
+    }
+
+    #[test]
+    fn scale_indexed() {
+        use pix::{gray::Gray8, rgb::SRgb8, Palette};
+        let mut palette = Palette::new(4);
+        for i in 0..4 {
+            palette.set_entry(SRgb8::new(i * 50, 0, 0));
+        }
+        let mut raster = Raster::<Gray8>::with_clear(3, 2);
+        for (i, p) in raster.pixels_mut().iter_mut().enumerate() {
+            *p = Gray8::new(i as u8 % 4);
+        }
+        let step = Step::with_indexed(raster, palette)
+            .with_region(Region::new(1, 1, 1, 1))
+            .with_delay_time_cs(Some(7));
+        let scaled: Vec<Step> =
+            scale([step], 6, 3).map(|s| s.unwrap()).collect();
+        let scaled = &scaled[0];
+        assert_eq!(scaled.region(), Region::new(2, 1, 2, 2));
+        assert_eq!(scaled.delay_time_cs(), Some(7));
+        match &scaled.raster {
+            crate::private::StepRaster::Indexed(r, p) => {
+                assert_eq!(p.len(), 4);
+                let idx: Vec<u8> = r.as_u8_slice().to_vec();
+                assert_eq!(
+                    idx,
+                    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 3, 3, 0, 0, 1, 1,]
+                );
+            }
+            _ => panic!("expected indexed raster"),
+        }
+        assert!(scale(make_steps(), 0, 3).next().unwrap().is_err());
+    }
+
+    /// Make an indexed animation, with one color per step
+    fn make_anim(
+        width: u32,
+        height: u32,
+        colors: &[(u8, u8, u8)],
+        delay: u16,
+    ) -> Vec<u8> {
+        use crate::Encoder;
+        use pix::{gray::Gray8, rgb::SRgb8, Palette};
+        let mut palette = Palette::new(colors.len());
+        for (r, g, b) in colors {
+            palette.set_entry(SRgb8::new(*r, *g, *b));
+        }
+        let mut enc = Encoder::new(vec![]).into_step_enc();
+        for i in 0..colors.len() {
+            let raster = Raster::with_color(width, height, Gray8::new(i as u8));
+            let step = Step::with_indexed(raster, palette.clone())
+                .with_delay_time_cs(Some(delay + i as u16));
+            enc.encode_step(&step).unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn concat_steps() {
+        let a = make_anim(4, 3, &[(255, 0, 0), (0, 255, 0)], 10);
+        let b = make_anim(4, 3, &[(0, 0, 255), (9, 9, 9), (7, 7, 7)], 20);
+        let anims = vec![
+            Decoder::new(&a[..]).into_steps(),
+            Decoder::new(&b[..]).into_steps(),
+        ];
+        let steps: Vec<Step> = concat(anims).map(|s| s.unwrap()).collect();
+        assert_eq!(steps.len(), 5);
+        let delays: Vec<_> = steps.iter().map(|s| s.delay_time_cs()).collect();
+        assert_eq!(delays, [Some(10), Some(11), Some(20), Some(21), Some(22)]);
+        let reds: Vec<_> = steps
+            .iter()
+            .map(|s| u8::from(pix::rgb::Rgb::red(s.raster().pixel(0, 0))))
+            .collect();
+        assert_eq!(reds, [255, 0, 0, 9, 7]);
+        // round trip keeps all steps and timing
+        let mut enc = crate::Encoder::new(vec![]).into_step_enc();
+        for step in &steps {
+            enc.encode_step(step).unwrap();
+        }
+        let gif = enc.finish().unwrap();
+        let delays: Vec<_> = Decoder::new(&gif[..])
+            .into_steps()
+            .map(|s| s.unwrap().delay_time_cs())
+            .collect();
+        assert_eq!(delays, [Some(10), Some(11), Some(20), Some(21), Some(22)]);
+    }
+
+    #[test]
+    fn concat_mismatch() {
+        let a = make_anim(4, 3, &[(255, 0, 0)], 10);
+        let b = make_anim(2, 2, &[(0, 0, 255)], 20);
+        let anims = vec![
+            Decoder::new(&a[..]).into_steps(),
+            Decoder::new(&b[..]).into_steps(),
+        ];
+        let mut steps = concat(anims);
+        assert!(steps.next().unwrap().is_ok());
+        match steps.next() {
+            Some(Err(Error::Source { index, error })) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*error, Error::InvalidRasterDimensions));
+            }
+            _ => panic!("expected source error"),
+        }
+        assert!(steps.next().is_none());
+        let anims = vec![
+            Decoder::new(&a[..]).into_steps(),
+            Decoder::new(&a[..20]).into_steps(),
+        ];
+        let err = concat(anims).find_map(|s| s.err()).unwrap();
+        assert!(matches!(err, Error::Source { index: 1, .. }));
+    }
+
+    #[test]
+    fn concat_padded() {
+        let a = make_anim(4, 3, &[(255, 0, 0)], 10);
+        let b = make_anim(2, 1, &[(0, 0, 255)], 20);
+        let anims = vec![
+            Decoder::new(&a[..]).into_steps(),
+            Decoder::new(&b[..]).into_steps(),
+        ];
+        let steps: Vec<Step> =
+            concat(anims).padded().map(|s| s.unwrap()).collect();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].region(), Region::new(1, 1, 2, 1));
+        assert_eq!(steps[1].delay_time_cs(), Some(20));
+        let raster = steps[1].raster();
+        assert_eq!((raster.width(), raster.height()), (4, 3));
+        let b = SRgba8::new(0, 0, 0xFF, 0xFF);
+        let t = SRgba8::default();
+        #[rustfmt::skip]
+        let expected = [
+            t, t, t, t,
+            t, b, b, t,
+            t, t, t, t,
+        ];
+        assert_eq!(raster.pixels(), expected);
+    }
+}
@@ -0,0 +1,647 @@
+// validate.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+//! GIF file validation
+use crate::block::*;
+use crate::decode::Recovered;
+use crate::error::{Error, Result};
+use crate::lzw::Decompressor;
+use crate::private::Decoder;
+use std::cell::Cell;
+use std::io::{self, BufReader, Read};
+use std::time::Duration;
+
+/// Extension data size (bytes) above which [OversizedExtension] is reported
+///
+/// [OversizedExtension]: enum.Issue.html#variant.OversizedExtension
+pub const MAX_EXTENSION_SZ: usize = 1 << 16;
+
+/// Severity of a validation [Finding]
+///
+/// [Finding]: struct.Finding.html
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// File can be decoded, but may not display as intended
+    Warning,
+    /// File is invalid, or cannot be completely decoded
+    Error,
+}
+
+/// Issue found while validating a GIF file
+#[derive(Debug)]
+pub enum Issue {
+    /// Decoding error (validation stops)
+    Decode(Error),
+    /// GIF version not supported (validated as 89a)
+    UnsupportedVersion(Version),
+    /// Color table truncated by end of file (sizes in bytes)
+    TruncatedColorTable {
+        /// Expected color table size
+        expected: usize,
+        /// Actual size before end of file
+        actual: usize,
+    },
+    /// Color table size set, but table not present
+    IgnoredColorTableSize,
+    /// Extension block missing its terminator
+    MissingTerminator,
+    /// Background color index outside of global color table
+    BackgroundColorOutOfRange {
+        /// Background color index
+        index: u8,
+        /// Number of entries in the global color table
+        table_len: usize,
+    },
+    /// Graphic control extension not followed by an image
+    UnusedGraphicControl,
+    /// Frame location / size outside of logical screen
+    FrameOutOfBounds,
+    /// No global or local color table for a frame
+    MissingColorTable,
+    /// Color index outside of color table
+    ColorIndexOverflow {
+        /// Largest color index in the frame
+        index: u8,
+        /// Number of entries in the color table
+        table_len: usize,
+    },
+    /// LZW minimum code size outside of 2 to 8 (validated as clamped)
+    InvalidLzwCodeSize(u8),
+    /// Compressed LZW data invalid or corrupt
+    InvalidLzwData,
+    /// Image data smaller than frame
+    IncompleteImageData,
+    /// Image data larger than frame
+    ExtraImageData,
+    /// Extension data larger than [MAX_EXTENSION_SZ] (bytes)
+    ///
+    /// [MAX_EXTENSION_SZ]: constant.MAX_EXTENSION_SZ.html
+    OversizedExtension(usize),
+    /// File ends without a trailer block
+    MissingTrailer,
+    /// Data after trailer block (bytes)
+    TrailingData(u64),
+}
+
+/// One finding in a [ValidationReport]
+///
+/// [ValidationReport]: struct.ValidationReport.html
+#[derive(Debug)]
+pub struct Finding {
+    /// Severity of the issue
+    pub severity: Severity,
+    /// Issue found
+    pub issue: Issue,
+    /// Byte offset of block containing the issue
+    pub offset: u64,
+    /// Index of frame containing the issue
+    pub frame: Option<usize>,
+}
+
+/// Report from [validate]ing a GIF file
+///
+/// [validate]: fn.validate.html
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Findings, in file order
+    pub findings: Vec<Finding>,
+    /// Number of frames
+    pub frame_count: usize,
+    /// Total duration of all frame delays
+    pub duration: Duration,
+    /// Total file size (bytes)
+    pub total_size: u64,
+}
+
+/// Reader which counts bytes read
+struct CountingReader<'a, R: Read> {
+    /// Wrapped reader
+    reader: R,
+    /// Count of bytes read
+    count: &'a Cell<u64>,
+}
+
+/// GIF validator state
+#[derive(Default)]
+struct Validator {
+    /// Report being built
+    report: ValidationReport,
+    /// Logical screen width and height
+    screen: (u16, u16),
+    /// Background color index
+    background_idx: u8,
+    /// Global color table length
+    global_tbl_len: Option<usize>,
+    /// Graphic control for next frame
+    graphic_control: Option<GraphicControl>,
+    /// Image descriptor for next frame
+    image_desc: Option<ImageDesc>,
+    /// Local color table length for next frame
+    local_tbl_len: Option<usize>,
+}
+
+/// Validate a GIF file, reporting all issues found.
+///
+/// Decoding is lenient, so that recoverable issues do not hide later
+/// ones.  An `Err` is only returned for I/O errors.
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let gif = &[
+/// #   0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00,
+/// #   0x02, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00,
+/// #   0xff, 0xff, 0xff, 0x2c, 0x00, 0x00, 0x00, 0x00,
+/// #   0x02, 0x00, 0x02, 0x00, 0x00, 0x02, 0x03, 0x0c,
+/// #   0x10, 0x05, 0x00, 0x3b,
+/// # ][..];
+/// let report = gift::validate(gif)?;
+/// for finding in &report.findings {
+///     println!("{:?} @ {}: {:?}", finding.severity, finding.offset,
+///         finding.issue);
+/// }
+/// assert!(report.is_valid());
+/// # Ok(())
+/// # }
+/// ```
+pub fn validate<R: Read>(reader: R) -> Result<ValidationReport> {
+    let mut reader = BufReader::new(reader);
+    let count = Cell::new(0);
+    let counter = CountingReader {
+        reader: &mut reader,
+        count: &count,
+    };
+    let mut blocks = Decoder::new(counter)
+        .raw_image_data(true)
+        .lenient(true)
+        .into_blocks();
+    let mut val = Validator::default();
+    let mut offset = 0;
+    let mut trailer = false;
+    while let Some(res) = blocks.next() {
+        match res {
+            Ok(block) => {
+                trailer = matches!(block, Block::Trailer(_));
+                for rec in blocks.take_recovered() {
+                    val.check_recovered(rec, offset);
+                }
+                val.check_block(block, offset);
+            }
+            Err(Error::Io(e)) => return Err(Error::Io(e)),
+            Err(Error::UnexpectedEndOfFile)
+                if offset > 0
+                    && count.get() == offset
+                    && !blocks.expects_block() =>
+            {
+                val.push(Issue::MissingTrailer, offset, None);
+            }
+            Err(e) => val.push(Issue::Decode(e), offset, None),
+        }
+        offset = count.get();
+    }
+    drop(blocks);
+    let mut total_size = offset;
+    if trailer {
+        let extra = io::copy(&mut reader, &mut io::sink())?;
+        if extra > 0 {
+            val.push(Issue::TrailingData(extra), offset, None);
+        }
+        total_size += extra;
+    }
+    val.report.total_size = total_size;
+    Ok(val.report)
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl Issue {
+    /// Get the severity of the issue
+    pub fn severity(&self) -> Severity {
+        use self::Issue::*;
+        match self {
+            UnsupportedVersion(_)
+            | IgnoredColorTableSize
+            | BackgroundColorOutOfRange { .. }
+            | UnusedGraphicControl
+            | FrameOutOfBounds
+            | ExtraImageData
+            | OversizedExtension(_)
+            | TrailingData(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl ValidationReport {
+    /// Check if the report contains no errors (warnings are allowed)
+    pub fn is_valid(&self) -> bool {
+        self.findings.iter().all(|f| f.severity < Severity::Error)
+    }
+}
+
+impl Validator {
+    /// Add a finding to the report
+    fn push(&mut self, issue: Issue, offset: u64, frame: Option<usize>) {
+        self.report.findings.push(Finding {
+            severity: issue.severity(),
+            issue,
+            offset,
+            frame,
+        });
+    }
+
+    /// Get the index of the next frame
+    fn frame(&self) -> Option<usize> {
+        Some(self.report.frame_count)
+    }
+
+    /// Check a problem recovered from while decoding a block
+    fn check_recovered(&mut self, rec: Recovered, offset: u64) {
+        let issue = match rec {
+            Recovered::TruncatedColorTable { expected, actual } => {
+                Issue::TruncatedColorTable { expected, actual }
+            }
+            Recovered::MissingTerminator => Issue::MissingTerminator,
+        };
+        self.push(issue, offset, None);
+    }
+
+    /// Check one block
+    fn check_block(&mut self, block: Block, offset: u64) {
+        match block {
+            Block::Header(b) => {
+                if let v @ Version::Other(_) = b.version_enum() {
+                    self.push(Issue::UnsupportedVersion(v), offset, None);
+                }
+            }
+            Block::LogicalScreenDesc(b) => {
+                self.screen = (b.screen_width(), b.screen_height());
+                self.background_idx = b.background_color_idx();
+                if is_table_size_ignored(b.flags()) {
+                    self.push(Issue::IgnoredColorTableSize, offset, None);
+                }
+            }
+            Block::GlobalColorTable(b) => {
+                let index = self.background_idx;
+                let table_len = b.len();
+                if usize::from(index) >= table_len {
+                    let issue =
+                        Issue::BackgroundColorOutOfRange { index, table_len };
+                    self.push(issue, offset, None);
+                }
+                self.global_tbl_len = Some(table_len);
+            }
+            Block::PlainText(b) => {
+                let sz = b.sub_blocks().iter().map(|b| b.len()).sum();
+                self.check_extension(sz, offset);
+            }
+            Block::GraphicControl(b) => {
+                if self.graphic_control.is_some() {
+                    let frame = self.frame();
+                    self.push(Issue::UnusedGraphicControl, offset, frame);
+                }
+                self.graphic_control = Some(b);
+            }
+            Block::Comment(b) => {
+                let sz = b.comments().map(|c| c.len()).sum();
+                self.check_extension(sz, offset);
+            }
+            Block::Application(b) => {
+                let sz = b.app_data().map(|d| d.len()).sum();
+                self.check_extension(sz, offset);
+            }
+            Block::Unknown(b) => {
+                let sz = b.sub_blocks().map(|d| d.len()).sum();
+                self.check_extension(sz, offset);
+            }
+            Block::ImageDesc(b) => self.check_image_desc(b, offset),
+            Block::LocalColorTable(b) => self.local_tbl_len = Some(b.len()),
+            Block::ImageData(b) => self.check_image_data(&b, offset),
+            Block::Trailer(_) => self.check_trailer(offset),
+        }
+    }
+
+    /// Check extension data size
+    fn check_extension(&mut self, sz: usize, offset: u64) {
+        if sz > MAX_EXTENSION_SZ {
+            self.push(Issue::OversizedExtension(sz), offset, None);
+        }
+    }
+
+    /// Check an image descriptor
+    fn check_image_desc(&mut self, b: ImageDesc, offset: u64) {
+        let right = u32::from(b.left()) + u32::from(b.width());
+        let bottom = u32::from(b.top()) + u32::from(b.height());
+        if right > self.screen.0.into() || bottom > self.screen.1.into() {
+            let frame = self.frame();
+            self.push(Issue::FrameOutOfBounds, offset, frame);
+        }
+        if is_table_size_ignored(b.flags()) {
+            let frame = self.frame();
+            self.push(Issue::IgnoredColorTableSize, offset, frame);
+        }
+        self.image_desc = Some(b);
+    }
+
+    /// Check image data (raw) for a frame
+    fn check_image_data(&mut self, b: &ImageData, offset: u64) {
+        let frame = self.frame();
+        let control = self.graphic_control.take();
+        let local_tbl_len = self.local_tbl_len.take();
+        let Some(image_desc) = self.image_desc.take() else {
+            return;
+        };
+        self.report.frame_count += 1;
+        if let Some(control) = control {
+            let ms = u64::from(control.delay_time_cs()) * 10;
+            self.report.duration += Duration::from_millis(ms);
+        }
+        let sz = image_desc.image_sz();
+        let mut min_code_bits = b.min_code_size().unwrap_or(2);
+        if !(2..=8).contains(&min_code_bits) {
+            let issue = Issue::InvalidLzwCodeSize(min_code_bits);
+            self.push(issue, offset, frame);
+            min_code_bits = min_code_bits.clamp(2, 8);
+        }
+        let mut dec = Decompressor::new(min_code_bits).with_max_len(sz);
+        let mut indices = Vec::with_capacity(sz);
+        let raw = b.data();
+        let mut pos = 0;
+        while pos < raw.len() {
+            let end = (pos + 1 + usize::from(raw[pos])).min(raw.len());
+            if dec.decompress(&raw[pos + 1..end], &mut indices).is_err() {
+                self.push(Issue::InvalidLzwData, offset, frame);
+                return;
+            }
+            pos = end;
+        }
+        if dec.is_truncated() {
+            self.push(Issue::ExtraImageData, offset, frame);
+        }
+        if indices.len() < sz {
+            self.push(Issue::IncompleteImageData, offset, frame);
+        }
+        match local_tbl_len.or(self.global_tbl_len) {
+            Some(table_len) => {
+                if let Some(index) = indices.iter().copied().max() {
+                    if usize::from(index) >= table_len {
+                        let issue =
+                            Issue::ColorIndexOverflow { index, table_len };
+                        self.push(issue, offset, frame);
+                    }
+                }
+            }
+            None => self.push(Issue::MissingColorTable, offset, frame),
+        }
+    }
+
+    /// Check trailer block
+    fn check_trailer(&mut self, offset: u64) {
+        if self.graphic_control.take().is_some() {
+            let frame = self.frame();
+            self.push(Issue::UnusedGraphicControl, offset, frame);
+        }
+    }
+}
+
+/// Check for a color table size set without the present flag
+fn is_table_size_ignored(flags: u8) -> bool {
+    flags & 0x80 == 0 && flags & 0x07 != 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[rustfmt::skip]
+    const GIF_1: &[u8] = &[
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x0A, 0x00, 0x0A, 0x00, 0x91, 0x00,
+        0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00,
+        0x00, 0x21, 0xF9, 0x04, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x00,
+        0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00, 0x00, 0x02, 0x16, 0x8C, 0x2D, 0x99,
+        0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8,
+        0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01, 0x00, 0x3B,
+    ];
+
+    /// Offset of graphic control extension
+    const GCE: usize = 25;
+
+    /// Offset of image descriptor
+    const DESC: usize = 33;
+
+    /// Offset of trailer
+    const TRAILER: usize = 68;
+
+    fn single(gif: &[u8]) -> Finding {
+        let mut report = validate(gif).unwrap();
+        assert_eq!(report.findings.len(), 1, "{:?}", report.findings);
+        report.findings.pop().unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let report = validate(GIF_1).unwrap();
+        assert!(report.findings.is_empty());
+        assert!(report.is_valid());
+        assert_eq!(report.frame_count, 1);
+        assert_eq!(report.duration, Duration::from_millis(100));
+        assert_eq!(report.total_size, GIF_1.len() as u64);
+    }
+
+    #[test]
+    fn missing_trailer() {
+        let f = single(&GIF_1[..TRAILER]);
+        assert!(matches!(f.issue, Issue::MissingTrailer));
+        assert_eq!(f.severity, Severity::Error);
+        assert_eq!(f.offset, TRAILER as u64);
+    }
+
+    #[test]
+    fn truncated() {
+        let f = single(&GIF_1[..50]);
+        assert!(matches!(f.issue, Issue::Decode(Error::UnexpectedEndOfFile)));
+        assert_eq!(f.offset, 43);
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut gif = GIF_1.to_vec();
+        gif.extend_from_slice(&[0; 3]);
+        let report = validate(&gif[..]).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.total_size, gif.len() as u64);
+        let f = &report.findings[0];
+        assert!(matches!(f.issue, Issue::TrailingData(3)));
+        assert_eq!(f.severity, Severity::Warning);
+        assert_eq!(f.offset, GIF_1.len() as u64);
+    }
+
+    #[test]
+    fn invalid_block() {
+        let mut gif = GIF_1.to_vec();
+        gif[TRAILER] = 0x99;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::Decode(Error::InvalidBlockCode)));
+    }
+
+    #[test]
+    fn unused_graphic_control() {
+        let mut gif = GIF_1.to_vec();
+        gif.splice(DESC..DESC, GIF_1[GCE..DESC].iter().copied());
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::UnusedGraphicControl));
+        assert_eq!(f.offset, DESC as u64);
+        assert_eq!(f.frame, Some(0));
+    }
+
+    #[test]
+    fn frame_out_of_bounds() {
+        let mut gif = GIF_1.to_vec();
+        gif[DESC + 1] = 5;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::FrameOutOfBounds));
+        assert_eq!(f.offset, DESC as u64);
+        assert_eq!(f.frame, Some(0));
+    }
+
+    #[test]
+    fn image_data_size() {
+        let mut gif = GIF_1.to_vec();
+        gif[DESC + 7] = 5;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::ExtraImageData));
+        assert_eq!(f.offset, 43);
+        gif[DESC + 7] = 11;
+        gif[8] = 11;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::IncompleteImageData));
+    }
+
+    #[test]
+    fn color_index_overflow() {
+        let mut gif = GIF_1.to_vec();
+        gif[DESC + 9] = 0x80;
+        gif.splice(DESC + 10..DESC + 10, [0; 6]);
+        let f = single(&gif);
+        match f.issue {
+            Issue::ColorIndexOverflow { index, table_len } => {
+                assert_eq!(index, 2);
+                assert_eq!(table_len, 2);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(f.frame, Some(0));
+    }
+
+    #[test]
+    fn missing_color_table() {
+        let mut gif = GIF_1.to_vec();
+        gif[10] = 0x10;
+        gif.drain(13..GCE);
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::MissingColorTable));
+    }
+
+    #[test]
+    fn background_color_out_of_range() {
+        let mut gif = GIF_1.to_vec();
+        gif[11] = 200;
+        assert!(validate(&gif[..]).unwrap().is_valid());
+        let f = single(&gif);
+        assert!(matches!(
+            f.issue,
+            Issue::BackgroundColorOutOfRange {
+                index: 200,
+                table_len: 4
+            }
+        ));
+        assert_eq!(f.severity, Severity::Warning);
+        assert_eq!(f.offset, 13);
+    }
+
+    #[test]
+    fn invalid_lzw_code_size() {
+        let mut gif = GIF_1.to_vec();
+        gif[DESC + 10] = 0;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::InvalidLzwCodeSize(0)));
+        assert_eq!(f.severity, Severity::Error);
+        assert_eq!(f.offset, 43);
+        assert_eq!(f.frame, Some(0));
+    }
+
+    #[test]
+    fn truncated_color_table() {
+        let report = validate(&GIF_1[..16]).unwrap();
+        assert!(!report.is_valid());
+        let f = &report.findings[0];
+        assert!(matches!(
+            f.issue,
+            Issue::TruncatedColorTable {
+                expected: 12,
+                actual: 3
+            }
+        ));
+        assert_eq!(f.offset, 13);
+    }
+
+    #[test]
+    fn ignored_color_table_size() {
+        let mut gif = GIF_1.to_vec();
+        gif[DESC + 9] = 0x01;
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::IgnoredColorTableSize));
+        assert_eq!(f.severity, Severity::Warning);
+        assert_eq!(f.offset, DESC as u64);
+        assert_eq!(f.frame, Some(0));
+    }
+
+    #[test]
+    fn missing_terminator() {
+        let mut gif = GIF_1[..TRAILER].to_vec();
+        gif.extend_from_slice(&[0x21, 0xFE, 0x03, b'a', b'b', b'c', 0x3B]);
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::MissingTerminator));
+        assert_eq!(f.severity, Severity::Error);
+        assert_eq!(f.offset, TRAILER as u64);
+    }
+
+    #[test]
+    fn oversized_extension() {
+        let mut gif = GIF_1[..TRAILER].to_vec();
+        gif.extend_from_slice(&[0x21, 0xFE]);
+        for _ in 0..300 {
+            gif.push(255);
+            gif.extend_from_slice(&[b'x'; 255]);
+        }
+        gif.extend_from_slice(&[0x00, 0x3B]);
+        let f = single(&gif);
+        assert!(matches!(f.issue, Issue::OversizedExtension(76500)));
+        assert_eq!(f.offset, TRAILER as u64);
+    }
+
+    #[test]
+    fn multiple() {
+        let mut gif = GIF_1.to_vec();
+        gif[5] = b'b';
+        gif[DESC + 1] = 5;
+        gif.push(0);
+        let report = validate(&gif[..]).unwrap();
+        assert!(report.is_valid());
+        let issues: Vec<_> = report.findings.iter().map(|f| &f.issue).collect();
+        assert!(matches!(
+            issues[..],
+            [
+                Issue::UnsupportedVersion(Version::Other(_)),
+                Issue::FrameOutOfBounds,
+                Issue::TrailingData(1)
+            ]
+        ));
+    }
+}
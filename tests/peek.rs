@@ -0,0 +1,76 @@
+// peek.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+#![cfg(feature = "cmd")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Run gift peek on a test fixture
+fn peek(args: &[&str]) -> Output {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join("red.gif");
+    Command::new(env!("CARGO_BIN_EXE_gift"))
+        .arg("peek")
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn peek_table() {
+    let out = peek(&[]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        " Blk#   Offset   Bytes  Block\n\
+        \x20   0        0       6  Header\n\
+        \x20   1        6       7  LogicalScreenDesc\n\
+        \x20   2       13       6  GlobalColorTable\n\
+        \x20   3       19      10  ImageDesc\n\
+        \x20   4       29       7  ImageData\n\
+        \x20   5       36       1  Trailer\n"
+    );
+}
+
+#[test]
+fn peek_block() {
+    let out = peek(&["--block", "3"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "block 3: ImageDesc, 10 bytes\n\
+         00000013  2c 00 00 00 00 04 00 04  00 00                    \
+         |,.........|\n"
+    );
+}
+
+#[test]
+fn peek_range() {
+    let out = peek(&["--range", "0..2"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "block 0: Header, 6 bytes\n\
+         00000000  47 49 46 38 39 61                                 \
+         |GIF89a|\n\
+         block 1: LogicalScreenDesc, 7 bytes\n\
+         00000006  04 00 04 00 80 00 00                              \
+         |.......|\n"
+    );
+}
+
+#[test]
+fn peek_out_of_range() {
+    let out = peek(&["--block", "6"]);
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("block 6 out of range (6 blocks)"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,77 @@
+// show.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+#![cfg(feature = "cmd")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Run gift show on a test fixture
+fn show(args: &[&str], name: &str) -> Output {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Command::new(env!("CARGO_BIN_EXE_gift"))
+        .arg("show")
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+}
+
+/// Get output lines with color escape codes removed
+fn lines(out: &Output) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let mut text = String::new();
+    let mut chars = stdout.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip to end of escape sequence
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            text.push(c);
+        }
+    }
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn show_all() {
+    let out = show(&[], "anim.gif");
+    assert!(out.status.success());
+    let lines = lines(&out);
+    assert_eq!(lines[1], "GIF89a, frames: 3, repeat: ∞");
+    assert_eq!(lines.len(), 6);
+}
+
+#[test]
+fn show_limit() {
+    let out = show(&["--limit", "2"], "anim.gif");
+    assert!(out.status.success());
+    let lines = lines(&out);
+    assert_eq!(lines[1], "GIF89a, frames: ≥2, repeat: ∞");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[4].starts_with("   1   0.25   bg"));
+}
+
+#[test]
+fn show_frames() {
+    let out = show(&["--frames", "1..3"], "anim.gif");
+    assert!(out.status.success());
+    let lines = lines(&out);
+    assert_eq!(lines[1], "GIF89a, frames: ≥3, repeat: ∞");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[3].starts_with("   1 "));
+    assert!(lines[4].starts_with("   2 "));
+}
+
+#[test]
+fn show_truncated() {
+    let out = show(&[], "truncated.gif");
+    assert_eq!(out.status.code(), Some(2));
+    let lines = lines(&out);
+    assert_eq!(lines[1], "GIF89a, frames: ≥1, repeat: ∞");
+    assert!(lines[3].starts_with("   0   0.10 none"));
+    assert!(lines[4].starts_with("error in frame 1:"));
+}
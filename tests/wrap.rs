@@ -0,0 +1,84 @@
+// wrap.rs
+//
+// Copyright (c) 2026  Douglas Lau
+//
+#![cfg(feature = "cmd")]
+
+use gift::Step;
+use pix::rgb::SRgba8;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Get path of a test fixture
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Get a temporary file path
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gift_{}_{name}", std::process::id()))
+}
+
+/// Run gift wrap with a manifest
+fn wrap(manifest: &Path, output: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gift"))
+        .arg("wrap")
+        .arg("--manifest")
+        .arg(manifest)
+        .arg("--output")
+        .arg(output)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn wrap_manifest() {
+    let output = temp_path("wrapped.gif");
+    let out = wrap(&fixture("anim.toml"), &output);
+    assert!(out.status.success(), "{out:?}");
+    let steps: Vec<Step> = gift::open(&output)
+        .unwrap()
+        .into_steps()
+        .map(|s| s.unwrap())
+        .collect();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(steps.len(), 3);
+    let delays: Vec<_> = steps.iter().map(|s| s.delay_time_cs()).collect();
+    assert_eq!(delays, [Some(10), Some(25), Some(50)]);
+    let red = SRgba8::new(0xFF, 0, 0, 0xFF);
+    let green = SRgba8::new(0, 0xFF, 0, 0xFF);
+    let blue = SRgba8::new(0, 0, 0xFF, 0xFF);
+    assert_eq!(steps[0].raster().pixels(), [red; 16]);
+    assert_eq!(steps[1].raster().pixels(), [green; 16]);
+    let raster = steps[2].raster();
+    assert_eq!(raster.pixel(0, 0), SRgba8::default());
+    assert_eq!(raster.pixel(1, 1), blue);
+    assert_eq!(raster.pixel(2, 2), blue);
+    assert_eq!(raster.pixel(3, 3), SRgba8::default());
+}
+
+#[test]
+fn wrap_invalid_frame() {
+    let manifest = temp_path("bad.toml");
+    let red = fixture("red.gif");
+    let blue = fixture("blue.gif");
+    fs::write(
+        &manifest,
+        format!(
+            "output = \"bad.gif\"\n\
+             [[frames]]\nfile = {blue:?}\n\
+             [[frames]]\nfile = {red:?}\n"
+        ),
+    )
+    .unwrap();
+    let output = temp_path("bad.gif");
+    let out = wrap(&manifest, &output);
+    fs::remove_file(&manifest).unwrap();
+    assert!(!out.status.success());
+    assert!(!output.exists());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("frame 1"), "{stderr}");
+}